@@ -0,0 +1,48 @@
+import * as anchor from "@project-serum/anchor";
+import { Program } from "@project-serum/anchor";
+import { Application } from "../target/types/application";
+
+// Each status change of an application lives in its own account, seeded with the
+// application and the index of the change.
+export const getStatusChangePDA = async (
+  applicationProgram: Program<Application>,
+  applicationPDA: anchor.web3.PublicKey,
+  index: number
+) => {
+  const [statusChangePDA, statusChangeBump] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from("statuschange"),
+        applicationPDA.toBuffer(),
+        new anchor.BN(index).toArrayLike(Buffer, "le", 8),
+      ],
+      applicationProgram.programId
+    );
+
+  return { statusChangePDA, statusChangeBump };
+};
+
+// Reads back every status change of an application, oldest first.
+export const fetchStatusHistory = async (
+  applicationProgram: Program<Application>,
+  applicationPDA: anchor.web3.PublicKey
+) => {
+  const applicationState =
+    await applicationProgram.account.applicationParameter.fetch(
+      applicationPDA
+    );
+
+  const history = [];
+  for (let i = 0; i < applicationState.statusChangeCount.toNumber(); i++) {
+    const { statusChangePDA } = await getStatusChangePDA(
+      applicationProgram,
+      applicationPDA,
+      i
+    );
+    history.push(
+      await applicationProgram.account.statusChange.fetch(statusChangePDA)
+    );
+  }
+
+  return history;
+};
@@ -1,19 +1,52 @@
 mod reward_calculator;
-pub use reward_calculator::RewardCalculator;
+pub use reward_calculator::{RewardBreakdown, RewardCalculator};
 use general::program::General;
+use general::versioning::{self, RESERVED_SPACE};
 use general::{self, GeneralParameter};
 use job::program::Job;
 use job::cpi::accounts::UpdateRewards;
-use job::{self, JobStakingParameter};
+use job::{self, JobStakingParameter, StatusPolicy};
 use anchor_lang::solana_program::sysvar::instructions as tx_instructions;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{ed25519_program, hash, program::{invoke, invoke_signed}, system_instruction};
 
 use anchor_lang::prelude::*;
+use anchor_lang::AccountsExit;
 
 declare_id!("7NBejsPH8cgtSkmqgSKyUffx8fYnrGCDp9fxePdjRXe7");
 
 const APPLICATION_SEED: &'static [u8] = b"application";
 const GENERAL_SEED: &'static [u8] = b"general";
 const JOB_SEED: &'static [u8] = b"jobfactory";
+const STATUS_CHANGE_SEED: &'static [u8] = b"statuschange";
+const STATUS_PROPOSAL_SEED: &'static [u8] = b"statusproposal";
+/// Starts every attestation message, so an attester signature can't be mistaken for one made for
+/// something else.
+pub const ATTESTATION_TAG: &'static [u8] = b"application-status-attestation";
+
+pub const STATUS_CHANGE_VERSION: u8 = 1;
+const STATUS_CHANGE_SPACE: usize = 8 + 32 + 8 + 1 + 1 + 32 + 8 + 2 + 32 + 1 + RESERVED_SPACE;
+pub const STATUS_PROPOSAL_VERSION: u8 = 1;
+const STATUS_PROPOSAL_SPACE: usize = 8 + 32 + 32 + 1 + 2 + 32 + 8 + 1 + 8 + 4 + 1 + RESERVED_SPACE;
+
+pub const APPLICATION_VERSION: u8 = 2;
+const APPLICATION_SPACE: usize = 8 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 32 + 32 + 8 + 8 + 1 + 1 + RESERVED_SPACE;
+/// The reserved bytes left once the bump and the committed reward are carved out of them.
+pub const APPLICATION_RESERVED: usize = RESERVED_SPACE - 1 - 8;
+/// Status changes and proposals don't use any of their reserved bytes yet.
+const RECORD_RESERVED: usize = RESERVED_SPACE;
+
+/// Reason codes of the status changes made by the program itself, the ones passed to
+/// `update_status` are up to the caller.
+pub const REASON_CANDIDATE_WITHDREW: u16 = 1;
+pub const REASON_JOB_FINALIZED: u16 = 2;
+pub const REASON_CHALLENGE_REVERTED: u16 = 3;
+pub const REASON_ATTESTED: u16 = 4;
+
+/// Layout of the ed25519 precompile instruction data: a signature count and a padding byte,
+/// then 7 little-endian u16 offsets per signature.
+const ED25519_OFFSETS_START: usize = 2;
+const ED25519_OFFSETS_SIZE: usize = 14;
 
 const CANDIDATE_STAKING_PROGRAM_ID: &'static str = "GCPpQABMRJ7bhRCqaYtBo7G1b5popATvcXDs2c1bK4hW";
 
@@ -26,13 +59,15 @@ pub mod application {
         ctx: Context<Initialize>,
         _job_ad_id: String,
         _application_id: String,
-        _general_bump: u8,
-        max_allowed_stake: u64
+        max_allowed_stake: u64,
+        candidate: Pubkey
     ) -> Result<()> {
 
         let parameter = &mut ctx.accounts.base_account;
+        let created_at = Clock::get()?.unix_timestamp;
 
-        parameter.reset(ctx.accounts.authority.key(), max_allowed_stake);
+        parameter.reset(ctx.accounts.authority.key(), ctx.accounts.job_account.key(), candidate, max_allowed_stake, created_at);
+        parameter.bump = *ctx.bumps.get("base_account").unwrap();
 
         Ok(())
     }
@@ -40,37 +75,278 @@ pub mod application {
     pub fn update_status(
         ctx: Context<UpdateStatus>,
         application_id: String,
-        application_bump: u8,
         job_id: String, 
-        job_bump: u8,
         status: JobStatus,
+        reason_code: u16,
+        evidence_hash: [u8; 32],
     ) -> Result<()> {
+        let application_key = ctx.accounts.base_account.key();
         let parameters = &mut ctx.accounts.base_account; 
-        parameters.status = status;
-        if !parameters.update_reward_value_in_job && (parameters.status == JobStatus::Selected || parameters.status == JobStatus::SelectedButCantWithdraw ){
-                let bump_vector = application_bump.to_le_bytes();
-                let inner = vec![
-                    APPLICATION_SEED,
-                    application_id.as_bytes()[..18].as_ref(),
-                    application_id.as_bytes()[18..].as_ref(),
-                    bump_vector.as_ref(),
-                ];
-                let outer = vec![inner.as_slice()];
-
-                let cpi_accounts = UpdateRewards {
-                    job_account: ctx.accounts.job_account.to_account_info(),
-                    authority: ctx.accounts.authority.to_account_info(),
-                    instructions: ctx.accounts.instruction.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.job_program.to_account_info();
-                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, outer.as_slice());
-                job::cpi::update_rewards(cpi_ctx, job_id.clone(),job_bump, parameters.total_reward_amount)?;
-                parameters.update_reward_value_in_job = true;
-        }
-        Ok(())
-    }
-
-    pub fn update_stake_amount(ctx: Context<UpdateStakeAmount>, _application_id: String, _application_bump: u8, stake_amount: u64, reward_amount: u64) -> Result<()> {
+        check_status_change_allowed(parameters, &ctx.accounts.general_account, &status)?;
+        let status_change = parameters.change_status(application_key, status, ctx.accounts.authority.key(), reason_code, evidence_hash)?;
+        *ctx.accounts.status_change = status_change;
+
+        let cpi_accounts = UpdateRewards {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+            instructions: ctx.accounts.instruction.to_account_info(),
+        };
+        sync_rewards_with_job(parameters, cpi_accounts, ctx.accounts.job_program.to_account_info(), &application_id, &job_id)?;
+
+        Ok(())
+    }
+
+    /// Upgrades an application created with an older layout to the current one. It grows the
+    /// account, records the job of an application from before versioning and bumps its version.
+    pub fn migrate_application(ctx: Context<MigrateApplication>, _application_id: String, _job_id: String) -> Result<()> {
+        let account_info = ctx.accounts.base_account.to_account_info();
+        versioning::grow_account(&account_info, &ctx.accounts.payer.to_account_info(), &ctx.accounts.system_program.to_account_info(), APPLICATION_SPACE)?;
+
+        let mut parameters = Account::<ApplicationParameter>::try_from(&account_info)?;
+        if parameters.version >= APPLICATION_VERSION {
+            return Err(error!(ErrorCode::AlreadyMigrated));
+        }
+        let job_key = ctx.accounts.job_account.key();
+        if parameters.version == 0 {
+            // nothing on chain ties an application from before versioning to its job, so its
+            // authority has to vouch for the one passed in
+            if ctx.accounts.payer.key() != parameters.authority {
+                return Err(error!(ErrorCode::InvalidAuthority));
+            }
+        } else if parameters.job != job_key {
+            return Err(error!(ErrorCode::InvalidJob));
+        }
+        parameters.upgrade(job_key, Clock::get()?.unix_timestamp);
+        parameters.bump = *ctx.bumps.get("base_account").unwrap();
+        parameters.exit(ctx.program_id)?;
+
+        Ok(())
+    }
+
+    /// Same as `update_status` but anyone can submit the change, as long as the instruction right
+    /// before it in the transaction is the ed25519 verification of the general attester's signature
+    /// of `attestation_message(program_id, job, application_id, status, timestamp)`.
+    pub fn update_status_attested(
+        ctx: Context<UpdateStatusAttested>,
+        application_id: String,
+        job_id: String,
+        status: JobStatus,
+        timestamp: i64,
+    ) -> Result<()> {
+        let application_key = ctx.accounts.base_account.key();
+        let general = &ctx.accounts.general_account;
+        if general.attester == Pubkey::default() {
+            return Err(error!(ErrorCode::NoAttester));
+        }
+
+        let ixns = ctx.accounts.instruction.to_account_info();
+        let current_index = tx_instructions::load_current_index_checked(&ixns)? as usize;
+        let verify_index = current_index.checked_sub(1).ok_or_else(|| ErrorCode::MissingAttestation)?;
+        let verify_ixn = tx_instructions::load_instruction_at_checked(verify_index, &ixns)?;
+        let message = attestation_message(ctx.program_id, &ctx.accounts.job_account.key(), &application_id, &status, timestamp);
+        let signature = verify_attestation(&verify_ixn, &general.attester, &message)?;
+
+        let parameters = &mut ctx.accounts.base_account;
+        // an attestation only moves the application forward, so it can't be replayed later on
+        if timestamp <= parameters.status_updated_at || timestamp > Clock::get()?.unix_timestamp {
+            return Err(error!(ErrorCode::InvalidAttestationTimestamp));
+        }
+        check_status_change_allowed(parameters, general, &status)?;
+
+        let status_change = parameters.change_status(application_key, status, general.attester, REASON_ATTESTED, hash::hash(&signature).to_bytes())?;
+        *ctx.accounts.status_change = status_change;
+
+        let cpi_accounts = UpdateRewards {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            authority: ctx.accounts.submitter.to_account_info(),
+            instructions: ctx.accounts.instruction.to_account_info(),
+        };
+        sync_rewards_with_job(parameters, cpi_accounts, ctx.accounts.job_program.to_account_info(), &application_id, &job_id)?;
+
+        Ok(())
+    }
+
+    /// Proposes to select the application when the general config requires approvals for it. The
+    /// proposal only applies to the status the application has now. While that status is
+    /// challenged, only the resolver can use the proposal, to revert the application to a hire.
+    pub fn propose_status(
+        ctx: Context<ProposeStatus>,
+        _application_id: String,
+        status: JobStatus,
+        reason_code: u16,
+        evidence_hash: [u8; 32],
+    ) -> Result<()> {
+        let application = &ctx.accounts.base_account;
+        if !status.is_hire() {
+            return Err(error!(ErrorCode::InvalidStatus));
+        }
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.application = application.key();
+        proposal.proposer = ctx.accounts.authority.key();
+        proposal.status = status;
+        proposal.reason_code = reason_code;
+        proposal.evidence_hash = evidence_hash;
+        proposal.status_change_count = application.status_change_count;
+        proposal.approvals = 0;
+        proposal.approver_nonce = ctx.accounts.general_account.approver_nonce;
+        proposal.created_at = Clock::get()?.unix_timestamp;
+        proposal.version = STATUS_PROPOSAL_VERSION;
+        proposal.reserved = [0; RECORD_RESERVED];
+
+        Ok(())
+    }
+
+    pub fn approve_status(ctx: Context<ApproveStatus>, _application_id: String) -> Result<()> {
+        let approver = ctx.accounts.approver.key();
+        let index = ctx.accounts.general_account.approver_index(&approver).ok_or_else(|| ErrorCode::NotAnApprover)?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        if proposal.approver_nonce != ctx.accounts.general_account.approver_nonce {
+            return Err(error!(ErrorCode::ApproversChanged));
+        }
+        proposal.approve(index)?;
+
+        emit!(StatusApproved {
+            application: proposal.application,
+            proposal: proposal.key(),
+            approver,
+            approvals: proposal.approval_count(ctx.accounts.general_account.approver_count),
+        });
+
+        Ok(())
+    }
+
+    /// Applies a proposal once enough approvers signed off on it. Anyone can execute it, the
+    /// status change is recorded as made by the proposer.
+    pub fn execute_status(
+        ctx: Context<ExecuteStatus>,
+        application_id: String,
+        job_id: String,
+    ) -> Result<()> {
+        let application_key = ctx.accounts.base_account.key();
+        let general = &ctx.accounts.general_account;
+        let proposal = &ctx.accounts.proposal;
+        let parameters = &mut ctx.accounts.base_account;
+
+        if parameters.challenge == ChallengeState::Open {
+            return Err(error!(ErrorCode::StatusChallenged));
+        }
+        proposal.check_approved(parameters, general)?;
+
+        let status_change = parameters.change_status(application_key, proposal.status.clone(), proposal.proposer, proposal.reason_code, proposal.evidence_hash)?;
+        *ctx.accounts.status_change = status_change;
+
+        let cpi_accounts = UpdateRewards {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            authority: ctx.accounts.executor.to_account_info(),
+            instructions: ctx.accounts.instruction.to_account_info(),
+        };
+        sync_rewards_with_job(parameters, cpi_accounts, ctx.accounts.job_program.to_account_info(), &application_id, &job_id)?;
+
+        Ok(())
+    }
+
+    pub fn cancel_status_proposal(_ctx: Context<CancelStatusProposal>, _application_id: String) -> Result<()> {
+        Ok(())
+    }
+
+    /// Freezes the payouts of the application while a staker challenges its status. Only called
+    /// through `candidate_staking::challenge_status`, which takes the challenger's bond.
+    pub fn set_challenged(ctx: Context<SetChallenged>, _application_id: String) -> Result<()> {
+        let ixns = ctx.accounts.instruction.to_account_info();
+        let current_index = tx_instructions::load_current_index_checked(&ixns)? as usize;
+        let current_ixn = tx_instructions::load_instruction_at_checked(current_index, &ixns)?;
+
+        if current_ixn.program_id.to_string() != CANDIDATE_STAKING_PROGRAM_ID {
+            return Err(error!(ErrorCode::InvalidCall));
+        }
+
+        ctx.accounts.base_account.open_challenge()
+    }
+
+    /// The resolver either upholds the challenged status or reverts the application to the status
+    /// it had before, which unfreezes its payouts either way. Reverting to a status that commits
+    /// rewards needs an approved proposal for it when the general config requires approvals.
+    pub fn resolve_challenge<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveChallenge<'info>>,
+        application_id: String,
+        job_id: String,
+        uphold: bool,
+    ) -> Result<()> {
+        let application_key = ctx.accounts.base_account.key();
+        let parameters = &mut ctx.accounts.base_account;
+        if parameters.challenge != ChallengeState::Open {
+            return Err(error!(ErrorCode::NoOpenChallenge));
+        }
+
+        if uphold {
+            parameters.challenge = ChallengeState::Upheld;
+            return Ok(());
+        }
+
+        let previous_status = ctx.accounts.last_status_change.previous_status.clone();
+        if previous_status.is_hire() && ctx.accounts.general_account.requires_approval() {
+            // going back to a hire commits the rewards again, so the approvers have to agree on it
+            // with a proposal for that status, passed as the first remaining account
+            let proposal_info = ctx.remaining_accounts.first().ok_or_else(|| ErrorCode::ApprovalRequired)?;
+            let proposal = Account::<StatusProposal>::try_from(proposal_info)?;
+            if proposal.application != application_key || proposal.status != previous_status {
+                return Err(error!(ErrorCode::InvalidProposal));
+            }
+            proposal.check_approved(parameters, &ctx.accounts.general_account)?;
+        }
+        let status_change = parameters.change_status(application_key, previous_status, ctx.accounts.resolver.key(), REASON_CHALLENGE_REVERTED, [0; 32])?;
+        *ctx.accounts.status_change = status_change;
+        parameters.challenge = ChallengeState::Reverted;
+
+        let cpi_accounts = UpdateRewards {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            authority: ctx.accounts.resolver.to_account_info(),
+            instructions: ctx.accounts.instruction.to_account_info(),
+        };
+        sync_rewards_with_job(parameters, cpi_accounts, ctx.accounts.job_program.to_account_info(), &application_id, &job_id)?;
+
+        Ok(())
+    }
+
+    /// Lets the candidate drop out of the process while the application is still open. Stakers
+    /// are refunded the same way as for a rejection.
+    pub fn withdraw_application(ctx: Context<WithdrawApplication>, _application_id: String) -> Result<()> {
+        let application_key = ctx.accounts.base_account.key();
+        let parameters = &mut ctx.accounts.base_account;
+        if !parameters.status.is_open() {
+            return Err(error!(ErrorCode::InvalidStatus));
+        }
+
+        let status_change = parameters.change_status(application_key, JobStatus::Withdrawn, ctx.accounts.candidate.key(), REASON_CANDIDATE_WITHDREW, [0; 32])?;
+        *ctx.accounts.status_change = status_change;
+
+        Ok(())
+    }
+
+    /// Takes a position paid out while the application is still open off its totals, so the
+    /// stakes that come after it are priced as if it never was. Only called through candidate_staking.
+    pub fn release_stake(ctx: Context<UpdateStakeAmount>, _application_id: String, stake_amount: u64, reward_amount: u64) -> Result<()> {
+        let ixns = ctx.accounts.instruction.to_account_info();
+        let current_index = tx_instructions::load_current_index_checked(&ixns)? as usize;
+        let current_ixn = tx_instructions::load_instruction_at_checked(current_index, &ixns)?;
+
+        if current_ixn.program_id.to_string() != CANDIDATE_STAKING_PROGRAM_ID {
+            return Err(error!(ErrorCode::InvalidCall));
+        }
+
+        let parameters = &mut ctx.accounts.base_account;
+        if !parameters.status.is_open() {
+            return Err(error!(ErrorCode::InvalidStatus));
+        }
+        parameters.staked_amount = parameters.staked_amount.saturating_sub(stake_amount);
+        parameters.total_reward_amount = parameters.total_reward_amount.saturating_sub(reward_amount);
+
+        Ok(())
+    }
+
+    pub fn update_stake_amount(ctx: Context<UpdateStakeAmount>, _application_id: String, stake_amount: u64, reward_amount: u64) -> Result<()> {
         msg!("cpi call is made yippee");
 
         let ixns = ctx.accounts.instruction.to_account_info();
@@ -90,10 +366,68 @@ pub mod application {
         Ok(())
     }
 
+    /// Closes the hiring of a job once `base_account` got the job: every other application of the
+    /// job passed through the remaining accounts that is still in the pipeline gets rejected, so its
+    /// stakers can unstake right away. Each application comes with the account its status change
+    /// is recorded in.
+    pub fn finalize_job<'info>(
+        ctx: Context<'_, '_, '_, 'info, FinalizeJob<'info>>,
+        _application_id: String,
+        _job_id: String,
+    ) -> Result<()> {
+        let selected = &ctx.accounts.base_account;
+        if !selected.status.is_hire() {
+            return Err(error!(ErrorCode::ApplicationNotSelected));
+        }
+
+        let job_key = ctx.accounts.job_account.key();
+        let authority_key = ctx.accounts.authority.key();
+        let payer = ctx.accounts.authority.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let mut rejected: u32 = 0;
+        let mut skipped: u32 = 0;
+
+        for accounts in ctx.remaining_accounts.chunks(2) {
+            let (application_info, status_change_info) = match accounts {
+                [application_info, status_change_info] => (application_info, status_change_info),
+                _ => return Err(error!(ErrorCode::MissingStatusChangeAccount)),
+            };
+            if application_info.key() == selected.key() {
+                continue;
+            }
+
+            let mut application = Account::<ApplicationParameter>::try_from(application_info)?;
+            if application.job != job_key {
+                return Err(error!(ErrorCode::InvalidJob));
+            }
+            if application.authority != authority_key {
+                return Err(error!(ErrorCode::InvalidAuthority));
+            }
+
+            if application.status.is_open() {
+                let status_change = application.change_status(application_info.key(), JobStatus::Rejected, authority_key, REASON_JOB_FINALIZED, [0; 32])?;
+                create_status_change(&payer, status_change_info, &system_program, &status_change, ctx.program_id)?;
+                application.exit(ctx.program_id)?;
+                rejected += 1;
+            } else {
+                skipped += 1;
+            }
+        }
+
+        emit!(JobFinalized {
+            job: job_key,
+            selected_application: selected.key(),
+            rejected,
+            skipped,
+        });
+
+        Ok(())
+    }
+
 }
 
 #[derive(Accounts)]
-#[instruction(job_ad_id: String, application_id: String, general_bump: u8)]
+#[instruction(job_ad_id: String, application_id: String)]
 pub struct Initialize<'info> {
     #[account(
         init,
@@ -101,36 +435,209 @@ pub struct Initialize<'info> {
         seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()],
         bump, 
         constraint = authority.key() == general_account.authority @ ErrorCode::InvalidAuthority,
-        space = 8 + 32 + 8 + 8 + 8 + 8 + 1
+        space = APPLICATION_SPACE
     )]
     pub base_account: Account<'info, ApplicationParameter>,
-    #[account(mut, seeds = [GENERAL_SEED], bump = general_bump, seeds::program = general_program.key())]
+    #[account(mut, seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
     pub general_account: Account<'info, GeneralParameter>,
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
     #[account(mut)]
     pub authority: Signer<'info>,
     pub general_program: Program<'info, General>,
+    pub job_program: Program<'info, Job>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(application_id: String, application_bump: u8, job_id: String, job_bump: u8)]
+#[instruction(application_id: String, job_id: String)]
 pub struct UpdateStatus<'info> {
-    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_bump, has_one = authority)]
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump, has_one = authority)]
     pub base_account: Account<'info, ApplicationParameter>,
     #[account(mut)]
     pub authority: Signer<'info>,
-    #[account(mut, seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_bump, seeds::program = job_program.key())]
-    pub job_account: Account<'info, JobStakingParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key(), constraint = base_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(init, payer = authority, seeds = [STATUS_CHANGE_SEED, base_account.key().as_ref(), base_account.status_change_count.to_le_bytes().as_ref()], bump, space = STATUS_CHANGE_SPACE)]
+    pub status_change: Account<'info, StatusChange>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    pub general_program: Program<'info, General>,
     pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
     ///CHECK:
     pub instruction: AccountInfo<'info>
     
 }
 
 #[derive(Accounts)]
-#[instruction(application_id: String, application_bump: u8)]
+#[instruction(application_id: String, job_id: String)]
+pub struct MigrateApplication<'info> {
+    /// CHECK: only deserialized once it has the size of the current layout, which is also
+    /// when its canonical bump gets stored
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump, owner = crate::ID)]
+    pub base_account: UncheckedAccount<'info>,
+    /// The job of the application, which has to be migrated first.
+    #[account(seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    /// Anyone can pay, except for applications from before versioning, which only their authority
+    /// can migrate.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_id: String)]
+pub struct UpdateStatusAttested<'info> {
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut)]
+    pub submitter: Signer<'info>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key(), constraint = base_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(init, payer = submitter, seeds = [STATUS_CHANGE_SEED, base_account.key().as_ref(), base_account.status_change_count.to_le_bytes().as_ref()], bump, space = STATUS_CHANGE_SPACE)]
+    pub status_change: Account<'info, StatusChange>,
+    pub general_program: Program<'info, General>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    #[account(address = tx_instructions::ID)]
+    ///CHECK:
+    pub instruction: AccountInfo<'info>
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String)]
+pub struct ProposeStatus<'info> {
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump, has_one = authority)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(init, payer = authority, seeds = [STATUS_PROPOSAL_SEED, base_account.key().as_ref(), base_account.status_change_count.to_le_bytes().as_ref()], bump, space = STATUS_PROPOSAL_SPACE)]
+    pub proposal: Account<'info, StatusProposal>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    pub general_program: Program<'info, General>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String)]
+pub struct ApproveStatus<'info> {
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut, constraint = proposal.application == base_account.key() @ ErrorCode::InvalidProposal)]
+    pub proposal: Account<'info, StatusProposal>,
+    pub approver: Signer<'info>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    pub general_program: Program<'info, General>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_id: String)]
+pub struct ExecuteStatus<'info> {
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut, close = proposer, has_one = proposer, constraint = proposal.application == base_account.key() @ ErrorCode::InvalidProposal)]
+    pub proposal: Account<'info, StatusProposal>,
+    /// CHECK: only gets the rent of the proposal back
+    #[account(mut)]
+    pub proposer: AccountInfo<'info>,
+    #[account(mut)]
+    pub executor: Signer<'info>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key(), constraint = base_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(init, payer = executor, seeds = [STATUS_CHANGE_SEED, base_account.key().as_ref(), base_account.status_change_count.to_le_bytes().as_ref()], bump, space = STATUS_CHANGE_SPACE)]
+    pub status_change: Account<'info, StatusChange>,
+    pub general_program: Program<'info, General>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    #[account(address = tx_instructions::ID)]
+    ///CHECK:
+    pub instruction: AccountInfo<'info>
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String)]
+pub struct CancelStatusProposal<'info> {
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump, has_one = authority)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut, close = authority, constraint = proposal.application == base_account.key() @ ErrorCode::InvalidProposal)]
+    pub proposal: Account<'info, StatusProposal>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String)]
+pub struct SetChallenged<'info> {
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    pub authority: Signer<'info>,
+    #[account(address = tx_instructions::ID)]
+    ///CHECK:
+    pub instruction: AccountInfo<'info>
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_id: String)]
+pub struct ResolveChallenge<'info> {
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut, constraint = resolver.key() == general_account.resolver @ ErrorCode::InvalidResolver)]
+    pub resolver: Signer<'info>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key(), constraint = base_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    /// The change being challenged, the last one of the application.
+    #[account(seeds = [STATUS_CHANGE_SEED, base_account.key().as_ref(), base_account.status_change_count.saturating_sub(1).to_le_bytes().as_ref()], bump)]
+    pub last_status_change: Account<'info, StatusChange>,
+    #[account(init, payer = resolver, seeds = [STATUS_CHANGE_SEED, base_account.key().as_ref(), base_account.status_change_count.to_le_bytes().as_ref()], bump, space = STATUS_CHANGE_SPACE)]
+    pub status_change: Account<'info, StatusChange>,
+    pub general_program: Program<'info, General>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    #[account(address = tx_instructions::ID)]
+    ///CHECK:
+    pub instruction: AccountInfo<'info>
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String)]
+pub struct WithdrawApplication<'info> {
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump, has_one = candidate)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut)]
+    pub candidate: Signer<'info>,
+    #[account(init, payer = candidate, seeds = [STATUS_CHANGE_SEED, base_account.key().as_ref(), base_account.status_change_count.to_le_bytes().as_ref()], bump, space = STATUS_CHANGE_SPACE)]
+    pub status_change: Account<'info, StatusChange>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_id: String)]
+pub struct FinalizeJob<'info> {
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump, has_one = authority)]
+    pub base_account: Account<'info, ApplicationParameter>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [JOB_SEED, job_id.as_bytes()[..18].as_ref(), job_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key(), constraint = base_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String)]
 pub struct UpdateStakeAmount<'info> {
-    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_bump)]
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = base_account.bump)]
     pub base_account: Account<'info, ApplicationParameter>,
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -147,6 +654,41 @@ pub enum JobStatus {
     SelectedButCantWithdraw,
     Selected,
     Pending,
+    Withdrawn,
+    Screening,
+    Interviewing,
+    Offered,
+    OfferAccepted,
+    InProbation,
+    Hired,
+}
+
+// the job keeps a policy per status, so both programs have to agree on how many there are
+const _: () = assert!(JobStatus::Hired as usize + 1 == job::STATUS_COUNT);
+
+impl JobStatus {
+    /// Whether the application is still in the hiring pipeline.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Pending
+                | JobStatus::Screening
+                | JobStatus::Interviewing
+                | JobStatus::Offered
+                | JobStatus::OfferAccepted
+                | JobStatus::InProbation
+        )
+    }
+
+    /// Whether the candidate got the job, which commits the application's rewards.
+    pub fn is_hire(&self) -> bool {
+        matches!(self, JobStatus::SelectedButCantWithdraw | JobStatus::Selected | JobStatus::Hired)
+    }
+
+    /// The policy the job applies to its applications at this status.
+    pub fn policy<'a>(&self, job: &'a JobStakingParameter) -> &'a StatusPolicy {
+        &job.status_policies[self.clone() as usize]
+    }
 }
 
 #[account]
@@ -156,16 +698,345 @@ pub struct ApplicationParameter {
     pub staked_amount: u64,      // 8 bytes
     pub max_allowed_staked: u64, // 8 bytes
     pub total_reward_amount: u64, // 8 bytes
-    pub update_reward_value_in_job: bool // 1 byte
+    pub update_reward_value_in_job: bool, // 1 byte
+    pub created_at: i64,         // 8 bytes
+    pub job: Pubkey,             // 32 bytes
+    pub candidate: Pubkey,       // 32 bytes, the applicant's wallet
+    pub status_change_count: u64, // 8 bytes
+    pub status_updated_at: i64,  // 8 bytes
+    pub challenge: ChallengeState, // 1 byte
+    pub version: u8,             // 1 byte
+    pub bump: u8,                // 1 byte, out of the reserved bytes
+    pub committed_reward: u64,   // 8 bytes, out of the reserved bytes, what update_rewards added to the job
+    pub reserved: [u8; APPLICATION_RESERVED], // the rest of the reserved bytes
+}
+
+#[derive(Debug, Clone, AnchorSerialize, AnchorDeserialize, PartialEq)]
+pub enum ChallengeState {
+    Unchallenged,
+    Open,
+    Upheld,
+    Reverted,
 }
 
 impl ApplicationParameter {
-    pub fn reset(&mut self, authority: Pubkey, max_allowed_staked: u64) {
+    pub fn reset(&mut self, authority: Pubkey, job: Pubkey, candidate: Pubkey, max_allowed_staked: u64, created_at: i64) {
         self.authority = authority;
+        self.job = job;
+        self.candidate = candidate;
         self.status = JobStatus::Pending;
         self.staked_amount = 0;
         self.max_allowed_staked = max_allowed_staked;
+        self.created_at = created_at;
+        self.status_change_count = 0;
+        self.status_updated_at = created_at;
+        self.challenge = ChallengeState::Unchallenged;
+        self.version = APPLICATION_VERSION;
+        self.committed_reward = 0;
+        self.reserved = [0; APPLICATION_RESERVED];
+    }
+
+    /// Brings a grown account to the current version. An application from before versioning only
+    /// had its amounts and status: it gets the job it belongs to and counts as created now, so the
+    /// time decay of its rewards starts at the migration. Its candidate stays unknown.
+    pub fn upgrade(&mut self, job: Pubkey, now: i64) {
+        if self.version == 0 {
+            self.job = job;
+            self.created_at = now;
+            self.status_updated_at = now;
+        }
+        self.version = APPLICATION_VERSION;
+    }
+
+    /// Moves the application to `status` and yields the record of the change, which is stored
+    /// at the `status_change_count` it had before the change.
+    pub fn change_status(&mut self, application: Pubkey, status: JobStatus, authority: Pubkey, reason_code: u16, evidence_hash: [u8; 32]) -> Result<StatusChange> {
+        let status_change = StatusChange {
+            application,
+            index: self.status_change_count,
+            previous_status: self.status.clone(),
+            new_status: status.clone(),
+            authority,
+            timestamp: Clock::get()?.unix_timestamp,
+            reason_code,
+            evidence_hash,
+            version: STATUS_CHANGE_VERSION,
+            reserved: [0; RECORD_RESERVED],
+        };
+
+        self.status = status;
+        self.status_updated_at = status_change.timestamp;
+        self.status_change_count = self.status_change_count.checked_add(1).ok_or_else(|| ErrorCode::StatusChangeCountOverflow)?;
+
+        Ok(status_change)
+    }
+
+    /// Opens a challenge of the current status. The status has to be final and recorded in the
+    /// status history, since reverting it restores the previous status of its record: an
+    /// application migrated from before the history, or whose status never changed, can't be
+    /// challenged.
+    pub fn open_challenge(&mut self) -> Result<()> {
+        if self.status.is_open() {
+            return Err(error!(ErrorCode::InvalidStatus));
+        }
+        if self.status_change_count == 0 {
+            return Err(error!(ErrorCode::NoStatusHistory));
+        }
+        if self.challenge == ChallengeState::Open {
+            return Err(error!(ErrorCode::StatusChallenged));
+        }
+
+        self.challenge = ChallengeState::Open;
+
+        Ok(())
     }
+
+    /// The status stakers are paid out on. Once the job is filled the applications still
+    /// open can't be selected anymore, so they are settled as rejected.
+    pub fn settlement_status(&self, job: &JobStakingParameter) -> JobStatus {
+        if job.is_filled && self.status.is_open() {
+            JobStatus::Rejected
+        } else {
+            self.status.clone()
+        }
+    }
+}
+
+/// A status change waiting for the approvers of the general config.
+#[account]
+pub struct StatusProposal {
+    pub application: Pubkey,       // 32 bytes
+    pub proposer: Pubkey,          // 32 bytes
+    pub status: JobStatus,         // 1 byte
+    pub reason_code: u16,          // 2 bytes
+    pub evidence_hash: [u8; 32],   // 32 bytes
+    pub status_change_count: u64,  // 8 bytes, of the application when proposed
+    pub approvals: u8,             // 1 byte, one bit per index in the approver set
+    pub created_at: i64,           // 8 bytes
+    pub approver_nonce: u32,       // 4 bytes, of the general config when proposed
+    pub version: u8,               // 1 byte
+    pub reserved: [u8; RECORD_RESERVED], // room for new fields
+}
+
+impl StatusProposal {
+    pub fn approve(&mut self, approver_index: usize) -> Result<()> {
+        let bit = 1u8 << approver_index;
+        if self.approvals & bit != 0 {
+            return Err(error!(ErrorCode::AlreadyApproved));
+        }
+        self.approvals |= bit;
+
+        Ok(())
+    }
+
+    /// Checks the proposal still applies to the status of the application and enough approvers
+    /// signed off on it.
+    pub fn check_approved(&self, parameters: &ApplicationParameter, general: &GeneralParameter) -> Result<()> {
+        if self.status_change_count != parameters.status_change_count {
+            return Err(error!(ErrorCode::StaleProposal));
+        }
+        if self.approver_nonce != general.approver_nonce {
+            return Err(error!(ErrorCode::ApproversChanged));
+        }
+        if self.approval_count(general.approver_count) < general.approval_threshold as u32 {
+            return Err(error!(ErrorCode::ThresholdNotMet));
+        }
+
+        Ok(())
+    }
+
+    /// Approvals of the first `approver_count` approvers, the ones that are still in the set.
+    pub fn approval_count(&self, approver_count: u8) -> u32 {
+        let mask = ((1u16 << approver_count.min(8)) - 1) as u8;
+        (self.approvals & mask).count_ones()
+    }
+}
+
+/// One entry of the status history of an application.
+#[account]
+pub struct StatusChange {
+    pub application: Pubkey,        // 32 bytes
+    pub index: u64,                 // 8 bytes
+    pub previous_status: JobStatus, // 1 byte
+    pub new_status: JobStatus,      // 1 byte
+    pub authority: Pubkey,          // 32 bytes
+    pub timestamp: i64,             // 8 bytes
+    pub reason_code: u16,           // 2 bytes
+    pub evidence_hash: [u8; 32],    // 32 bytes, hash of the off-chain evidence
+    pub version: u8,                // 1 byte
+    pub reserved: [u8; RECORD_RESERVED], // room for new fields
+}
+
+/// Commits the rewards of the application to the job when it becomes a hire, and releases them
+/// along with the hire when it stops being one, like when a challenge reverts it.
+fn sync_rewards_with_job<'info>(
+    parameters: &mut ApplicationParameter,
+    cpi_accounts: UpdateRewards<'info>,
+    job_program: AccountInfo<'info>,
+    application_id: &str,
+    job_id: &str,
+) -> Result<()> {
+    if parameters.update_reward_value_in_job == parameters.status.is_hire() {
+        return Ok(());
+    }
+
+    let bump_vector = parameters.bump.to_le_bytes();
+    let inner = vec![
+        APPLICATION_SEED,
+        application_id.as_bytes()[..18].as_ref(),
+        application_id.as_bytes()[18..].as_ref(),
+        bump_vector.as_ref(),
+    ];
+    let outer = vec![inner.as_slice()];
+
+    let cpi_ctx = CpiContext::new_with_signer(job_program, cpi_accounts, outer.as_slice());
+    if parameters.update_reward_value_in_job {
+        // hires committed before the amount was tracked give back the current total instead
+        let committed_reward = if parameters.committed_reward == 0 { parameters.total_reward_amount } else { parameters.committed_reward };
+        job::cpi::release_rewards(cpi_ctx, job_id.to_string(), committed_reward)?;
+        parameters.update_reward_value_in_job = false;
+        parameters.committed_reward = 0;
+    } else {
+        job::cpi::update_rewards(cpi_ctx, job_id.to_string(), parameters.total_reward_amount)?;
+        parameters.update_reward_value_in_job = true;
+        parameters.committed_reward = parameters.total_reward_amount;
+    }
+
+    Ok(())
+}
+
+/// Status changes can't happen while the current status is challenged, and selecting an
+/// application needs a proposal once the general config requires approvals.
+fn check_status_change_allowed(parameters: &ApplicationParameter, general: &GeneralParameter, status: &JobStatus) -> Result<()> {
+    if parameters.challenge == ChallengeState::Open {
+        return Err(error!(ErrorCode::StatusChallenged));
+    }
+    if status.is_hire() && general.requires_approval() {
+        return Err(error!(ErrorCode::ApprovalRequired));
+    }
+
+    Ok(())
+}
+
+/// What the attester signs for `update_status_attested`: `ATTESTATION_TAG`, the id of this program,
+/// the job account, the application id, the status discriminant and the little-endian timestamp of
+/// the outcome.
+pub fn attestation_message(program_id: &Pubkey, job: &Pubkey, application_id: &str, status: &JobStatus, timestamp: i64) -> Vec<u8> {
+    let mut message = Vec::with_capacity(ATTESTATION_TAG.len() + 32 + 32 + application_id.len() + 1 + 8);
+    message.extend_from_slice(ATTESTATION_TAG);
+    message.extend_from_slice(program_id.as_ref());
+    message.extend_from_slice(job.as_ref());
+    message.extend_from_slice(application_id.as_bytes());
+    message.push(status.clone() as u8);
+    message.extend_from_slice(&timestamp.to_le_bytes());
+    message
+}
+
+/// Checks that `ixn` is an ed25519 precompile instruction verifying one signature of `message`
+/// by `attester`, and yields that signature. The precompile already failed the transaction if
+/// the signature is invalid, so only what it verified has to be checked here.
+fn verify_attestation(ixn: &Instruction, attester: &Pubkey, message: &[u8]) -> Result<[u8; 64]> {
+    if ixn.program_id != ed25519_program::ID {
+        return Err(error!(ErrorCode::MissingAttestation));
+    }
+
+    let data = &ixn.data;
+    if data.len() < ED25519_OFFSETS_START + ED25519_OFFSETS_SIZE || data[0] != 1 {
+        return Err(error!(ErrorCode::InvalidAttestation));
+    }
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let signature_offset = read_u16(ED25519_OFFSETS_START) as usize;
+    let signature_ixn_index = read_u16(ED25519_OFFSETS_START + 2);
+    let public_key_offset = read_u16(ED25519_OFFSETS_START + 4) as usize;
+    let public_key_ixn_index = read_u16(ED25519_OFFSETS_START + 6);
+    let message_offset = read_u16(ED25519_OFFSETS_START + 8) as usize;
+    let message_size = read_u16(ED25519_OFFSETS_START + 10) as usize;
+    let message_ixn_index = read_u16(ED25519_OFFSETS_START + 12);
+
+    // everything the precompile verified has to come from its own data
+    if [signature_ixn_index, public_key_ixn_index, message_ixn_index].iter().any(|index| *index != u16::MAX) {
+        return Err(error!(ErrorCode::InvalidAttestation));
+    }
+    let slice = |offset: usize, size: usize| data.get(offset..offset + size).ok_or_else(|| error!(ErrorCode::InvalidAttestation));
+
+    if slice(public_key_offset, 32)? != attester.as_ref() || slice(message_offset, message_size)? != message {
+        return Err(error!(ErrorCode::InvalidAttestation));
+    }
+
+    let mut signature = [0; 64];
+    signature.copy_from_slice(slice(signature_offset, 64)?);
+    Ok(signature)
+}
+
+/// Creates the status change account passed through the remaining accounts and writes the
+/// record into it, for the instructions that change the status of several applications.
+fn create_status_change<'info>(
+    payer: &AccountInfo<'info>,
+    status_change_info: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    status_change: &StatusChange,
+    program_id: &Pubkey,
+) -> Result<()> {
+    let index_bytes = status_change.index.to_le_bytes();
+    let (expected_key, bump) = Pubkey::find_program_address(
+        &[STATUS_CHANGE_SEED, status_change.application.as_ref(), index_bytes.as_ref()],
+        program_id,
+    );
+    if expected_key != status_change_info.key() {
+        return Err(error!(ErrorCode::InvalidStatusChangeAccount));
+    }
+
+    // same as anchor's `init`: someone may have sent lamports to the address already, which
+    // makes create_account fail, so those are topped up to the rent instead
+    let bump_bytes = [bump];
+    let signer_seeds: &[&[u8]] = &[STATUS_CHANGE_SEED, status_change.application.as_ref(), index_bytes.as_ref(), &bump_bytes];
+    let lamports = Rent::get()?.minimum_balance(STATUS_CHANGE_SPACE);
+    let current_lamports = status_change_info.lamports();
+    if current_lamports == 0 {
+        invoke_signed(
+            &system_instruction::create_account(payer.key, status_change_info.key, lamports, STATUS_CHANGE_SPACE as u64, program_id),
+            &[payer.clone(), status_change_info.clone(), system_program.clone()],
+            &[signer_seeds],
+        )?;
+    } else {
+        let required_lamports = lamports.saturating_sub(current_lamports);
+        if required_lamports > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, status_change_info.key, required_lamports),
+                &[payer.clone(), status_change_info.clone(), system_program.clone()],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(status_change_info.key, STATUS_CHANGE_SPACE as u64),
+            &[status_change_info.clone(), system_program.clone()],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(status_change_info.key, program_id),
+            &[status_change_info.clone(), system_program.clone()],
+            &[signer_seeds],
+        )?;
+    }
+
+    let mut data = status_change_info.try_borrow_mut_data()?;
+    let mut writer: &mut [u8] = &mut data;
+    status_change.try_serialize(&mut writer)
+}
+
+#[event]
+pub struct JobFinalized {
+    pub job: Pubkey,
+    pub selected_application: Pubkey,
+    pub rejected: u32,
+    pub skipped: u32,
+}
+
+#[event]
+pub struct StatusApproved {
+    pub application: Pubkey,
+    pub proposal: Pubkey,
+    pub approver: Pubkey,
+    pub approvals: u32,
 }
 
 #[error_code]
@@ -180,4 +1051,209 @@ pub enum ErrorCode {
     StakeAmountOverflow,
     #[msg("Total reward amount overflow")]
     TotalRewardAmountOverflow,
+    #[msg("The application doesn't belong to this job")]
+    InvalidJob,
+    #[msg("The application hasn't been selected")]
+    ApplicationNotSelected,
+    #[msg("Status change count overflow")]
+    StatusChangeCountOverflow,
+    #[msg("Every application needs the account its status change is recorded in")]
+    MissingStatusChangeAccount,
+    #[msg("The status change account doesn't match the application history")]
+    InvalidStatusChangeAccount,
+    #[msg("The status of the application is being challenged")]
+    StatusChallenged,
+    #[msg("The application has no open challenge")]
+    NoOpenChallenge,
+    #[msg("Only the resolver can resolve challenges")]
+    InvalidResolver,
+    #[msg("Selecting an application needs the approvers to agree on a proposal")]
+    ApprovalRequired,
+    #[msg("Signer isn't one of the approvers")]
+    NotAnApprover,
+    #[msg("Approver already approved the proposal")]
+    AlreadyApproved,
+    #[msg("Not enough approvals to execute the proposal")]
+    ThresholdNotMet,
+    #[msg("The status of the application changed since the proposal")]
+    StaleProposal,
+    #[msg("Proposal isn't for this application")]
+    InvalidProposal,
+    #[msg("No attester is configured")]
+    NoAttester,
+    #[msg("The instruction before has to verify the attestation")]
+    MissingAttestation,
+    #[msg("Attestation isn't signed by the attester for this status change")]
+    InvalidAttestation,
+    #[msg("Attestation is older than the current status or from the future")]
+    InvalidAttestationTimestamp,
+    #[msg("The account already has the current layout")]
+    AlreadyMigrated,
+    #[msg("The approvers changed since the proposal")]
+    ApproversChanged,
+    #[msg("The status has no record in the status history to revert to")]
+    NoStatusHistory,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::Discriminator;
+    use job::STATUS_COUNT;
+
+    #[test]
+    fn every_status_has_a_policy() {
+        assert_eq!(JobStatus::Hired as usize + 1, STATUS_COUNT);
+        assert_eq!(job::default_status_policies().len(), STATUS_COUNT);
+    }
+
+    #[test]
+    fn default_policies_keep_staking_to_the_open_statuses() {
+        let policies = job::default_status_policies();
+        assert!(policies[JobStatus::Pending as usize].allow_stake);
+        assert!(!policies[JobStatus::Offered as usize].allow_stake);
+        assert!(!policies[JobStatus::Selected as usize].allow_stake);
+        assert!(!policies[JobStatus::Pending as usize].allow_withdraw);
+        assert!(policies[JobStatus::Rejected as usize].allow_withdraw);
+        assert!(policies[JobStatus::Withdrawn as usize].allow_withdraw);
+        assert!(policies[JobStatus::Hired as usize].allow_withdraw);
+    }
+
+    /// The layout of `ApplicationParameter` before accounts were versioned.
+    #[derive(AnchorSerialize)]
+    struct ApplicationParameterV0 {
+        authority: Pubkey,
+        status: JobStatus,
+        staked_amount: u64,
+        max_allowed_staked: u64,
+        total_reward_amount: u64,
+        update_reward_value_in_job: bool,
+    }
+
+    #[test]
+    fn v0_application_gets_its_job_once_migrated() {
+        let v0 = ApplicationParameterV0 {
+            authority: Pubkey::new_from_array([1; 32]),
+            status: JobStatus::Selected,
+            staked_amount: 4_000,
+            max_allowed_staked: 10_000,
+            total_reward_amount: 10_000,
+            update_reward_value_in_job: true,
+        };
+        let mut data = ApplicationParameter::discriminator().to_vec();
+        v0.serialize(&mut data).unwrap();
+        // what migrate_application leaves in the account before upgrading it: the v0 bytes
+        // followed by zeros
+        data.resize(APPLICATION_SPACE, 0);
+
+        let mut parameters = ApplicationParameter::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(parameters.version, 0);
+        let job = Pubkey::new_from_array([2; 32]);
+        parameters.upgrade(job, 1_650_000_000);
+
+        assert_eq!(parameters.version, APPLICATION_VERSION);
+        assert_eq!(parameters.authority, v0.authority);
+        assert_eq!(parameters.status, JobStatus::Selected);
+        assert_eq!(parameters.staked_amount, 4_000);
+        assert_eq!(parameters.max_allowed_staked, 10_000);
+        assert_eq!(parameters.total_reward_amount, 10_000);
+        assert!(parameters.update_reward_value_in_job);
+        assert_eq!(parameters.job, job);
+        assert_eq!(parameters.created_at, 1_650_000_000);
+        assert_eq!(parameters.status_updated_at, 1_650_000_000);
+        assert_eq!(parameters.candidate, Pubkey::default());
+        assert_eq!(parameters.status_change_count, 0);
+        assert_eq!(parameters.challenge, ChallengeState::Unchallenged);
+        assert_eq!(parameters.bump, 0);
+        assert_eq!(parameters.committed_reward, 0);
+        assert_eq!(parameters.reserved, [0; APPLICATION_RESERVED]);
+
+        // its status was never recorded, so a challenge of it couldn't be resolved
+        assert!(parameters.open_challenge().is_err());
+        assert_eq!(parameters.challenge, ChallengeState::Unchallenged);
+        parameters.status_change_count = 1;
+        parameters.open_challenge().unwrap();
+        assert_eq!(parameters.challenge, ChallengeState::Open);
+    }
+
+    fn new_status_proposal() -> StatusProposal {
+        StatusProposal {
+            application: Pubkey::new_from_array([0; 32]),
+            proposer: Pubkey::new_from_array([0; 32]),
+            status: JobStatus::Selected,
+            reason_code: 0,
+            evidence_hash: [0; 32],
+            status_change_count: 0,
+            approvals: 0,
+            created_at: 0,
+            approver_nonce: 0,
+            version: STATUS_PROPOSAL_VERSION,
+            reserved: [0; RECORD_RESERVED],
+        }
+    }
+
+    #[test]
+    fn status_proposal_counts_each_approver_once() {
+        let mut proposal = new_status_proposal();
+        proposal.approve(0).unwrap();
+        proposal.approve(7).unwrap();
+        assert!(proposal.approve(0).is_err());
+        assert_eq!(proposal.approval_count(8), 2);
+    }
+
+    /// Builds the data of an ed25519 precompile instruction the same way the web3 client does.
+    fn ed25519_instruction(public_key: &Pubkey, message: &[u8]) -> Instruction {
+        let public_key_offset = ED25519_OFFSETS_START + ED25519_OFFSETS_SIZE;
+        let signature_offset = public_key_offset + 32;
+        let message_offset = signature_offset + 64;
+
+        let mut data = vec![1, 0];
+        for value in [signature_offset, u16::MAX as usize, public_key_offset, u16::MAX as usize, message_offset, message.len(), u16::MAX as usize] {
+            data.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        data.extend_from_slice(public_key.as_ref());
+        data.extend_from_slice(&[7; 64]);
+        data.extend_from_slice(message);
+
+        Instruction { program_id: ed25519_program::ID, accounts: vec![], data }
+    }
+
+    #[test]
+    fn attestation_checks_the_signed_message_and_key() {
+        let attester = Pubkey::new_from_array([1; 32]);
+        let job = Pubkey::new_from_array([4; 32]);
+        let message = attestation_message(&ID, &job, "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61", &JobStatus::Selected, 1_000);
+        let ixn = ed25519_instruction(&attester, &message);
+
+        assert_eq!(verify_attestation(&ixn, &attester, &message).unwrap(), [7; 64]);
+
+        let other_message = attestation_message(&ID, &job, "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61", &JobStatus::Selected, 1_001);
+        assert!(verify_attestation(&ixn, &attester, &other_message).is_err());
+        assert!(verify_attestation(&ixn, &Pubkey::new_from_array([2; 32]), &message).is_err());
+
+        let other_job_message = attestation_message(&ID, &Pubkey::new_from_array([5; 32]), "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61", &JobStatus::Selected, 1_000);
+        assert!(verify_attestation(&ixn, &attester, &other_job_message).is_err());
+        let other_program_message = attestation_message(&Pubkey::new_from_array([6; 32]), &job, "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61", &JobStatus::Selected, 1_000);
+        assert!(verify_attestation(&ixn, &attester, &other_program_message).is_err());
+    }
+
+    #[test]
+    fn attestation_needs_the_ed25519_program() {
+        let attester = Pubkey::new_from_array([1; 32]);
+        let job = Pubkey::new_from_array([4; 32]);
+        let message = attestation_message(&ID, &job, "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61", &JobStatus::Hired, 1_000);
+        let mut ixn = ed25519_instruction(&attester, &message);
+        ixn.program_id = Pubkey::new_from_array([3; 32]);
+
+        assert!(verify_attestation(&ixn, &attester, &message).is_err());
+    }
+
+    #[test]
+    fn status_proposal_ignores_approvers_removed_from_the_set() {
+        let mut proposal = new_status_proposal();
+        proposal.approve(0).unwrap();
+        proposal.approve(2).unwrap();
+        assert_eq!(proposal.approval_count(2), 1);
+        assert_eq!(proposal.approval_count(0), 0);
+    }
 }
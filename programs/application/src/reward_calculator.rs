@@ -1,8 +1,16 @@
 use crate::{ApplicationParameter, JobStatus};
 use anchor_lang::prelude::*;
+use job::{StatusPolicy, MAX_BPS};
 
 use std::cmp::{max, min};
 
+/// How a stake was split across the tiers and what it yields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardBreakdown {
+    pub tier_amounts: [u64; 3],
+    pub reward_amount: u64,
+}
+
 /// RewardCalculator is a utility wrapper type that contains
 /// the application parameters and yields how much will be paid
 /// corresponding to each tier in case the candidate is selected
@@ -10,20 +18,64 @@ use std::cmp::{max, min};
 ///
 /// Note that for simplicity we're not allowing to stake if the amount
 /// to be staked exceeds the available amount to stake.
-
+///
+/// Optionally, a time decay can be applied on top of the tiers: the part
+/// of the reward that exceeds the staked amount is halved every `half_life`
+/// seconds since the application was created, so the effective multiplier
+/// of a tier is `1 + (multiplier - 1) * decay`. The principal is never decayed.
+///
+/// With a status policy, whether staking is open and how much the premium is
+/// boosted or cut depend on the status of the application instead of only
+/// allowing stakes while it is pending.
+///
+/// Finally, the premium can be boosted by the reputation of the staker, see
+/// `general::ReputationConfig`.
 pub struct RewardCalculator<'a> {
     application_parameters: &'a ApplicationParameter,
+    half_life: i64,
+    now: i64,
+    status_policy: Option<&'a StatusPolicy>,
+    reputation_boost_bps: u16,
 }
 
 impl<'a> RewardCalculator<'a> {
     pub fn new(application_parameters: &'a ApplicationParameter) -> Self {
         Self {
             application_parameters,
+            half_life: 0,
+            now: 0,
+            status_policy: None,
+            reputation_boost_bps: 0,
         }
     }
 
+    /// Boosts the premium by `boost_bps` on top of everything else.
+    pub fn with_reputation_boost(mut self, boost_bps: u16) -> Self {
+        self.reputation_boost_bps = boost_bps;
+        self
+    }
+
+    /// Applies the job's policy for the current status of the application.
+    pub fn with_status_policy(mut self, status_policy: &'a StatusPolicy) -> Self {
+        self.status_policy = Some(status_policy);
+        self
+    }
+
+    /// Enables the time decay. A `half_life` of 0 leaves the tiers untouched.
+    pub fn with_time_decay(mut self, half_life: i64, now: i64) -> Self {
+        self.half_life = half_life;
+        self.now = now;
+        self
+    }
+
     pub fn calculate_reward(&self, k: u64) -> Result<u64> {
+        Ok(self.calculate_reward_breakdown(k)?.reward_amount)
+    }
+
+    /// Same as `calculate_reward` but also yields how much of the amount landed in each tier.
+    pub fn calculate_reward_breakdown(&self, k: u64) -> Result<RewardBreakdown> {
         // for simplicity -> k: amount_pledged_to_stake
+        let amount = k;
         let mut k: u64 = k;
         let w = self.application_parameters.staked_amount;
         let max_allowed_staked = self.application_parameters.max_allowed_staked;
@@ -32,8 +84,14 @@ impl<'a> RewardCalculator<'a> {
             .checked_sub(w)
             .ok_or_else(|| ErrorCode::AmountUnderflow)?;
         msg!("{}", available_amount_to_stake);
-        if self.application_parameters.status != JobStatus::Pending {
-            return Err(error!(ErrorCode::StatusNotPending));
+        match self.status_policy {
+            Some(status_policy) if !status_policy.allow_stake => {
+                return Err(error!(ErrorCode::StakingClosed));
+            }
+            None if self.application_parameters.status != JobStatus::Pending => {
+                return Err(error!(ErrorCode::StatusNotPending));
+            }
+            _ => {}
         }
         if k > available_amount_to_stake {
             return Err(error!(ErrorCode::NotEnoughStakeAvailable));
@@ -70,7 +128,61 @@ impl<'a> RewardCalculator<'a> {
 
         let a = 3;
         let b = 2;
-        Ok(k_tier_1 * a + k_tier_2 * b + k_tier_3 * 3 / 2)
+        let reward = k_tier_1 * a + k_tier_2 * b + k_tier_3 * 3 / 2;
+
+        Ok(RewardBreakdown {
+            tier_amounts: [k_tier_1, k_tier_2, k_tier_3],
+            reward_amount: self.apply_reputation_boost(
+                amount,
+                self.apply_status_multiplier(amount, self.apply_time_decay(amount, reward)),
+            ),
+        })
+    }
+
+    /// Halves the premium (reward above the principal) once per elapsed
+    /// half-life, interpolating linearly within the current half-life so
+    /// that the decay is continuous and only uses integer math.
+    fn apply_time_decay(&self, amount: u64, reward: u64) -> u64 {
+        if self.half_life <= 0 {
+            return reward;
+        }
+
+        let elapsed = max(0, self.now - self.application_parameters.created_at) as u64;
+        let half_life = self.half_life as u64;
+
+        let halvings = elapsed / half_life;
+        if halvings >= 64 {
+            return amount;
+        }
+
+        let premium = reward.saturating_sub(amount) >> halvings;
+        let remainder = elapsed % half_life;
+        let premium = (premium as u128 * (2 * half_life - remainder) as u128
+            / (2 * half_life) as u128) as u64;
+
+        amount + premium
+    }
+
+    /// Scales the premium by the multiplier of the status policy, the principal is kept whole.
+    fn apply_status_multiplier(&self, amount: u64, reward: u64) -> u64 {
+        let multiplier_bps = match self.status_policy {
+            Some(status_policy) => status_policy.multiplier_bps,
+            None => return reward,
+        };
+
+        let premium = reward.saturating_sub(amount);
+        amount + (premium as u128 * multiplier_bps as u128 / MAX_BPS as u128) as u64
+    }
+
+    /// Scales the premium up by the reputation boost, the principal is kept whole.
+    fn apply_reputation_boost(&self, amount: u64, reward: u64) -> u64 {
+        if self.reputation_boost_bps == 0 {
+            return reward;
+        }
+
+        let premium = reward.saturating_sub(amount);
+        let multiplier_bps = MAX_BPS as u128 + self.reputation_boost_bps as u128;
+        amount + (premium as u128 * multiplier_bps / MAX_BPS as u128) as u64
     }
 }
 
@@ -82,13 +194,15 @@ pub enum ErrorCode {
     StatusNotPending,
     #[msg("Staking available is less than what the user wants to stake")]
     NotEnoughStakeAvailable,
+    #[msg("Staking is closed at the current status of the application")]
+    StakingClosed,
 }
 
 #[cfg(test)]
 mod test {
     use anchor_lang::prelude::Pubkey;
 
-    use crate::JobStatus;
+    use crate::{ChallengeState, JobStatus};
 
     use super::*;
 
@@ -104,51 +218,51 @@ mod test {
             max_allowed_staked,
             total_reward_amount,
             update_reward_value_in_job: false,
+            created_at: 0,
+            job: Pubkey::new_from_array([0; 32]),
+            candidate: Pubkey::new_from_array([0; 32]),
+            status_change_count: 0,
+            status_updated_at: 0,
+            challenge: ChallengeState::Unchallenged,
+            version: 1,
+            bump: 0,
+            committed_reward: 0,
+            reserved: [0; crate::APPLICATION_RESERVED],
         }
     }
 
     #[test]
     fn calculate_reward_tier_one_only() {
         let application_parameters = new_application_parameters(0, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 30);
     }
 
     #[test]
     fn calculate_reward_tier_two_only() {
         let application_parameters = new_application_parameters(33, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 20);
     }
 
     #[test]
     fn calculate_reward_tier_three_only() {
         let application_parameters = new_application_parameters(66, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 15);
     }
 
     #[test]
     fn calculate_reward_tier_one_and_two() {
         let application_parameters = new_application_parameters(0, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         assert_eq!(reward_calculator.calculate_reward(40).unwrap(), 99 + 14);
     }
 
     #[test]
     fn calculate_reward_tier_two_and_three() {
         let application_parameters = new_application_parameters(50, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         assert_eq!(
             reward_calculator.calculate_reward(40).unwrap(),
             0 + 16 * 2 + 24 * 3 / 2
@@ -158,9 +272,7 @@ mod test {
     #[test]
     fn calculate_reward_three_tiers_only() {
         let application_parameters = new_application_parameters(0, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         assert_eq!(
             reward_calculator.calculate_reward(100).unwrap(),
             33 * 3 + 33 * 2 + 34 * 3 / 2
@@ -170,11 +282,102 @@ mod test {
     #[test]
     fn calculate_reward_not_staking_capacity() {
         let application_parameters = new_application_parameters(80, 100, 0);
-        let reward_calculator = RewardCalculator {
-            application_parameters: &application_parameters,
-        };
+        let reward_calculator = RewardCalculator::new(&application_parameters);
         // there's capacity for 20
         assert!(reward_calculator.calculate_reward(20).is_ok());
         assert!(reward_calculator.calculate_reward(21).is_err());
     }
+
+    #[test]
+    fn calculate_reward_breakdown_per_tier() {
+        let application_parameters = new_application_parameters(50, 100, 0);
+        let reward_calculator = RewardCalculator::new(&application_parameters);
+        assert_eq!(
+            reward_calculator.calculate_reward_breakdown(40).unwrap(),
+            RewardBreakdown {
+                tier_amounts: [0, 16, 24],
+                reward_amount: 16 * 2 + 24 * 3 / 2,
+            }
+        );
+    }
+
+    #[test]
+    fn calculate_reward_without_time_decay() {
+        let application_parameters = new_application_parameters(0, 100, 0);
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_time_decay(0, 1_000_000);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 30);
+    }
+
+    #[test]
+    fn calculate_reward_time_decay_halves_premium() {
+        let application_parameters = new_application_parameters(0, 100, 0);
+        // right at creation the tier multiplier applies fully
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_time_decay(100, 0);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 30);
+        // after one half-life the 20 of premium becomes 10
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_time_decay(100, 100);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 20);
+        // halfway through the first half-life it's interpolated
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_time_decay(100, 50);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 25);
+    }
+
+    #[test]
+    fn calculate_reward_time_decay_never_touches_principal() {
+        let application_parameters = new_application_parameters(0, 100, 0);
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_time_decay(1, i64::MAX);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 10);
+    }
+
+    #[test]
+    fn calculate_reward_status_policy_closes_staking() {
+        let mut application_parameters = new_application_parameters(0, 100, 0);
+        application_parameters.status = JobStatus::Offered;
+        let status_policy = StatusPolicy::new(false, false);
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_status_policy(&status_policy);
+        assert!(reward_calculator.calculate_reward(10).is_err());
+
+        let status_policy = StatusPolicy::new(true, false);
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_status_policy(&status_policy);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 30);
+    }
+
+    #[test]
+    fn calculate_reward_status_multiplier_scales_premium() {
+        let application_parameters = new_application_parameters(0, 100, 0);
+        let mut status_policy = StatusPolicy::new(true, false);
+        // a cut to half of the premium
+        status_policy.multiplier_bps = 5_000;
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_status_policy(&status_policy);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 20);
+        // a boost of half the premium
+        status_policy.multiplier_bps = 15_000;
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_status_policy(&status_policy);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 40);
+    }
+
+    #[test]
+    fn calculate_reward_reputation_boosts_premium() {
+        let application_parameters = new_application_parameters(0, 100, 0);
+        // a boost of a quarter of the premium
+        let reward_calculator =
+            RewardCalculator::new(&application_parameters).with_reputation_boost(2_500);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 35);
+        // applied after the status multiplier
+        let mut status_policy = StatusPolicy::new(true, false);
+        status_policy.multiplier_bps = 5_000;
+        let reward_calculator = RewardCalculator::new(&application_parameters)
+            .with_status_policy(&status_policy)
+            .with_reputation_boost(10_000);
+        assert_eq!(reward_calculator.calculate_reward(10).unwrap(), 30);
+    }
 }
@@ -1,13 +1,17 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount, Transfer};
-use application::cpi::accounts::UpdateStakeAmount;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::program::set_return_data;
+use anchor_lang::{AccountsClose, AccountsExit};
+use anchor_spl::token::{Burn, CloseAccount, Mint, MintTo, Token, TokenAccount, Transfer};
+use application::cpi::accounts::{SetChallenged, UpdateStakeAmount};
 use application::program::Application;
-use application::{self, ApplicationParameter, JobStatus, RewardCalculator};
+use application::{self, ApplicationParameter, ChallengeState, JobStatus, RewardCalculator};
 use general::program::General;
-use general::{self, GeneralParameter};
-use job::cpi::accounts::UnstakeToken;
+use general::versioning::{self, RESERVED_SPACE};
+use general::{self, GeneralParameter, ReputationConfig, StakerApproval};
+use job::cpi::accounts::{RecordForfeit, UnstakeToken};
 use job::program::Job;
-use job::{self, JobStakingParameter};
+use job::{self, JobGating, JobStakingParameter, StakeLimits, MAX_BPS};
 
 declare_id!("GCPpQABMRJ7bhRCqaYtBo7G1b5popATvcXDs2c1bK4hW");
 
@@ -16,6 +20,42 @@ const APPLICATION_SEED: &'static [u8] = b"application";
 const GENERAL_SEED: &'static [u8] = b"general";
 const WALLET_SEED: &'static [u8] = b"wallet";
 const JOB_SEED: &'static [u8] = b"jobfactory";
+const POSITION_SEED: &'static [u8] = b"position";
+const RECEIPT_SEED: &'static [u8] = b"receipt";
+const COMMIT_QUEUE_SEED: &'static [u8] = b"commitqueue";
+const COMMITMENT_SEED: &'static [u8] = b"commitment";
+const CHALLENGE_SEED: &'static [u8] = b"challenge";
+const CHALLENGE_VAULT_SEED: &'static [u8] = b"challengevault";
+const PROFILE_SEED: &'static [u8] = b"profile";
+const REFERRER_SEED: &'static [u8] = b"referrer";
+const REFERRAL_VAULT_SEED: &'static [u8] = b"referralvault";
+const CRANK_TREASURY_SEED: &'static [u8] = b"cranktreasury";
+
+pub const CANDIDATE_VERSION: u8 = 3;
+const CANDIDATE_SPACE: usize = 8 + 8 + 32 + 8 + 8 + 8 + 32 + 32 + 8 + 1 + RESERVED_SPACE + 32;
+pub const PROFILE_VERSION: u8 = 1;
+const PROFILE_SPACE: usize = 8 + 32 + 8 * 6 + 1 + RESERVED_SPACE;
+pub const REFERRER_VERSION: u8 = 1;
+const REFERRER_SPACE: usize = 8 + 32 + 8 * 2 + 1 + RESERVED_SPACE;
+/// The reserved bytes left once the bump, the last stake time, the haircut and the sponsored
+/// stake count are carved out of them.
+pub const CANDIDATE_RESERVED: usize = RESERVED_SPACE - 1 - 8 - 8 - 8;
+/// The reserved bytes left once the bump is carved out of them, for profiles and referrers.
+pub const PROFILE_RESERVED: usize = RESERVED_SPACE - 1;
+/// Positions and challenges don't use any of their reserved bytes yet.
+const RECORD_RESERVED: usize = RESERVED_SPACE;
+/// The reserved bytes of a commitment left once the commit time is carved out of them.
+const COMMITMENT_RESERVED: usize = RESERVED_SPACE - 8;
+/// The reserved bytes of a commit queue left once the reveal order is carved out of them.
+const COMMIT_QUEUE_RESERVED: usize = RESERVED_SPACE - 8 * 3;
+pub const POSITION_VERSION: u8 = 1;
+const POSITION_SPACE: usize = 8 + 32 + 8 + 8 + 8 * 3 + 8 + 8 + 8 + 1 + RESERVED_SPACE;
+pub const COMMIT_QUEUE_VERSION: u8 = 2;
+const COMMIT_QUEUE_SPACE: usize = 8 + 8 + 8 + 1 + RESERVED_SPACE;
+pub const COMMITMENT_VERSION: u8 = 1;
+const COMMITMENT_SPACE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1 + RESERVED_SPACE;
+pub const CHALLENGE_VERSION: u8 = 1;
+const CHALLENGE_SPACE: usize = 8 + 32 + 32 + 8 + 1 + 8 + 1 + RESERVED_SPACE;
 
 #[program]
 pub mod candidate_staking {
@@ -26,297 +66,1451 @@ pub mod candidate_staking {
         ctx: Context<Initialize>,
         _job_ad_id: String,
         _application_id: String,
-        _job_bump: u8,
     ) -> Result<()> {
+        check_gating(
+            &ctx.accounts.job_account,
+            &ctx.accounts.gate,
+            &ctx.accounts.authority.key(),
+        )?;
+
         let state = &mut ctx.accounts.base_account;
 
         state.reset(ctx.accounts.authority.key());
+        state.bump = *ctx.bumps.get("base_account").unwrap();
+
+        Ok(())
+    }
+
+    /// Upgrades a staker's account created with an older layout to the current one. Anyone can
+    /// pay for it, it grows the account, fills in what an account from before versioning lacks
+    /// and bumps its version.
+    pub fn migrate_candidate(
+        ctx: Context<MigrateCandidate>,
+        _application_id: String,
+        _staker: Pubkey,
+    ) -> Result<()> {
+        let account_info = ctx.accounts.base_account.to_account_info();
+        versioning::grow_account(
+            &account_info,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            CANDIDATE_SPACE,
+        )?;
+
+        let mut parameters = Account::<CandidateParameter>::try_from(&account_info)?;
+        if parameters.version >= CANDIDATE_VERSION {
+            return Err(error!(ErrorCode::AlreadyMigrated));
+        }
+        parameters.upgrade();
+        parameters.bump = *ctx.bumps.get("base_account").unwrap();
+        parameters.exit(ctx.program_id)?;
+
+        Ok(())
+    }
+
+    /// Upgrades the commit queue of `application` created before queues were versioned, or
+    /// before reveals were ordered. Anyone can pay for it, it grows the account and sets its
+    /// version.
+    pub fn migrate_commit_queue(
+        ctx: Context<MigrateCommitQueue>,
+        _application: Pubkey,
+    ) -> Result<()> {
+        let account_info = ctx.accounts.commit_queue.to_account_info();
+        versioning::grow_account(
+            &account_info,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            COMMIT_QUEUE_SPACE,
+        )?;
+
+        let mut commit_queue = Account::<CommitQueue>::try_from(&account_info)?;
+        if commit_queue.version >= COMMIT_QUEUE_VERSION {
+            return Err(error!(ErrorCode::AlreadyMigrated));
+        }
+        commit_queue.upgrade();
+        commit_queue.exit(ctx.program_id)?;
+
+        Ok(())
+    }
+
+    /// Creates the receipt mint of the position. From then on every stake mints receipts 1:1
+    /// with the staked amount and the position can only be paid out by burning them.
+    pub fn enable_receipts(
+        ctx: Context<EnableReceipts>,
+        _job_ad_id: String,
+        _application_id: String,
+    ) -> Result<()> {
+        let candidate_parameter = &mut ctx.accounts.base_account;
+
+        if candidate_parameter.staked_amount != 0 {
+            return Err(error!(ErrorCode::ReceiptsAfterStaking));
+        }
+
+        candidate_parameter.receipt_mint = ctx.accounts.receipt_mint.key();
 
         Ok(())
     }
 
-    pub fn stake(
-        ctx: Context<Stake>,
+    pub fn stake<'info>(
+        ctx: Context<'_, '_, '_, 'info, Stake<'info>>,
         _job_ad_id: String,
         application_id: String,
-        base_bump: u8,
-        _general_bump: u8,
-        application_bump: u8,
-        _job_bump: u8,
-        _wallet_bump: u8,
         amount: u64,
+        referrer: Option<Pubkey>,
     ) -> Result<()> {
-        let general_parameter = &mut ctx.accounts.general_account;
-        let application_parameter = &mut ctx.accounts.application_account;
-        let candidate_parameter = &mut ctx.accounts.base_account;
+        if ctx.accounts.job_account.reveal_window != 0 {
+            return Err(error!(ErrorCode::CommitRevealRequired));
+        }
+        if let Some(referrer) = referrer {
+            let payer = ctx.accounts.wallet_to_withdraw_from.owner;
+            ctx.accounts.base_account.set_referrer(referrer, &payer)?;
+        }
 
-        let already_staked_amount = application_parameter.staked_amount;
-        let max_amount = application_parameter.max_allowed_staked;
+        let authority_key = ctx.accounts.authority.key();
+        ctx.accounts
+            .staker_profile
+            .initialize_if_new(authority_key, *ctx.bumps.get("staker_profile").unwrap());
 
-        if already_staked_amount
-            .checked_add(amount)
-            .ok_or_else(|| ErrorCode::MaxAmountExceeded)?
-            < max_amount
-        {
-            msg!("You can transfer");
-            msg!("Transfer is initiated");
+        process_stake(
+            ctx.accounts,
+            ctx.remaining_accounts,
+            &application_id,
+            amount,
+            None,
+        )
+    }
 
-            let reward_calculator = RewardCalculator::new(application_parameter.as_ref());
+    /// Stakes on behalf of `beneficiary`: the payer funds the stake while the position and its
+    /// rewards belong to the beneficiary. With `principal_to_payer` the principal goes back to
+    /// the payer when the position is paid out.
+    pub fn stake_for<'info>(
+        ctx: Context<'_, '_, '_, 'info, StakeFor<'info>>,
+        _job_ad_id: String,
+        application_id: String,
+        beneficiary: Pubkey,
+        amount: u64,
+        principal_to_payer: bool,
+    ) -> Result<()> {
+        if ctx.accounts.job_account.reveal_window != 0 {
+            return Err(error!(ErrorCode::CommitRevealRequired));
+        }
+        // the position belongs to the beneficiary, so they're the one who has to be let in
+        check_gating(&ctx.accounts.job_account, &ctx.accounts.gate, &beneficiary)?;
 
-            candidate_parameter.staked_amount = candidate_parameter
-                .staked_amount
-                .checked_add(amount)
-                .ok_or_else(|| ErrorCode::StakeAmountOverflow)?;
-            let reward_amount = reward_calculator.calculate_reward(amount)?;
-            candidate_parameter.reward_amount = candidate_parameter
-                .reward_amount
-                .checked_add(reward_amount)
-                .ok_or_else(|| ErrorCode::RewardAmountOverflow)?;
+        let payer_key = ctx.accounts.payer.key();
+        // a deposit the beneficiary didn't sign for
+        let sponsored = payer_key != beneficiary && !ctx.accounts.beneficiary_wallet.is_signer;
+        // neither can stake on the application through the other
+        for staker in [&beneficiary, &payer_key] {
+            check_conflict_of_interest(
+                &ctx.accounts.job_account,
+                &ctx.accounts.application_account.candidate,
+                staker,
+            )?;
+        }
 
-            let authority_key = ctx.accounts.authority.key();
+        if ctx.accounts.base_account.authority == Pubkey::default() {
+            ctx.accounts.base_account.reset(beneficiary);
+            ctx.accounts.base_account.bump = *ctx.bumps.get("base_account").unwrap();
+        }
+        let principal_recipient = if principal_to_payer {
+            // otherwise anyone could pin the principal of someone else's position to
+            // themselves with a dust stake
+            if ctx.accounts.base_account.staked_amount == 0
+                && !ctx.accounts.beneficiary_wallet.is_signer
+            {
+                return Err(error!(ErrorCode::BeneficiaryConsentRequired));
+            }
+            payer_key
+        } else {
+            beneficiary
+        };
+        ctx.accounts
+            .base_account
+            .set_principal_recipient(principal_recipient)?;
+        let reputation_boost_bps = ctx
+            .accounts
+            .staker_profile
+            .reputation_boost_bps(&ctx.accounts.general_account.reputation);
+        record_stake(
+            &ctx.accounts.job_account,
+            &ctx.accounts.application_account,
+            reputation_boost_bps,
+            &mut ctx.accounts.base_account,
+            &mut ctx.accounts.position_account,
+            amount,
+            None,
+            sponsored,
+        )?;
+        ctx.accounts
+            .staker_profile
+            .initialize_if_new(beneficiary, *ctx.bumps.get("staker_profile").unwrap());
+        ctx.accounts.staker_profile.record_stake(amount)?;
 
-            let bump_vector = base_bump.to_le_bytes();
-            let inner = vec![
-                CANDIDATE_SEED,
-                application_id.as_bytes()[..18].as_ref(),
-                application_id.as_bytes()[18..].as_ref(),
-                authority_key.as_ref(),
-                bump_vector.as_ref(),
-            ];
-            let outer = vec![inner.as_slice()];
+        let bump_vector = ctx.accounts.base_account.bump.to_le_bytes();
+        let inner = vec![
+            CANDIDATE_SEED,
+            application_id.as_bytes()[..18].as_ref(),
+            application_id.as_bytes()[18..].as_ref(),
+            beneficiary.as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let outer = vec![inner.as_slice()];
 
-            let cpi_accounts = UpdateStakeAmount {
-                base_account: ctx.accounts.application_account.to_account_info(),
-                authority: ctx.accounts.authority.to_account_info(),
-                instruction: ctx.accounts.instruction.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.application_program.to_account_info();
-            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, outer.as_slice());
-            application::cpi::update_stake_amount(
-                cpi_ctx,
-                application_id.clone(),
-                application_bump,
+        let cpi_accounts = UpdateStakeAmount {
+            base_account: ctx.accounts.application_account.to_account_info(),
+            authority: ctx.accounts.payer.to_account_info(),
+            instruction: ctx.accounts.instruction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.application_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, outer.as_slice());
+        application::cpi::update_stake_amount(
+            cpi_ctx,
+            application_id.clone(),
+            amount,
+            ctx.accounts.base_account.reward_amount,
+        )?;
+
+        let transfer_instruction = Transfer {
+            from: ctx.accounts.wallet_to_withdraw_from.to_account_info(),
+            to: ctx.accounts.escrow_wallet_state.to_account_info(),
+            authority: ctx.accounts.payer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+
+        anchor_spl::token::transfer(cpi_ctx, amount)?;
+        msg!("token is deposited on behalf of {}", beneficiary);
+
+        if ctx.accounts.base_account.receipt_mint != Pubkey::default() {
+            mint_receipts(
+                &ctx.accounts.base_account,
+                ctx.remaining_accounts,
+                ctx.accounts.token_program.to_account_info(),
+                outer.as_slice(),
                 amount,
-                ctx.accounts.base_account.reward_amount,
             )?;
+        }
 
-            // Below is the actual instruction that we are going to send to the Token program.
-            let transfer_instruction = Transfer {
-                from: ctx.accounts.wallet_to_withdraw_from.to_account_info(),
-                to: ctx.accounts.escrow_wallet_state.to_account_info(),
-                authority: ctx.accounts.authority.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                transfer_instruction,
-                outer.as_slice(), //signer PDA
-            );
+        Ok(())
+    }
 
-            anchor_spl::token::transfer(cpi_ctx, amount)?;
-            msg!("token is deposited");
-        } else {
-            return Err(error!(ErrorCode::MaxAmountExceeded));
+    /// First phase of commit-reveal staking: only the hash of the stake is published, so the
+    /// stake can't be front-run. Commitments are queued in the order they land, which is the
+    /// order they're revealed and priced in.
+    pub fn commit_stake(
+        ctx: Context<CommitStake>,
+        _job_ad_id: String,
+        _application_id: String,
+        hash: [u8; 32],
+    ) -> Result<()> {
+        if ctx.accounts.job_account.reveal_window == 0 {
+            return Err(error!(ErrorCode::CommitRevealDisabled));
+        }
+        let status = &ctx.accounts.application_account.status;
+        if !status.policy(&ctx.accounts.job_account).allow_stake {
+            return Err(error!(ErrorCode::StakingClosed));
+        }
+        if ctx.accounts.job_account.is_filled {
+            return Err(error!(ErrorCode::JobFilled));
+        }
+        // the stake is rate limited when it's committed, the reveal window may well be longer
+        // than the cooldown
+        let clock = Clock::get()?;
+        check_stake_rate(
+            &ctx.accounts.job_account,
+            &ctx.accounts.base_account,
+            clock.unix_timestamp,
+        )?;
+
+        let commit_queue = &mut ctx.accounts.commit_queue;
+        let commitment = &mut ctx.accounts.commitment;
+
+        // a queue created by this commitment, or one from before reveals were ordered
+        if commit_queue.version < COMMIT_QUEUE_VERSION {
+            commit_queue.upgrade();
         }
 
+        commitment.authority = ctx.accounts.authority.key();
+        commitment.hash = hash;
+        commitment.index = commit_queue.commit_count;
+        commitment.slot = clock.slot;
+        // the window is fixed at commit time, so changing it can't strand pending commitments
+        commitment.deadline = commitment.slot + ctx.accounts.job_account.reveal_window;
+        commitment.version = COMMITMENT_VERSION;
+        commitment.committed_at = clock.unix_timestamp;
+        commitment.reserved = [0; COMMITMENT_RESERVED];
+        commit_queue.commit_count += 1;
+        commit_queue.pending_count += 1;
+
         Ok(())
     }
 
-    pub fn unstake(
-        ctx: Context<Unstake>,
-        base_bump: u8,
-        _application_bump: u8,
-        wallet_bump: u8,
+    /// Second phase of commit-reveal staking. Commitments are revealed in the order they were
+    /// made, so each stake is priced against the stakes committed before it. The next commitment
+    /// gets a full reveal window from when the one before it is revealed or expired, so a staker
+    /// who never reveals only holds the others back until `expire_commitment`.
+    pub fn reveal_stake<'info>(
+        ctx: Context<'_, '_, '_, 'info, RevealStake<'info>>,
+        _job_ad_id: String,
         application_id: String,
-        job_ad_id: String,
-        job_bump: u8,
+        amount: u64,
+        salt: [u8; 32],
     ) -> Result<()> {
-        let application = &mut ctx.accounts.application_account;
-        let candidate_parameters = &mut ctx.accounts.base_account;
+        let commit_queue = &mut ctx.accounts.commit_queue;
+        let commitment = &ctx.accounts.commitment;
+        let authority_key = ctx.accounts.stake.authority.key();
+        let slot = Clock::get()?.slot;
+
+        if commit_queue.version < COMMIT_QUEUE_VERSION {
+            commit_queue.upgrade();
+        }
+        if commitment.hash != commitment_hash(amount, &salt, &authority_key) {
+            return Err(error!(ErrorCode::InvalidReveal));
+        }
+        if !commit_queue.is_next(commitment) {
+            return Err(error!(ErrorCode::RevealOutOfOrder));
+        }
+        if slot > commit_queue.reveal_deadline(commitment) {
+            return Err(error!(ErrorCode::CommitmentExpired));
+        }
+        commit_queue.advance(commitment, slot);
+
+        let committed_at = commitment.committed_at;
+        ctx.accounts
+            .stake
+            .staker_profile
+            .initialize_if_new(authority_key, *ctx.bumps.get("staker_profile").unwrap());
+
+        process_stake(
+            &mut ctx.accounts.stake,
+            ctx.remaining_accounts,
+            &application_id,
+            amount,
+            Some(committed_at),
+        )?;
+
+        ctx.accounts
+            .commitment
+            .close(ctx.accounts.stake.authority.to_account_info())?;
+
+        Ok(())
+    }
+
+    /// Drops the next commitment to reveal once its reveal window is over, which lets the
+    /// commitment after it reveal, and returns its rent to the staker.
+    pub fn expire_commitment(
+        ctx: Context<ExpireCommitment>,
+        _job_ad_id: String,
+        _application_id: String,
+    ) -> Result<()> {
+        let commit_queue = &mut ctx.accounts.commit_queue;
+        let commitment = &ctx.accounts.commitment;
+        let slot = Clock::get()?.slot;
+
+        if commit_queue.version < COMMIT_QUEUE_VERSION {
+            commit_queue.upgrade();
+        }
+        if !commit_queue.is_next(commitment) {
+            return Err(error!(ErrorCode::RevealOutOfOrder));
+        }
+        if slot <= commit_queue.reveal_deadline(commitment) {
+            return Err(error!(ErrorCode::CommitmentNotExpired));
+        }
+
+        commit_queue.advance(commitment, slot);
+        ctx.accounts
+            .commitment
+            .close(ctx.accounts.staker.to_account_info())?;
+
+        Ok(())
+    }
+
+    /// Returns, through the return data, the reward that staking `amount` right now would yield.
+    /// It goes through the same calculator as `stake` so quotes and stakes never diverge.
+    pub fn quote_reward(
+        ctx: Context<QuoteReward>,
+        _job_ad_id: String,
+        _application_id: String,
+        _staker: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        let profile_info = ctx.accounts.staker_profile.to_account_info();
+        let reputation_boost_bps = if profile_info.data_is_empty() {
+            0
+        } else {
+            Account::<StakerProfile>::try_from(&profile_info)?
+                .reputation_boost_bps(&ctx.accounts.general_account.reputation)
+        };
+        let reward_calculator = reward_calculator(
+            &ctx.accounts.job_account,
+            &ctx.accounts.application_account,
+            reputation_boost_bps,
+        )?;
+        let reward_amount = reward_calculator.calculate_reward(amount)?;
+
+        msg!("reward for staking {} is {}", amount, reward_amount);
+        set_return_data(&reward_amount.to_le_bytes());
+
+        Ok(())
+    }
+
+    pub fn unstake(ctx: Context<Unstake>, application_id: String, job_ad_id: String) -> Result<()> {
+        let application = &ctx.accounts.application_account;
+        let candidate_parameters = &ctx.accounts.base_account;
 
         if candidate_parameters.staked_amount == 0 && candidate_parameters.reward_amount == 0 {
             return Err(error!(ErrorCode::AlreadyUnstaked));
         }
+        if candidate_parameters.receipt_mint != Pubkey::default() {
+            return Err(error!(ErrorCode::ReceiptRequired));
+        }
+        check_payouts_unlocked(&ctx.accounts.general_account, application)?;
+
+        let status = application.settlement_status(&ctx.accounts.job_account);
+        let (principal, reward, forfeited) =
+            payout_for(&status, &ctx.accounts.job_account, candidate_parameters)?;
+
+        let authority_key = ctx.accounts.authority.key();
+
+        let bump_vector = candidate_parameters.bump.to_le_bytes();
+        let inner = vec![
+            CANDIDATE_SEED,
+            application_id.as_bytes()[..18].as_ref(),
+            application_id.as_bytes()[18..].as_ref(),
+            authority_key.as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let outer = vec![inner.as_slice()];
+
+        let escrow = EscrowPayout {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            token_mint: ctx.accounts.token_mint.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+            escrow_wallet_state: ctx.accounts.escrow_wallet_state.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+            instruction: ctx.accounts.instruction.to_account_info(),
+            job_program: ctx.accounts.job_program.to_account_info(),
+        };
+        // the referrer's share comes out of the staker's reward
+        let reward = reward
+            - pay_referral(
+                &escrow,
+                &ctx.accounts.general_account,
+                candidate_parameters,
+                &ctx.accounts.referrer_account,
+                &ctx.accounts.referral_vault,
+                &job_ad_id,
+                reward,
+                ctx.program_id,
+            )?;
+
+        if candidate_parameters.principal_recipient == authority_key {
+            escrow.pay(
+                ctx.accounts.wallet_to_deposit_to.to_account_info(),
+                None,
+                outer.as_slice(),
+                &job_ad_id,
+                principal + reward,
+            )?;
+        } else {
+            // delegated stake: the principal goes back to whoever funded it
+            escrow.pay(
+                ctx.accounts.principal_wallet.to_account_info(),
+                Some(candidate_parameters.principal_recipient),
+                outer.as_slice(),
+                &job_ad_id,
+                principal,
+            )?;
+            escrow.pay(
+                ctx.accounts.wallet_to_deposit_to.to_account_info(),
+                None,
+                outer.as_slice(),
+                &job_ad_id,
+                reward,
+            )?;
+        }
+        escrow.forfeit(&job_ad_id, forfeited)?;
+
+        emit!(PositionsSettled {
+            candidate: candidate_parameters.key(),
+            authority: candidate_parameters.authority,
+            position_count: candidate_parameters.position_count,
+            status: status.clone(),
+            amount: principal + reward,
+        });
+
+        let profile = &mut ctx.accounts.staker_profile;
+        profile.initialize_if_new(authority_key, *ctx.bumps.get("staker_profile").unwrap());
+        // the forfeited principal isn't outstanding anymore either
+        profile.record_payout(principal + forfeited, reward);
+        profile.record_settlement(
+            &status,
+            candidate_parameters.open_position_count(),
+            candidate_parameters.staked_amount,
+            ctx.accounts.general_account.min_pick_amount,
+        );
+
+        if application.status.is_open() {
+            release_application_stake(
+                ctx.accounts.application_program.to_account_info(),
+                ctx.accounts.application_account.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                ctx.accounts.instruction.to_account_info(),
+                &application_id,
+                candidate_parameters.staked_amount,
+                candidate_parameters.reward_amount,
+            )?;
+        }
+
+        ctx.accounts.base_account.reset_after_unstake();
+
+        Ok(())
+    }
+
+    /// Permissionless crank that pays out every position passed through the remaining accounts
+    /// once the application is settled. Each position comes as `(candidate, staker profile,
+    /// destination)`, followed by the principal destination when the principal goes to another
+    /// wallet and by `(referrer account, referral vault)` when the staker was referred.
+    /// Positions already paid out or backed by receipts are skipped.
+    pub fn settle_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleBatch<'info>>,
+        application_id: String,
+        job_ad_id: String,
+    ) -> Result<()> {
+        check_payouts_unlocked(
+            &ctx.accounts.general_account,
+            &ctx.accounts.application_account,
+        )?;
+
+        let status = ctx
+            .accounts
+            .application_account
+            .settlement_status(&ctx.accounts.job_account);
+        let token_mint = ctx.accounts.token_mint.key();
+
+        let escrow = EscrowPayout {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            token_mint: ctx.accounts.token_mint.to_account_info(),
+            authority: ctx.accounts.cranker.to_account_info(),
+            escrow_wallet_state: ctx.accounts.escrow_wallet_state.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+            instruction: ctx.accounts.instruction.to_account_info(),
+            job_program: ctx.accounts.job_program.to_account_info(),
+        };
 
-        match application.status {
-            JobStatus::Pending => {
-                msg!("It is locked, u wont get anything now");
-                return Err(error!(ErrorCode::StatusPending));
+        let mut settled: u64 = 0;
+        let mut remaining_accounts = ctx.remaining_accounts.iter();
+        while let Some(candidate_info) = remaining_accounts.next() {
+            let mut candidate_parameters = Account::<CandidateParameter>::try_from(candidate_info)?;
+            let profile_info = remaining_accounts
+                .next()
+                .ok_or_else(|| ErrorCode::MissingSettlementAccounts)?;
+            let destination = next_destination(
+                &mut remaining_accounts,
+                candidate_parameters.authority,
+                token_mint,
+            )?;
+            let principal_destination =
+                if candidate_parameters.principal_recipient != candidate_parameters.authority {
+                    Some(next_destination(
+                        &mut remaining_accounts,
+                        candidate_parameters.principal_recipient,
+                        token_mint,
+                    )?)
+                } else {
+                    None
+                };
+            let referral_accounts = if candidate_parameters.referrer != Pubkey::default() {
+                let referrer_info = remaining_accounts
+                    .next()
+                    .ok_or_else(|| ErrorCode::MissingSettlementAccounts)?;
+                let referral_vault_info = remaining_accounts
+                    .next()
+                    .ok_or_else(|| ErrorCode::MissingSettlementAccounts)?;
+                Some((referrer_info, referral_vault_info))
+            } else {
+                None
+            };
+
+            if (candidate_parameters.staked_amount == 0 && candidate_parameters.reward_amount == 0)
+                || candidate_parameters.receipt_mint != Pubkey::default()
+            {
+                continue;
             }
-            JobStatus::SelectedButCantWithdraw => {
-                msg!("You are selected but u need to wait before we can transfer");
-                return Err(error!(ErrorCode::SelectedButCantTransfer));
+
+            let bump_vector = candidate_parameters.bump.to_le_bytes();
+            let inner = vec![
+                CANDIDATE_SEED,
+                application_id.as_bytes()[..18].as_ref(),
+                application_id.as_bytes()[18..].as_ref(),
+                candidate_parameters.authority.as_ref(),
+                bump_vector.as_ref(),
+            ];
+            let expected_key = Pubkey::create_program_address(&inner, ctx.program_id)
+                .map_err(|_| error!(ErrorCode::InvalidCandidateAccount))?;
+            if expected_key != candidate_info.key() {
+                return Err(error!(ErrorCode::InvalidCandidateAccount));
             }
-            JobStatus::Selected => {
-                msg!("you are selected");
-                let authority_key = ctx.accounts.authority.key();
-
-                let bump_vector = base_bump.to_le_bytes();
-                let inner = vec![
-                    CANDIDATE_SEED,
-                    application_id.as_bytes()[..18].as_ref(),
-                    application_id.as_bytes()[18..].as_ref(),
-                    authority_key.as_ref(),
-                    bump_vector.as_ref(),
-                ];
-                let outer = vec![inner.as_slice()];
-
-                let cpi_accounts = UnstakeToken {
-                    job_account: ctx.accounts.job_account.to_account_info(),
-                    token_mint: ctx.accounts.token_mint.to_account_info(),
-                    authority: ctx.accounts.authority.to_account_info(),
-                    escrow_wallet_state: ctx.accounts.escrow_wallet_state.to_account_info(),
-                    wallet_to_deposit_to: ctx.accounts.wallet_to_deposit_to.to_account_info(),
-                    system_program: ctx.accounts.system_program.to_account_info(),
-                    token_program: ctx.accounts.token_program.to_account_info(),
-                    rent: ctx.accounts.rent.to_account_info(),
-                    instructions: ctx.accounts.instruction.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.job_program.to_account_info();
-                let cpi_ctx =
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, outer.as_slice());
-                job::cpi::unstake(
-                    cpi_ctx,
-                    job_ad_id,
-                    job_bump,
-                    wallet_bump,
-                    candidate_parameters.reward_amount,
-                )?;
 
-                candidate_parameters.reset_after_unstake();
+            let (principal, reward, forfeited) =
+                payout_for(&status, &ctx.accounts.job_account, &candidate_parameters)?;
+
+            let outer = vec![inner.as_slice()];
+            let reward = match referral_accounts {
+                Some((referrer_info, referral_vault_info)) => {
+                    reward
+                        - pay_referral(
+                            &escrow,
+                            &ctx.accounts.general_account,
+                            &candidate_parameters,
+                            referrer_info,
+                            referral_vault_info,
+                            &job_ad_id,
+                            reward,
+                            ctx.program_id,
+                        )?
+                }
+                None => reward,
+            };
+
+            match principal_destination {
+                Some(principal_destination) => {
+                    escrow.pay(
+                        principal_destination,
+                        Some(candidate_parameters.principal_recipient),
+                        outer.as_slice(),
+                        &job_ad_id,
+                        principal,
+                    )?;
+                    escrow.pay(
+                        destination,
+                        Some(candidate_parameters.authority),
+                        outer.as_slice(),
+                        &job_ad_id,
+                        reward,
+                    )?;
+                }
+                None => {
+                    escrow.pay(
+                        destination,
+                        Some(candidate_parameters.authority),
+                        outer.as_slice(),
+                        &job_ad_id,
+                        principal + reward,
+                    )?;
+                }
             }
-            JobStatus::Rejected => {
-                msg!("you are rejected");
-                msg!("{}", candidate_parameters.staked_amount);
-                let authority_key = ctx.accounts.authority.key();
-
-                let bump_vector = base_bump.to_le_bytes();
-                let inner = vec![
-                    CANDIDATE_SEED,
-                    application_id.as_bytes()[..18].as_ref(),
-                    application_id.as_bytes()[18..].as_ref(),
-                    authority_key.as_ref(),
-                    bump_vector.as_ref(),
-                ];
-                let outer = vec![inner.as_slice()];
-
-                let cpi_accounts = UnstakeToken {
-                    job_account: ctx.accounts.job_account.to_account_info(),
-                    token_mint: ctx.accounts.token_mint.to_account_info(),
-                    authority: ctx.accounts.authority.to_account_info(),
-                    escrow_wallet_state: ctx.accounts.escrow_wallet_state.to_account_info(),
-                    wallet_to_deposit_to: ctx.accounts.wallet_to_deposit_to.to_account_info(),
-                    system_program: ctx.accounts.system_program.to_account_info(),
-                    token_program: ctx.accounts.token_program.to_account_info(),
-                    rent: ctx.accounts.rent.to_account_info(),
-                    instructions: ctx.accounts.instruction.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.job_program.to_account_info();
-                let cpi_ctx =
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, outer.as_slice());
-                job::cpi::unstake(
-                    cpi_ctx,
-                    job_ad_id,
-                    job_bump,
-                    wallet_bump,
+            escrow.forfeit(&job_ad_id, forfeited)?;
+
+            emit!(PositionsSettled {
+                candidate: candidate_info.key(),
+                authority: candidate_parameters.authority,
+                position_count: candidate_parameters.position_count,
+                status: status.clone(),
+                amount: principal + reward,
+            });
+
+            if let Some(mut profile) = load_staker_profile(
+                profile_info,
+                &candidate_parameters.authority,
+                ctx.program_id,
+            )? {
+                profile.record_payout(principal + forfeited, reward);
+                profile.record_settlement(
+                    &status,
+                    candidate_parameters.open_position_count(),
+                    candidate_parameters.staked_amount,
+                    ctx.accounts.general_account.min_pick_amount,
+                );
+                profile.exit(ctx.program_id)?;
+            }
+
+            if ctx.accounts.application_account.status.is_open() {
+                release_application_stake(
+                    ctx.accounts.application_program.to_account_info(),
+                    ctx.accounts.application_account.to_account_info(),
+                    ctx.accounts.cranker.to_account_info(),
+                    ctx.accounts.instruction.to_account_info(),
+                    &application_id,
                     candidate_parameters.staked_amount,
+                    candidate_parameters.reward_amount,
                 )?;
-                candidate_parameters.reset_after_unstake();
             }
+
+            candidate_parameters.reset_after_unstake();
+            candidate_parameters.exit(ctx.program_id)?;
+            settled += 1;
+        }
+
+        // the escrow holds the stakers' principal, so the crank is only paid by the treasury
+        // and as far as it's funded
+        let crank_reward = ctx
+            .accounts
+            .general_account
+            .crank_reward
+            .checked_mul(settled)
+            .ok_or_else(|| ErrorCode::RewardAmountOverflow)?
+            .min(ctx.accounts.crank_treasury.amount);
+        if crank_reward > 0 {
+            let bump_vector = ctx
+                .bumps
+                .get("crank_treasury_authority")
+                .unwrap()
+                .to_le_bytes();
+            let inner = vec![CRANK_TREASURY_SEED, bump_vector.as_ref()];
+            let outer = vec![inner.as_slice()];
+
+            let transfer_instruction = Transfer {
+                from: ctx.accounts.crank_treasury.to_account_info(),
+                to: ctx.accounts.cranker_wallet.to_account_info(),
+                authority: ctx.accounts.crank_treasury_authority.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_instruction,
+                outer.as_slice(),
+            );
+            anchor_spl::token::transfer(cpi_ctx, crank_reward)?;
         }
 
+        msg!("settled {} positions", settled);
+
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-#[instruction(job_ad_id: String, application_id: String, job_bump: u8)]
-pub struct Initialize<'info> {
-    #[account(init, payer = authority, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), authority.key().as_ref()], bump, space = 8 + 8 + 32 + 8 )]
-    pub base_account: Account<'info, CandidateParameter>,
-    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_bump, seeds::program = job_program.key())]
-    pub job_account: Box<Account<'info, JobStakingParameter>>,
-    #[account(
-        init_if_needed, payer = authority,
-        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
-        bump,
-        token::mint = token_mint,
-        token::authority = job_account,
-    )]
-    pub escrow_wallet_state: Account<'info, TokenAccount>,
-    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
-    pub token_mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub job_program: Program<'info, Job>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    /// Pays out the share of the position backed by `amount` receipts to whoever burns them,
+    /// regardless of who staked in the first place.
+    pub fn redeem_receipt(
+        ctx: Context<RedeemReceipt>,
+        application_id: String,
+        job_ad_id: String,
+        amount: u64,
+    ) -> Result<()> {
+        let candidate_parameters = &ctx.accounts.base_account;
 
-#[derive(Accounts)]
-#[instruction(job_ad_id: String, application_id: String, base_bump: u8, general_bump: u8, application_bump: u8, job_bump: u8, wallet_bump: u8)]
-pub struct Stake<'info> {
-    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref() ,authority.key().as_ref()],bump = base_bump)]
-    pub base_account: Box<Account<'info, CandidateParameter>>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        if amount == 0 || amount > candidate_parameters.staked_amount {
+            return Err(error!(ErrorCode::InvalidReceiptAmount));
+        }
+        check_payouts_unlocked(
+            &ctx.accounts.general_account,
+            &ctx.accounts.application_account,
+        )?;
 
-    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
-    pub token_mint: Account<'info, Mint>,
+        let reward_share = (candidate_parameters.reward_amount as u128 * amount as u128
+            / candidate_parameters.staked_amount as u128) as u64;
 
-    #[account(mut, seeds = [GENERAL_SEED], bump = general_bump, seeds::program = general_program.key())]
-    pub general_account: Account<'info, GeneralParameter>,
-    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_bump, seeds::program = job_program.key())]
-    pub job_account: Box<Account<'info, JobStakingParameter>>,
-    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_bump, seeds::program = application_program.key())]
-    pub application_account: Account<'info, ApplicationParameter>,
+        let status = ctx
+            .accounts
+            .application_account
+            .settlement_status(&ctx.accounts.job_account);
+        check_withdraw_allowed(&status, &ctx.accounts.job_account)?;
+        let haircut_share = candidate_parameters.haircut_share(amount);
+        let forfeited = rejection_haircut(&status, candidate_parameters, amount);
+        let payout = if status.is_hire() {
+            reward_share
+        } else {
+            amount - forfeited
+        };
 
-    pub general_program: Program<'info, General>,
-    pub application_program: Program<'info, Application>,
-    pub job_program: Program<'info, Job>,
+        let burn_instruction = Burn {
+            mint: ctx.accounts.receipt_mint.to_account_info(),
+            to: ctx.accounts.receipt_token_account.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            burn_instruction,
+        );
+        anchor_spl::token::burn(cpi_ctx, amount)?;
 
-    #[account(
-        mut,
-        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
-        bump = wallet_bump,
-        token::mint = token_mint,
-        token::authority = job_account,
-    )]
-    pub escrow_wallet_state: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint=wallet_to_withdraw_from.owner == authority.key(),
-        constraint=wallet_to_withdraw_from.mint == token_mint.key()
-    )]
-    pub wallet_to_withdraw_from: Account<'info, TokenAccount>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-    ///CHECK:   
-    pub instruction: AccountInfo<'info>,
-}
+        let staker_key = candidate_parameters.authority;
+        let bump_vector = candidate_parameters.bump.to_le_bytes();
+        let inner = vec![
+            CANDIDATE_SEED,
+            application_id.as_bytes()[..18].as_ref(),
+            application_id.as_bytes()[18..].as_ref(),
+            staker_key.as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let outer = vec![inner.as_slice()];
 
-#[derive(Accounts)]
-#[instruction(base_bump: u8, application_bump: u8, wallet_bump: u8, application_id: String, job_ad_id: String, job_bump: u8)]
-pub struct Unstake<'info> {
-    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref() ,authority.key().as_ref()],bump = base_bump)]
-    pub base_account: Account<'info, CandidateParameter>,
-    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_bump, seeds::program = job_program.key())]
-    pub job_account: Box<Account<'info, JobStakingParameter>>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        let escrow = EscrowPayout {
+            job_account: ctx.accounts.job_account.to_account_info(),
+            token_mint: ctx.accounts.token_mint.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+            escrow_wallet_state: ctx.accounts.escrow_wallet_state.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+            instruction: ctx.accounts.instruction.to_account_info(),
+            job_program: ctx.accounts.job_program.to_account_info(),
+        };
+        // the referrer's share comes out of the reward of the redeemed receipts
+        let payout = payout
+            - pay_referral(
+                &escrow,
+                &ctx.accounts.general_account,
+                candidate_parameters,
+                &ctx.accounts.referrer_account,
+                &ctx.accounts.referral_vault,
+                &job_ad_id,
+                payout.saturating_sub(amount),
+                ctx.program_id,
+            )?;
+        escrow.pay(
+            ctx.accounts.wallet_to_deposit_to.to_account_info(),
+            None,
+            outer.as_slice(),
+            &job_ad_id,
+            payout,
+        )?;
+        escrow.forfeit(&job_ad_id, forfeited)?;
 
-    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
-    pub token_mint: Account<'info, Mint>,
+        // the pick stays with the staker who made it, otherwise buying the receipts of a winning
+        // position would buy its reputation; the reward only counts when the staker redeems it
+        let reward = if ctx.accounts.authority.key() == staker_key {
+            payout.saturating_sub(amount)
+        } else {
+            0
+        };
+        let profile = &mut ctx.accounts.staker_profile;
+        profile.initialize_if_new(staker_key, *ctx.bumps.get("staker_profile").unwrap());
+        profile.record_payout(amount, reward);
 
-    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_bump, seeds::program = application_program.key())]
-    pub application_account: Account<'info, ApplicationParameter>,
+        if ctx.accounts.application_account.status.is_open() {
+            release_application_stake(
+                ctx.accounts.application_program.to_account_info(),
+                ctx.accounts.application_account.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                ctx.accounts.instruction.to_account_info(),
+                &application_id,
+                amount,
+                reward_share,
+            )?;
+        }
 
-    pub application_program: Program<'info, Application>,
+        let min_pick_amount = ctx.accounts.general_account.min_pick_amount;
+        let candidate_parameters = &mut ctx.accounts.base_account;
+        // the pick is recorded at the first redemption, while the whole stake is still known
+        if candidate_parameters.open_position_count() > 0 {
+            profile.record_settlement(
+                &status,
+                candidate_parameters.open_position_count(),
+                candidate_parameters.staked_amount,
+                min_pick_amount,
+            );
+            candidate_parameters.settled_position_count = candidate_parameters.position_count;
+        }
+        candidate_parameters.staked_amount -= amount;
+        candidate_parameters.reward_amount -= reward_share;
+        candidate_parameters.haircut_amount -= haircut_share;
+        if candidate_parameters.staked_amount == 0 {
+            candidate_parameters.reset_after_unstake();
+        }
+
+        Ok(())
+    }
+
+    /// Lets a staker of the application dispute its final status during the dispute window by
+    /// bonding the challenge bond. Payouts of the application are frozen until the resolver
+    /// decides on it.
+    pub fn challenge_status(
+        ctx: Context<ChallengeStatus>,
+        _job_ad_id: String,
+        application_id: String,
+    ) -> Result<()> {
+        let application = &ctx.accounts.application_account;
+        let general = &ctx.accounts.general_account;
+
+        if ctx.accounts.base_account.staked_amount == 0 {
+            return Err(error!(ErrorCode::NotAStaker));
+        }
+        if application.status.is_open() {
+            return Err(error!(ErrorCode::StatusNotFinal));
+        }
+        let now = Clock::get()?.unix_timestamp;
+        if now >= dispute_deadline(general, application) {
+            return Err(error!(ErrorCode::DisputeWindowClosed));
+        }
+
+        let challenge = &mut ctx.accounts.challenge;
+        challenge.challenger = ctx.accounts.authority.key();
+        challenge.application = application.key();
+        challenge.bond = general.challenge_bond;
+        challenge.challenged_status = application.status.clone();
+        challenge.created_at = now;
+        challenge.version = CHALLENGE_VERSION;
+        challenge.reserved = [0; RECORD_RESERVED];
+
+        if challenge.bond > 0 {
+            let transfer_instruction = Transfer {
+                from: ctx.accounts.wallet_to_withdraw_from.to_account_info(),
+                to: ctx.accounts.challenge_vault.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_instruction,
+            );
+            anchor_spl::token::transfer(cpi_ctx, challenge.bond)?;
+        }
+
+        let cpi_accounts = SetChallenged {
+            base_account: ctx.accounts.application_account.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+            instruction: ctx.accounts.instruction.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.application_program.to_account_info(),
+            cpi_accounts,
+        );
+        application::cpi::set_challenged(cpi_ctx, application_id)?;
+
+        Ok(())
+    }
+
+    /// Once the resolver decided, returns the bond to the challenger if the status got reverted
+    /// or slashes it into the job's reward pool if it was upheld.
+    pub fn settle_challenge(
+        ctx: Context<SettleChallenge>,
+        _application_id: String,
+        _job_ad_id: String,
+    ) -> Result<()> {
+        let application_key = ctx.accounts.application_account.key();
+        let destination = match ctx.accounts.application_account.challenge {
+            ChallengeState::Reverted => ctx.accounts.challenger_wallet.to_account_info(),
+            ChallengeState::Upheld => ctx.accounts.escrow_wallet_state.to_account_info(),
+            _ => return Err(error!(ErrorCode::ChallengeNotResolved)),
+        };
+
+        let (_, challenge_bump) = Pubkey::find_program_address(
+            &[CHALLENGE_SEED, application_key.as_ref()],
+            ctx.program_id,
+        );
+        let bump_vector = challenge_bump.to_le_bytes();
+        let inner = vec![
+            CHALLENGE_SEED,
+            application_key.as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let outer = vec![inner.as_slice()];
+
+        let bond = ctx.accounts.challenge_vault.amount;
+        if bond > 0 {
+            let transfer_instruction = Transfer {
+                from: ctx.accounts.challenge_vault.to_account_info(),
+                to: destination,
+                authority: ctx.accounts.challenge.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_instruction,
+                outer.as_slice(),
+            );
+            anchor_spl::token::transfer(cpi_ctx, bond)?;
+        }
+
+        let close_instruction = CloseAccount {
+            account: ctx.accounts.challenge_vault.to_account_info(),
+            destination: ctx.accounts.challenger.to_account_info(),
+            authority: ctx.accounts.challenge.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            close_instruction,
+            outer.as_slice(),
+        );
+        anchor_spl::token::close_account(cpi_ctx)?;
+
+        emit!(ChallengeSettled {
+            application: application_key,
+            challenger: ctx.accounts.challenge.challenger,
+            bond,
+            upheld: ctx.accounts.application_account.challenge == ChallengeState::Upheld,
+        });
+
+        Ok(())
+    }
+
+    /// Creates the treasury, one per mint, that pays the crank rewards of `settle_batch`. It's
+    /// funded separately with plain token transfers, never out of a job escrow.
+    pub fn initialize_crank_treasury(_ctx: Context<InitializeCrankTreasury>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Opens the account and the vault the referral rewards of the signer accrue to. Until a
+    /// referrer registers, the stakers they referred keep their share.
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let referrer_account = &mut ctx.accounts.referrer_account;
+
+        referrer_account.referrer = ctx.accounts.referrer.key();
+        referrer_account.accrued = 0;
+        referrer_account.claimed = 0;
+        referrer_account.version = REFERRER_VERSION;
+        referrer_account.bump = *ctx.bumps.get("referrer_account").unwrap();
+
+        Ok(())
+    }
+
+    /// Pays out everything that accrued in the referral vault since the last claim.
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let amount = ctx.accounts.referral_vault.amount;
+        if amount == 0 {
+            return Err(error!(ErrorCode::NothingToClaim));
+        }
+
+        let referrer_key = ctx.accounts.referrer.key();
+        let bump_vector = ctx.accounts.referrer_account.bump.to_le_bytes();
+        let inner = vec![REFERRER_SEED, referrer_key.as_ref(), bump_vector.as_ref()];
+        let outer = vec![inner.as_slice()];
+
+        let transfer_instruction = Transfer {
+            from: ctx.accounts.referral_vault.to_account_info(),
+            to: ctx.accounts.wallet_to_deposit_to.to_account_info(),
+            authority: ctx.accounts.referrer_account.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            outer.as_slice(),
+        );
+        anchor_spl::token::transfer(cpi_ctx, amount)?;
+
+        let referrer_account = &mut ctx.accounts.referrer_account;
+        referrer_account.claimed = referrer_account
+            .claimed
+            .checked_add(amount)
+            .ok_or_else(|| ErrorCode::RewardAmountOverflow)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, staker: Pubkey)]
+pub struct MigrateCandidate<'info> {
+    /// CHECK: only deserialized once it has the size of the current layout, which is also
+    /// when its canonical bump gets stored
+    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), staker.as_ref()], bump, owner = crate::ID)]
+    pub base_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(application: Pubkey)]
+pub struct MigrateCommitQueue<'info> {
+    /// CHECK: only deserialized once it has the size of the current layout
+    #[account(mut, seeds = [COMMIT_QUEUE_SEED, application.as_ref()], bump, owner = crate::ID)]
+    pub commit_queue: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String)]
+pub struct Initialize<'info> {
+    #[account(init, payer = authority, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), authority.key().as_ref()], bump, space = CANDIDATE_SPACE)]
+    pub base_account: Account<'info, CandidateParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(
+        init_if_needed, payer = authority,
+        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = job_account,
+    )]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: the staker approval or credential token account the gating of the job asks for,
+    /// ignored by open jobs
+    pub gate: UncheckedAccount<'info>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String)]
+pub struct EnableReceipts<'info> {
+    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), authority.key().as_ref()], bump = base_account.bump)]
+    pub base_account: Box<Account<'info, CandidateParameter>>,
+    #[account(
+        init, payer = authority,
+        seeds = [RECEIPT_SEED, base_account.key().as_ref()],
+        bump,
+        mint::decimals = token_mint.decimals,
+        mint::authority = base_account,
+    )]
+    pub receipt_mint: Account<'info, Mint>,
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String)]
+pub struct Stake<'info> {
+    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref() ,authority.key().as_ref()],bump = base_account.bump)]
+    pub base_account: Box<Account<'info, CandidateParameter>>,
+    #[account(init, payer = authority, seeds = [POSITION_SEED, base_account.key().as_ref(), base_account.position_count.to_le_bytes().as_ref()], bump, space = POSITION_SPACE)]
+    pub position_account: Box<Account<'info, StakePosition>>,
+    #[account(init_if_needed, payer = authority, seeds = [PROFILE_SEED, authority.key().as_ref()], bump, space = PROFILE_SPACE)]
+    pub staker_profile: Box<Account<'info, StakerProfile>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: the staker approval or credential token account the gating of the job asks for,
+    /// ignored by open jobs
+    pub gate: UncheckedAccount<'info>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(mut, seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+
+    pub general_program: Program<'info, General>,
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+
+    #[account(
+        mut,
+        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = job_account,
+    )]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint=wallet_to_withdraw_from.owner == authority.key(),
+        constraint=wallet_to_withdraw_from.mint == token_mint.key()
+    )]
+    pub wallet_to_withdraw_from: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+    ///CHECK:   
+    pub instruction: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String, beneficiary: Pubkey)]
+pub struct StakeFor<'info> {
+    #[account(init_if_needed, payer = payer, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), beneficiary.as_ref()], bump, space = CANDIDATE_SPACE)]
+    pub base_account: Box<Account<'info, CandidateParameter>>,
+    #[account(init, payer = payer, seeds = [POSITION_SEED, base_account.key().as_ref(), base_account.position_count.to_le_bytes().as_ref()], bump, space = POSITION_SPACE)]
+    pub position_account: Box<Account<'info, StakePosition>>,
+    #[account(init_if_needed, payer = payer, seeds = [PROFILE_SEED, beneficiary.as_ref()], bump, space = PROFILE_SPACE)]
+    pub staker_profile: Box<Account<'info, StakerProfile>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: the beneficiary, who has to sign to send the principal of their position to the payer
+    #[account(address = beneficiary)]
+    pub beneficiary_wallet: UncheckedAccount<'info>,
+    /// CHECK: the staker approval or credential token account of the beneficiary the gating of
+    /// the job asks for, ignored by open jobs
+    pub gate: UncheckedAccount<'info>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(mut, seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+
+    pub general_program: Program<'info, General>,
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+
+    #[account(
+        mut,
+        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = job_account,
+    )]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint=wallet_to_withdraw_from.owner == payer.key(),
+        constraint=wallet_to_withdraw_from.mint == token_mint.key()
+    )]
+    pub wallet_to_withdraw_from: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+    ///CHECK:
+    pub instruction: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String)]
+pub struct CommitStake<'info> {
+    #[account(
+        init_if_needed, payer = authority,
+        seeds = [COMMIT_QUEUE_SEED, application_account.key().as_ref()],
+        bump,
+        space = COMMIT_QUEUE_SPACE
+    )]
+    pub commit_queue: Account<'info, CommitQueue>,
+    #[account(
+        init, payer = authority,
+        seeds = [COMMITMENT_SEED, application_account.key().as_ref(), authority.key().as_ref()],
+        bump,
+        space = COMMITMENT_SPACE
+    )]
+    pub commitment: Account<'info, StakeCommitment>,
+    #[account(seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), authority.key().as_ref()], bump = base_account.bump)]
+    pub base_account: Box<Account<'info, CandidateParameter>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealStake<'info> {
+    pub stake: Stake<'info>,
+    #[account(mut, seeds = [COMMIT_QUEUE_SEED, stake.application_account.key().as_ref()], bump)]
+    pub commit_queue: Account<'info, CommitQueue>,
+    #[account(mut, seeds = [COMMITMENT_SEED, stake.application_account.key().as_ref(), stake.authority.key().as_ref()], bump)]
+    pub commitment: Account<'info, StakeCommitment>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String)]
+pub struct ExpireCommitment<'info> {
+    #[account(mut, seeds = [COMMIT_QUEUE_SEED, application_account.key().as_ref()], bump)]
+    pub commit_queue: Account<'info, CommitQueue>,
+    #[account(mut, seeds = [COMMITMENT_SEED, application_account.key().as_ref(), staker.key().as_ref()], bump)]
+    pub commitment: Account<'info, StakeCommitment>,
+    ///CHECK: only receives the rent of the expired commitment
+    #[account(mut, address = commitment.authority)]
+    pub staker: UncheckedAccount<'info>,
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String, staker: Pubkey)]
+pub struct QuoteReward<'info> {
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Box<Account<'info, GeneralParameter>>,
+    /// CHECK: the profile of the wallet the quote is for, it's only read when it exists
+    #[account(seeds = [PROFILE_SEED, staker.as_ref()], bump)]
+    pub staker_profile: UncheckedAccount<'info>,
+    pub general_program: Program<'info, General>,
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_ad_id: String)]
+pub struct Unstake<'info> {
+    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref() ,authority.key().as_ref()],bump = base_account.bump)]
+    pub base_account: Account<'info, CandidateParameter>,
+    /// Created on the way out for stakers who staked before profiles existed.
+    #[account(init_if_needed, payer = authority, seeds = [PROFILE_SEED, authority.key().as_ref()], bump, space = PROFILE_SPACE)]
+    pub staker_profile: Box<Account<'info, StakerProfile>>,
+    /// CHECK: the account of the referrer of the staker, only checked when the payout earns them
+    /// a share
+    #[account(mut)]
+    pub referrer_account: UncheckedAccount<'info>,
+    /// CHECK: checked along with the referrer account
+    #[account(mut)]
+    pub referral_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+
+    pub application_program: Program<'info, Application>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Box<Account<'info, GeneralParameter>>,
+    pub general_program: Program<'info, General>,
+    #[account(
+        mut,
+        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = job_account,
+    )]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint=wallet_to_deposit_to.owner == authority.key(),
+        constraint=wallet_to_deposit_to.mint == token_mint.key()
+    )]
+    pub wallet_to_deposit_to: Account<'info, TokenAccount>,
+    /// Receives the principal when it doesn't go to the staker, it's the same wallet otherwise.
+    #[account(
+        mut,
+        constraint=principal_wallet.owner == base_account.principal_recipient,
+        constraint=principal_wallet.mint == token_mint.key()
+    )]
+    pub principal_wallet: Account<'info, TokenAccount>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+    ///CHECK:
+    pub instruction: AccountInfo<'info>,
+}
+
+/// Builds the reward calculator with the job's configuration and the staker's reputation
+/// applied. Every path that prices a stake (`stake`, `quote_reward`) must go through here, so
+/// filled jobs aren't quoted either.
+fn reward_calculator<'a>(
+    job: &'a JobStakingParameter,
+    application: &'a ApplicationParameter,
+    reputation_boost_bps: u16,
+) -> Result<RewardCalculator<'a>> {
+    if job.is_filled {
+        return Err(error!(ErrorCode::JobFilled));
+    }
+    let now = Clock::get()?.unix_timestamp;
+
+    Ok(RewardCalculator::new(application)
+        .with_time_decay(job.reward_half_life, now)
+        .with_status_policy(application.status.policy(job))
+        .with_reputation_boost(reputation_boost_bps))
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_ad_id: String)]
+pub struct SettleBatch<'info> {
+    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    #[account(
+        mut,
+        constraint=cranker_wallet.owner == cranker.key(),
+        constraint=cranker_wallet.mint == crank_treasury.mint
+    )]
+    pub cranker_wallet: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [CRANK_TREASURY_SEED, crank_treasury.mint.as_ref()], bump)]
+    pub crank_treasury: Box<Account<'info, TokenAccount>>,
+    /// CHECK: only signs for the crank treasury
+    #[account(seeds = [CRANK_TREASURY_SEED], bump)]
+    pub crank_treasury_authority: UncheckedAccount<'info>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Account<'info, GeneralParameter>,
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+
+    pub general_program: Program<'info, General>,
+    pub application_program: Program<'info, Application>,
     #[account(
         mut,
         seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
-        bump = wallet_bump,
+        bump,
+        token::mint = token_mint,
+        token::authority = job_account,
+    )]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+    ///CHECK:
+    pub instruction: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_ad_id: String)]
+pub struct RedeemReceipt<'info> {
+    #[account(mut, seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), base_account.authority.as_ref()], bump = base_account.bump)]
+    pub base_account: Box<Account<'info, CandidateParameter>>,
+    #[account(mut, seeds = [RECEIPT_SEED, base_account.key().as_ref()], bump)]
+    pub receipt_mint: Account<'info, Mint>,
+    /// The profile of the staker, not of whoever holds the receipts.
+    #[account(init_if_needed, payer = authority, seeds = [PROFILE_SEED, base_account.authority.as_ref()], bump, space = PROFILE_SPACE)]
+    pub staker_profile: Box<Account<'info, StakerProfile>>,
+    /// CHECK: the account of the referrer of the staker, only checked when the payout earns them
+    /// a share
+    #[account(mut)]
+    pub referrer_account: UncheckedAccount<'info>,
+    /// CHECK: checked along with the referrer account
+    #[account(mut)]
+    pub referral_vault: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = receipt_token_account.mint == receipt_mint.key() @ ErrorCode::InvalidReceiptMint,
+        constraint = receipt_token_account.owner == authority.key()
+    )]
+    pub receipt_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Account<'info, ApplicationParameter>,
+
+    pub application_program: Program<'info, Application>,
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Box<Account<'info, GeneralParameter>>,
+    pub general_program: Program<'info, General>,
+    #[account(
+        mut,
+        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
+        bump,
         token::mint = token_mint,
         token::authority = job_account,
     )]
@@ -335,11 +1529,810 @@ pub struct Unstake<'info> {
     pub instruction: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, application_id: String)]
+pub struct ChallengeStatus<'info> {
+    #[account(seeds = [CANDIDATE_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref(), authority.key().as_ref()], bump = base_account.bump)]
+    pub base_account: Box<Account<'info, CandidateParameter>>,
+    #[account(init, payer = authority, seeds = [CHALLENGE_SEED, application_account.key().as_ref()], bump, space = CHALLENGE_SPACE)]
+    pub challenge: Box<Account<'info, Challenge>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [CHALLENGE_VAULT_SEED, application_account.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = challenge,
+    )]
+    pub challenge_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint=wallet_to_withdraw_from.owner == authority.key(),
+        constraint=wallet_to_withdraw_from.mint == token_mint.key()
+    )]
+    pub wallet_to_withdraw_from: Account<'info, TokenAccount>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Box<Account<'info, GeneralParameter>>,
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut, seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Box<Account<'info, ApplicationParameter>>,
+
+    pub general_program: Program<'info, General>,
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+    ///CHECK:
+    pub instruction: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(application_id: String, job_ad_id: String)]
+pub struct SettleChallenge<'info> {
+    #[account(mut, seeds = [CHALLENGE_SEED, application_account.key().as_ref()], bump, has_one = challenger, close = challenger)]
+    pub challenge: Box<Account<'info, Challenge>>,
+    #[account(mut, seeds = [CHALLENGE_VAULT_SEED, application_account.key().as_ref()], bump)]
+    pub challenge_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: only receives the rent of the challenge accounts
+    #[account(mut)]
+    pub challenger: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint=challenger_wallet.owner == challenger.key(),
+        constraint=challenger_wallet.mint == token_mint.key()
+    )]
+    pub challenger_wallet: Account<'info, TokenAccount>,
+
+    #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(seeds = [JOB_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, seeds::program = job_program.key())]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(seeds = [APPLICATION_SEED, application_id.as_bytes()[..18].as_ref(), application_id.as_bytes()[18..].as_ref()], bump = application_account.bump, seeds::program = application_program.key(), constraint = application_account.job == job_account.key() @ ErrorCode::InvalidJob)]
+    pub application_account: Box<Account<'info, ApplicationParameter>>,
+    #[account(
+        mut,
+        seeds = [WALLET_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = job_account,
+    )]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+
+    pub application_program: Program<'info, Application>,
+    pub job_program: Program<'info, Job>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeCrankTreasury<'info> {
+    #[account(
+        init,
+        payer = payer,
+        seeds = [CRANK_TREASURY_SEED, token_mint.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = crank_treasury_authority,
+    )]
+    pub crank_treasury: Box<Account<'info, TokenAccount>>,
+    /// CHECK: only signs for the crank treasury
+    #[account(seeds = [CRANK_TREASURY_SEED], bump)]
+    pub crank_treasury_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(constraint = token_mint.key() == general_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Box<Account<'info, GeneralParameter>>,
+    pub general_program: Program<'info, General>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(init, payer = referrer, seeds = [REFERRER_SEED, referrer.key().as_ref()], bump, space = REFERRER_SPACE)]
+    pub referrer_account: Box<Account<'info, ReferrerAccount>>,
+    #[account(
+        init,
+        payer = referrer,
+        seeds = [REFERRAL_VAULT_SEED, referrer.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = referrer_account,
+    )]
+    pub referral_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(constraint = token_mint.key() == general_account.mint @ ErrorCode::InvalidToken)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
+    pub general_account: Box<Account<'info, GeneralParameter>>,
+    pub general_program: Program<'info, General>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut, seeds = [REFERRER_SEED, referrer.key().as_ref()], bump = referrer_account.bump, has_one = referrer)]
+    pub referrer_account: Box<Account<'info, ReferrerAccount>>,
+    #[account(mut, seeds = [REFERRAL_VAULT_SEED, referrer.key().as_ref()], bump)]
+    pub referral_vault: Box<Account<'info, TokenAccount>>,
+    pub referrer: Signer<'info>,
+    #[account(
+        mut,
+        constraint=wallet_to_deposit_to.owner == referrer.key(),
+        constraint=wallet_to_deposit_to.mint == referral_vault.mint
+    )]
+    pub wallet_to_deposit_to: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Records a stake of the signer and moves the funds into the escrow. Shared by `stake` and
+/// `reveal_stake`, which only differ in how the stake is allowed to happen. `committed_at` is
+/// when a revealed stake was committed.
+fn process_stake<'info>(
+    accounts: &mut Stake<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    application_id: &str,
+    amount: u64,
+    committed_at: Option<i64>,
+) -> Result<()> {
+    let authority_key = accounts.authority.key();
+
+    check_gating(&accounts.job_account, &accounts.gate, &authority_key)?;
+    check_conflict_of_interest(
+        &accounts.job_account,
+        &accounts.application_account.candidate,
+        &authority_key,
+    )?;
+    accounts
+        .base_account
+        .set_principal_recipient(authority_key)?;
+    let reputation_boost_bps = accounts
+        .staker_profile
+        .reputation_boost_bps(&accounts.general_account.reputation);
+    record_stake(
+        &accounts.job_account,
+        &accounts.application_account,
+        reputation_boost_bps,
+        &mut accounts.base_account,
+        &mut accounts.position_account,
+        amount,
+        committed_at,
+        false,
+    )?;
+    accounts.staker_profile.record_stake(amount)?;
+
+    let bump_vector = accounts.base_account.bump.to_le_bytes();
+    let inner = vec![
+        CANDIDATE_SEED,
+        application_id.as_bytes()[..18].as_ref(),
+        application_id.as_bytes()[18..].as_ref(),
+        authority_key.as_ref(),
+        bump_vector.as_ref(),
+    ];
+    let outer = vec![inner.as_slice()];
+
+    let cpi_accounts = UpdateStakeAmount {
+        base_account: accounts.application_account.to_account_info(),
+        authority: accounts.authority.to_account_info(),
+        instruction: accounts.instruction.to_account_info(),
+    };
+    let cpi_program = accounts.application_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, outer.as_slice());
+    application::cpi::update_stake_amount(
+        cpi_ctx,
+        application_id.to_string(),
+        amount,
+        accounts.base_account.reward_amount,
+    )?;
+
+    // Below is the actual instruction that we are going to send to the Token program.
+    let transfer_instruction = Transfer {
+        from: accounts.wallet_to_withdraw_from.to_account_info(),
+        to: accounts.escrow_wallet_state.to_account_info(),
+        authority: accounts.authority.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        accounts.token_program.to_account_info(),
+        transfer_instruction,
+        outer.as_slice(), //signer PDA
+    );
+
+    anchor_spl::token::transfer(cpi_ctx, amount)?;
+    msg!("token is deposited");
+
+    if accounts.base_account.receipt_mint != Pubkey::default() {
+        mint_receipts(
+            &accounts.base_account,
+            remaining_accounts,
+            accounts.token_program.to_account_info(),
+            outer.as_slice(),
+            amount,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks that the application still has room for `amount`, prices it and records it both in
+/// the aggregated `CandidateParameter` and in a new `StakePosition`. A `sponsored` stake, made
+/// for the staker by someone else without their signature, skips the rate limits of the job and
+/// doesn't count against them, so no one can use them up for the staker.
+#[allow(clippy::too_many_arguments)]
+fn record_stake(
+    job: &JobStakingParameter,
+    application: &ApplicationParameter,
+    reputation_boost_bps: u16,
+    candidate_parameter: &mut Account<CandidateParameter>,
+    position: &mut Account<StakePosition>,
+    amount: u64,
+    committed_at: Option<i64>,
+    sponsored: bool,
+) -> Result<()> {
+    if job.is_filled {
+        return Err(error!(ErrorCode::JobFilled));
+    }
+    if application
+        .staked_amount
+        .checked_add(amount)
+        .ok_or_else(|| ErrorCode::MaxAmountExceeded)?
+        >= application.max_allowed_staked
+    {
+        return Err(error!(ErrorCode::MaxAmountExceeded));
+    }
+
+    let clock = Clock::get()?;
+    // a revealed stake is rate limited as of when it was committed
+    if !sponsored {
+        check_stake_rate(
+            job,
+            candidate_parameter,
+            committed_at.unwrap_or(clock.unix_timestamp),
+        )?;
+    }
+
+    msg!("You can transfer");
+    msg!("Transfer is initiated");
+
+    let reward_calculator = reward_calculator(job, application, reputation_boost_bps)?;
+
+    candidate_parameter.staked_amount = candidate_parameter
+        .staked_amount
+        .checked_add(amount)
+        .ok_or_else(|| ErrorCode::StakeAmountOverflow)?;
+    let breakdown = reward_calculator.calculate_reward_breakdown(amount)?;
+    candidate_parameter.reward_amount = candidate_parameter
+        .reward_amount
+        .checked_add(breakdown.reward_amount)
+        .ok_or_else(|| ErrorCode::RewardAmountOverflow)?;
+    candidate_parameter.tier_one_amount = candidate_parameter
+        .tier_one_amount
+        .checked_add(breakdown.tier_amounts[0])
+        .ok_or_else(|| ErrorCode::StakeAmountOverflow)?;
+    candidate_parameter.haircut_amount = candidate_parameter
+        .haircut_amount
+        .checked_add(job.rejection_haircut(amount))
+        .ok_or_else(|| ErrorCode::StakeAmountOverflow)?;
+
+    check_stake_limits(
+        &job.stake_limits,
+        application.max_allowed_staked,
+        amount,
+        candidate_parameter.staked_amount,
+        candidate_parameter.tier_one_amount,
+    )?;
+
+    position.candidate = candidate_parameter.key();
+    position.index = candidate_parameter.position_count;
+    position.amount = amount;
+    position.tier_amounts = breakdown.tier_amounts;
+    position.reward_amount = breakdown.reward_amount;
+    position.timestamp = clock.unix_timestamp;
+    position.slot = clock.slot;
+    position.version = POSITION_VERSION;
+    position.reserved = [0; RECORD_RESERVED];
+    candidate_parameter.position_count += 1;
+    if sponsored {
+        candidate_parameter.sponsored_stake_count += 1;
+    } else {
+        candidate_parameter.last_stake_ts = clock.unix_timestamp;
+    }
+
+    emit!(StakePositionCreated {
+        candidate: position.candidate,
+        authority: candidate_parameter.authority,
+        index: position.index,
+        amount,
+        tier_amounts: position.tier_amounts,
+        reward_amount: position.reward_amount,
+        timestamp: position.timestamp,
+        slot: position.slot,
+    });
+
+    Ok(())
+}
+
+/// Enforces the job's limits on how often a position can take a stake made at `now`. A
+/// commitment made before the last stake of the position already waited out the cooldown when
+/// it was committed, so the cooldown skips it.
+fn check_stake_rate(
+    job: &JobStakingParameter,
+    candidate_parameter: &CandidateParameter,
+    now: i64,
+) -> Result<()> {
+    if job.max_stakes_per_position != 0
+        && candidate_parameter.own_stake_count() >= job.max_stakes_per_position as u64
+    {
+        return Err(error!(ErrorCode::MaxStakesPerPositionReached));
+    }
+    // positions that never staked, or did before the cooldown existed, have no timestamp
+    if job.stake_cooldown != 0
+        && candidate_parameter.last_stake_ts != 0
+        && now >= candidate_parameter.last_stake_ts
+        && now < candidate_parameter.last_stake_ts + job.stake_cooldown as i64
+    {
+        return Err(error!(ErrorCode::StakeCooldown));
+    }
+    Ok(())
+}
+
+/// Enforces the job's concentration limits given the position totals after the stake.
+fn check_stake_limits(
+    stake_limits: &StakeLimits,
+    max_allowed_staked: u64,
+    amount: u64,
+    staked_amount: u64,
+    tier_one_amount: u64,
+) -> Result<()> {
+    if amount < stake_limits.min_stake_amount {
+        return Err(error!(ErrorCode::StakeBelowMinimum));
+    }
+    if stake_limits.max_stake_per_staker != 0 && staked_amount > stake_limits.max_stake_per_staker {
+        return Err(error!(ErrorCode::MaxStakePerStakerExceeded));
+    }
+    if stake_limits.max_tier_one_share_bps != 0 {
+        let tier_one_capacity = max_allowed_staked / 3;
+        let max_tier_one_amount = (tier_one_capacity as u128
+            * stake_limits.max_tier_one_share_bps as u128
+            / MAX_BPS as u128) as u64;
+        if tier_one_amount > max_tier_one_amount {
+            return Err(error!(ErrorCode::MaxTierOneShareExceeded));
+        }
+    }
+    Ok(())
+}
+
+/// Checks `staker` can stake on the job, given the account its gating asks for: the
+/// `StakerApproval` of the staker for allowlisted jobs, a token account of the staker holding the
+/// credential for credential gated jobs.
+fn check_gating<'info>(
+    job: &JobStakingParameter,
+    gate: &AccountInfo<'info>,
+    staker: &Pubkey,
+) -> Result<()> {
+    match job.gating {
+        JobGating::Open => Ok(()),
+        JobGating::Allowlist => {
+            let approval = Account::<StakerApproval>::try_from(gate)
+                .map_err(|_| error!(ErrorCode::StakerNotApproved))?;
+            if approval.staker != *staker {
+                return Err(error!(ErrorCode::StakerNotApproved));
+            }
+            Ok(())
+        }
+        JobGating::Credential { mint } => {
+            let credential = Account::<TokenAccount>::try_from(gate)
+                .map_err(|_| error!(ErrorCode::MissingCredential))?;
+            if credential.owner != *staker || credential.mint != mint || credential.amount == 0 {
+                return Err(error!(ErrorCode::MissingCredential));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Keeps whoever has a hand in the outcome of the application from staking on it: the
+/// candidate, the employers of the job and the wallets the job blocked.
+fn check_conflict_of_interest(
+    job: &JobStakingParameter,
+    candidate: &Pubkey,
+    staker: &Pubkey,
+) -> Result<()> {
+    if candidate == staker {
+        return Err(error!(ErrorCode::CandidateCannotStake));
+    }
+    if job.is_employer(staker) {
+        return Err(error!(ErrorCode::EmployerCannotStake));
+    }
+    if job.is_blocked(staker) {
+        return Err(error!(ErrorCode::StakerBlocked));
+    }
+    Ok(())
+}
+
+/// Checks the job lets stakers withdraw while the application is at `status`.
+fn check_withdraw_allowed(status: &JobStatus, job: &JobStakingParameter) -> Result<()> {
+    if status.policy(job).allow_withdraw {
+        return Ok(());
+    }
+
+    match status {
+        JobStatus::Pending => {
+            msg!("It is locked, u wont get anything now");
+            Err(error!(ErrorCode::StatusPending))
+        }
+        JobStatus::SelectedButCantWithdraw => {
+            msg!("You are selected but u need to wait before we can transfer");
+            Err(error!(ErrorCode::SelectedButCantTransfer))
+        }
+        _ => Err(error!(ErrorCode::WithdrawLocked)),
+    }
+}
+
+/// Splits what a position is paid for the given application status into the principal, the
+/// reward on top of it and the principal forfeited. Only hires pay the reward, any other outcome
+/// refunds the stake, minus the haircut of its stakes on rejections.
+fn payout_for(
+    status: &JobStatus,
+    job: &JobStakingParameter,
+    candidate_parameters: &CandidateParameter,
+) -> Result<(u64, u64, u64)> {
+    check_withdraw_allowed(status, job)?;
+
+    if status.is_hire() {
+        msg!("you are selected");
+        Ok((
+            candidate_parameters.staked_amount,
+            candidate_parameters
+                .reward_amount
+                .saturating_sub(candidate_parameters.staked_amount),
+            0,
+        ))
+    } else {
+        msg!("you are not selected");
+        msg!("{}", candidate_parameters.staked_amount);
+        let forfeited = rejection_haircut(
+            status,
+            candidate_parameters,
+            candidate_parameters.staked_amount,
+        );
+        Ok((candidate_parameters.staked_amount - forfeited, 0, forfeited))
+    }
+}
+
+/// What the staker forfeits out of `principal` when the application is paid out at `status`.
+/// Only rejections are cut, a withdrawn application is no fault of its stakers.
+fn rejection_haircut(
+    status: &JobStatus,
+    candidate_parameters: &CandidateParameter,
+    principal: u64,
+) -> u64 {
+    if *status == JobStatus::Rejected {
+        candidate_parameters.haircut_share(principal)
+    } else {
+        0
+    }
+}
+
+/// When the dispute window of the last status change of the application closes.
+fn dispute_deadline(general: &GeneralParameter, application: &ApplicationParameter) -> i64 {
+    let dispute_window = i64::try_from(general.dispute_window).unwrap_or(i64::MAX);
+
+    application.status_updated_at.saturating_add(dispute_window)
+}
+
+/// Payouts wait for the dispute window of the last status change to close and for any
+/// challenge of it to be resolved.
+fn check_payouts_unlocked(
+    general: &GeneralParameter,
+    application: &ApplicationParameter,
+) -> Result<()> {
+    if application.challenge == ChallengeState::Open {
+        return Err(error!(ErrorCode::PayoutsFrozen));
+    }
+    if Clock::get()?.unix_timestamp < dispute_deadline(general, application) {
+        return Err(error!(ErrorCode::DisputeWindowOpen));
+    }
+
+    Ok(())
+}
+
+/// Gives the room of a position paid out while the application is still open back to it, so
+/// it doesn't count against the stakes that come after.
+fn release_application_stake<'info>(
+    application_program: AccountInfo<'info>,
+    application_account: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    instruction: AccountInfo<'info>,
+    application_id: &str,
+    stake_amount: u64,
+    reward_amount: u64,
+) -> Result<()> {
+    let cpi_accounts = UpdateStakeAmount {
+        base_account: application_account,
+        authority,
+        instruction,
+    };
+    let cpi_ctx = CpiContext::new(application_program, cpi_accounts);
+    application::cpi::release_stake(
+        cpi_ctx,
+        application_id.to_string(),
+        stake_amount,
+        reward_amount,
+    )
+}
+
+/// Takes the next remaining account of `settle_batch` and checks it's a token account of the
+/// job mint owned by `owner`.
+fn next_destination<'a, 'info: 'a>(
+    remaining_accounts: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+    owner: Pubkey,
+    token_mint: Pubkey,
+) -> Result<AccountInfo<'info>> {
+    let destination_info = remaining_accounts
+        .next()
+        .ok_or_else(|| ErrorCode::MissingSettlementAccounts)?;
+    let destination = Account::<TokenAccount>::try_from(destination_info)?;
+
+    if destination.owner != owner || destination.mint != token_mint {
+        return Err(error!(ErrorCode::InvalidSettlementDestination));
+    }
+
+    Ok(destination_info.clone())
+}
+
+/// Takes the profile of `staker` passed to `settle_batch`. Stakers who staked before profiles
+/// existed may not have one, there's nothing to update then.
+fn load_staker_profile<'info>(
+    profile_info: &AccountInfo<'info>,
+    staker: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Option<Account<'info, StakerProfile>>> {
+    if profile_info.data_is_empty() {
+        let (expected_key, _) =
+            Pubkey::find_program_address(&[PROFILE_SEED, staker.as_ref()], program_id);
+        if expected_key != profile_info.key() {
+            return Err(error!(ErrorCode::InvalidStakerProfile));
+        }
+        return Ok(None);
+    }
+
+    let profile = Account::<StakerProfile>::try_from(profile_info)?;
+    let expected_key = Pubkey::create_program_address(
+        &[PROFILE_SEED, staker.as_ref(), &[profile.bump]],
+        program_id,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidStakerProfile))?;
+    if expected_key != profile_info.key() {
+        return Err(error!(ErrorCode::InvalidStakerProfile));
+    }
+
+    Ok(Some(profile))
+}
+
+/// The share of `reward` earned by the referrer of the position, taken out of it.
+fn referral_share(referral_share_bps: u16, candidate: &CandidateParameter, reward: u64) -> u64 {
+    if candidate.referrer == Pubkey::default() {
+        return 0;
+    }
+
+    (reward as u128 * referral_share_bps as u128 / MAX_BPS as u128) as u64
+}
+
+/// Takes the account of `referrer` passed to a payout. Referrers who never registered don't
+/// have one, the staker keeps their share then.
+fn load_referrer_account<'info>(
+    referrer_info: &AccountInfo<'info>,
+    referrer: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Option<Account<'info, ReferrerAccount>>> {
+    if referrer_info.data_is_empty() {
+        let (expected_key, _) =
+            Pubkey::find_program_address(&[REFERRER_SEED, referrer.as_ref()], program_id);
+        if expected_key != referrer_info.key() {
+            return Err(error!(ErrorCode::InvalidReferrerAccount));
+        }
+        return Ok(None);
+    }
+
+    let referrer_account = Account::<ReferrerAccount>::try_from(referrer_info)?;
+    let expected_key = Pubkey::create_program_address(
+        &[REFERRER_SEED, referrer.as_ref(), &[referrer_account.bump]],
+        program_id,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidReferrerAccount))?;
+    if expected_key != referrer_info.key() {
+        return Err(error!(ErrorCode::InvalidReferrerAccount));
+    }
+
+    Ok(Some(referrer_account))
+}
+
+/// Pays the referrer of the position their share of its `reward` out of the job escrow into
+/// their referral vault, which has to be the vault `register_referrer` created for them.
+/// Returns the share paid, which the caller takes out of the reward.
+#[allow(clippy::too_many_arguments)]
+fn pay_referral<'info>(
+    escrow: &EscrowPayout<'info>,
+    general: &GeneralParameter,
+    candidate: &CandidateParameter,
+    referrer_info: &AccountInfo<'info>,
+    referral_vault_info: &AccountInfo<'info>,
+    job_ad_id: &str,
+    reward: u64,
+    program_id: &Pubkey,
+) -> Result<u64> {
+    let share = referral_share(general.referral_share_bps, candidate, reward);
+    if share == 0 {
+        return Ok(0);
+    }
+
+    let mut referrer_account =
+        match load_referrer_account(referrer_info, &candidate.referrer, program_id)? {
+            Some(referrer_account) => referrer_account,
+            None => return Ok(0),
+        };
+    let (expected_vault, _) = Pubkey::find_program_address(
+        &[REFERRAL_VAULT_SEED, candidate.referrer.as_ref()],
+        program_id,
+    );
+    if expected_vault != referral_vault_info.key() {
+        return Err(error!(ErrorCode::InvalidReferralVault));
+    }
+    let referral_vault = Account::<TokenAccount>::try_from(referral_vault_info)?;
+    if referral_vault.owner != referrer_info.key() || referral_vault.mint != escrow.token_mint.key()
+    {
+        return Err(error!(ErrorCode::InvalidReferralVault));
+    }
+
+    escrow.pay(
+        referral_vault_info.clone(),
+        Some(referrer_info.key()),
+        &[],
+        job_ad_id,
+        share,
+    )?;
+    referrer_account.accrued = referrer_account.accrued.saturating_add(share);
+    referrer_account.exit(program_id)?;
+
+    emit!(ReferralPaid {
+        referrer: candidate.referrer,
+        staker: candidate.authority,
+        amount: share,
+    });
+
+    Ok(share)
+}
+
+/// The accounts needed to pay out of the job escrow through `job::unstake`.
+struct EscrowPayout<'info> {
+    job_account: AccountInfo<'info>,
+    token_mint: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    escrow_wallet_state: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    rent: AccountInfo<'info>,
+    instruction: AccountInfo<'info>,
+    job_program: AccountInfo<'info>,
+}
+
+impl<'info> EscrowPayout<'info> {
+    /// Pays `amount` into `destination`, which `job::unstake` checks is owned by the signer
+    /// unless `destination_owner` names someone else.
+    fn pay(
+        &self,
+        destination: AccountInfo<'info>,
+        destination_owner: Option<Pubkey>,
+        signer_seeds: &[&[&[u8]]],
+        job_ad_id: &str,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let cpi_accounts = UnstakeToken {
+            job_account: self.job_account.clone(),
+            token_mint: self.token_mint.clone(),
+            authority: self.authority.clone(),
+            escrow_wallet_state: self.escrow_wallet_state.clone(),
+            wallet_to_deposit_to: destination,
+            system_program: self.system_program.clone(),
+            token_program: self.token_program.clone(),
+            rent: self.rent.clone(),
+            instructions: self.instruction.clone(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(self.job_program.clone(), cpi_accounts, signer_seeds);
+
+        job::cpi::unstake(cpi_ctx, job_ad_id.to_string(), amount, destination_owner)
+    }
+
+    /// Leaves `amount` of forfeited principal in the escrow and records it on the job.
+    fn forfeit(&self, job_ad_id: &str, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let cpi_accounts = RecordForfeit {
+            job_account: self.job_account.clone(),
+            authority: self.authority.clone(),
+            instructions: self.instruction.clone(),
+        };
+        let cpi_ctx = CpiContext::new(self.job_program.clone(), cpi_accounts);
+
+        job::cpi::record_forfeit(cpi_ctx, job_ad_id.to_string(), amount)
+    }
+}
+
+/// Mints `amount` receipts of the position. The receipt mint and the token account that
+/// receives them are expected as the first two remaining accounts of `stake`.
+fn mint_receipts<'info>(
+    candidate: &Account<'info, CandidateParameter>,
+    remaining_accounts: &[AccountInfo<'info>],
+    token_program: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let receipt_mint = remaining_accounts
+        .get(0)
+        .ok_or_else(|| ErrorCode::MissingReceiptAccounts)?;
+    let receipt_token_account_info = remaining_accounts
+        .get(1)
+        .ok_or_else(|| ErrorCode::MissingReceiptAccounts)?;
+
+    let receipt_token_account = Account::<TokenAccount>::try_from(receipt_token_account_info)?;
+    if receipt_mint.key() != candidate.receipt_mint
+        || receipt_token_account.mint != candidate.receipt_mint
+    {
+        return Err(error!(ErrorCode::InvalidReceiptMint));
+    }
+
+    let mint_instruction = MintTo {
+        mint: receipt_mint.clone(),
+        to: receipt_token_account_info.clone(),
+        authority: candidate.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program, mint_instruction, signer_seeds);
+
+    anchor_spl::token::mint_to(cpi_ctx, amount)
+}
+
 #[account]
 pub struct CandidateParameter {
-    pub authority: Pubkey,  // 32 bytes
-    pub staked_amount: u64, // 8 bytes
-    pub reward_amount: u64, // 8 bytes
+    pub authority: Pubkey,                  // 32 bytes
+    pub staked_amount: u64,                 // 8 bytes
+    pub reward_amount: u64,                 // 8 bytes
+    pub position_count: u64,                // 8 bytes
+    pub settled_position_count: u64,        // 8 bytes
+    pub receipt_mint: Pubkey,               // 32 bytes, default when receipts are disabled
+    pub principal_recipient: Pubkey,        // 32 bytes
+    pub tier_one_amount: u64,               // 8 bytes
+    pub version: u8,                        // 1 byte
+    pub bump: u8,                           // 1 byte, out of the reserved bytes
+    pub last_stake_ts: i64,                 // 8 bytes, out of the reserved bytes
+    pub haircut_amount: u64,                // 8 bytes, out of the reserved bytes
+    pub sponsored_stake_count: u64,         // 8 bytes, out of the reserved bytes
+    pub reserved: [u8; CANDIDATE_RESERVED], // the rest of the reserved bytes
+    pub referrer: Pubkey,                   // 32 bytes, since version 3; default without a referrer
 }
 
 impl CandidateParameter {
@@ -347,13 +2340,292 @@ impl CandidateParameter {
         self.authority = authority;
         self.staked_amount = 0;
         self.reward_amount = 0;
+        self.position_count = 0;
+        self.settled_position_count = 0;
+        self.receipt_mint = Pubkey::default();
+        self.principal_recipient = authority;
+        self.tier_one_amount = 0;
+        self.version = CANDIDATE_VERSION;
+        self.last_stake_ts = 0;
+        self.haircut_amount = 0;
+        self.sponsored_stake_count = 0;
+        self.reserved = [0; CANDIDATE_RESERVED];
+        self.referrer = Pubkey::default();
+    }
+    /// Brings a grown account to the current version. An account from before versioning only
+    /// had the staker and its amounts: its stake counts as a single position whose principal
+    /// goes back to the staker.
+    pub fn upgrade(&mut self) {
+        if self.version == 0 {
+            self.principal_recipient = self.authority;
+            if self.staked_amount != 0 {
+                self.position_count = 1;
+            }
+        }
+        self.version = CANDIDATE_VERSION;
+    }
+    /// The principal of a position can only go back to a single wallet, so it's fixed
+    /// from the first stake until the position is paid out.
+    pub fn set_principal_recipient(&mut self, principal_recipient: Pubkey) -> Result<()> {
+        if self.staked_amount == 0 {
+            self.principal_recipient = principal_recipient;
+        } else if self.principal_recipient != principal_recipient {
+            return Err(error!(ErrorCode::PrincipalRecipientMismatch));
+        }
+        Ok(())
+    }
+    /// A staker can only be referred by their first stake, and never by themselves: neither the
+    /// staker, the payer of the stake nor the wallet its principal goes back to can refer it.
+    pub fn set_referrer(&mut self, referrer: Pubkey, payer: &Pubkey) -> Result<()> {
+        if referrer == self.authority || referrer == self.principal_recipient || referrer == *payer
+        {
+            return Err(error!(ErrorCode::SelfReferral));
+        }
+        if referrer == self.referrer {
+            return Ok(());
+        }
+        if self.referrer != Pubkey::default() || self.position_count != 0 {
+            return Err(error!(ErrorCode::ReferrerAlreadySet));
+        }
+        self.referrer = referrer;
+        Ok(())
+    }
+    /// Positions that haven't been paid out yet.
+    pub fn open_position_count(&self) -> u64 {
+        self.position_count - self.settled_position_count
+    }
+    /// The stakes the staker made, or signed for, themselves. Only those count against the
+    /// rate limits of the job.
+    pub fn own_stake_count(&self) -> u64 {
+        self.position_count
+            .saturating_sub(self.sponsored_stake_count)
     }
+    /// Unstaking pays out every position at once, so all of them are settled.
     pub fn reset_after_unstake(&mut self) {
         self.staked_amount = 0;
         self.reward_amount = 0;
+        self.settled_position_count = self.position_count;
+        self.tier_one_amount = 0;
+        self.haircut_amount = 0;
+    }
+    /// The part of the haircut that applies to `principal` out of the stake. Each stake adds the
+    /// haircut of the job at stake time, so changing the haircut later never reaches it.
+    pub fn haircut_share(&self, principal: u64) -> u64 {
+        if self.staked_amount == 0 {
+            return 0;
+        }
+        (self.haircut_amount as u128 * principal as u128 / self.staked_amount as u128) as u64
+    }
+}
+
+/// Activity of a wallet across every application it staked on, seeded by the wallet. Stakes
+/// and payouts keep it up to date so it can be read in one go.
+#[account]
+pub struct StakerProfile {
+    pub authority: Pubkey,                // 32 bytes
+    pub total_staked: u64,                // 8 bytes, every stake ever made
+    pub total_outstanding: u64,           // 8 bytes, staked and not paid out yet
+    pub lifetime_rewards: u64,            // 8 bytes, paid out on top of the principal
+    pub correct_picks: u64,               // 8 bytes, applications settled as hires
+    pub incorrect_picks: u64,             // 8 bytes, applications settled as rejections
+    pub open_positions: u64,              // 8 bytes
+    pub version: u8,                      // 1 byte
+    pub bump: u8,                         // 1 byte, out of the reserved bytes
+    pub reserved: [u8; PROFILE_RESERVED], // the rest of the reserved bytes
+}
+
+impl StakerProfile {
+    /// Profiles are created the first time they're needed, by whoever needs them.
+    pub fn initialize_if_new(&mut self, authority: Pubkey, bump: u8) {
+        if self.authority != Pubkey::default() {
+            return;
+        }
+        self.authority = authority;
+        self.version = PROFILE_VERSION;
+        self.bump = bump;
+    }
+    pub fn record_stake(&mut self, amount: u64) -> Result<()> {
+        self.total_staked = self
+            .total_staked
+            .checked_add(amount)
+            .ok_or_else(|| ErrorCode::StakeAmountOverflow)?;
+        self.total_outstanding = self
+            .total_outstanding
+            .checked_add(amount)
+            .ok_or_else(|| ErrorCode::StakeAmountOverflow)?;
+        self.open_positions += 1;
+        Ok(())
+    }
+    /// The boost the track record of the wallet earns on the premium of its next stake.
+    pub fn reputation_boost_bps(&self, config: &ReputationConfig) -> u16 {
+        config.boost_bps(self.correct_picks, self.incorrect_picks)
+    }
+    /// Payouts saturate: positions staked before the profile existed were never added to it.
+    pub fn record_payout(&mut self, principal: u64, reward: u64) {
+        self.total_outstanding = self.total_outstanding.saturating_sub(principal);
+        self.lifetime_rewards = self.lifetime_rewards.saturating_add(reward);
+    }
+    /// Closes the positions of an application paid out at `status`. It counts as a pick once
+    /// the outcome is known: a hire or a rejection. Withdrawn applications count as neither, and
+    /// neither do stakes below `min_pick_amount`.
+    pub fn record_settlement(
+        &mut self,
+        status: &JobStatus,
+        positions: u64,
+        principal: u64,
+        min_pick_amount: u64,
+    ) {
+        self.open_positions = self.open_positions.saturating_sub(positions);
+        if principal < min_pick_amount {
+            return;
+        }
+        if status.is_hire() {
+            self.correct_picks += 1;
+        } else if *status == JobStatus::Rejected {
+            self.incorrect_picks += 1;
+        }
+    }
+}
+
+/// What a referrer earned on the stakers they referred, seeded by the referrer. The rewards
+/// themselves wait in the referral vault, which is owned by this account.
+#[account]
+pub struct ReferrerAccount {
+    pub referrer: Pubkey,                 // 32 bytes
+    pub accrued: u64,                     // 8 bytes, paid into the vault so far
+    pub claimed: u64,                     // 8 bytes, paid out of the vault so far
+    pub version: u8,                      // 1 byte
+    pub bump: u8,                         // 1 byte, out of the reserved bytes
+    pub reserved: [u8; PROFILE_RESERVED], // the rest of the reserved bytes
+}
+
+/// One record per `stake` call, seeded by the candidate account and the position index.
+/// A position is settled once its index is below `CandidateParameter.settled_position_count`.
+#[account]
+pub struct StakePosition {
+    pub candidate: Pubkey,               // 32 bytes
+    pub index: u64,                      // 8 bytes
+    pub amount: u64,                     // 8 bytes
+    pub tier_amounts: [u64; 3],          // 24 bytes
+    pub reward_amount: u64,              // 8 bytes
+    pub timestamp: i64,                  // 8 bytes
+    pub slot: u64,                       // 8 bytes
+    pub version: u8,                     // 1 byte
+    pub reserved: [u8; RECORD_RESERVED], // room for new fields
+}
+
+/// How many commitments were made on an application, how many are still waiting for their
+/// reveal, and which one has to be revealed next.
+#[account]
+pub struct CommitQueue {
+    pub commit_count: u64,                     // 8 bytes
+    pub pending_count: u64,                    // 8 bytes
+    pub version: u8,                           // 1 byte
+    pub next_reveal: u64,                      // 8 bytes, out of the reserved bytes
+    pub head_since: u64,                       // 8 bytes, out of the reserved bytes
+    pub ordered_from: u64,                     // 8 bytes, out of the reserved bytes
+    pub reserved: [u8; COMMIT_QUEUE_RESERVED], // room for new fields
+}
+
+impl CommitQueue {
+    /// Brings a new queue, or one from before queues were versioned, to the current version.
+    /// The counters of an older queue carry over as they are, and the commitments already made
+    /// on it keep being revealed in any order within their own window.
+    pub fn upgrade(&mut self) {
+        if self.version < 2 {
+            self.next_reveal = self.commit_count;
+            self.ordered_from = self.commit_count;
+        }
+        self.version = COMMIT_QUEUE_VERSION;
+    }
+    /// Whether the commitment can be revealed or expired now, rather than after the
+    /// commitments made before it.
+    pub fn is_next(&self, commitment: &StakeCommitment) -> bool {
+        commitment.index < self.ordered_from || commitment.index == self.next_reveal
+    }
+    /// The last slot the commitment can be revealed at. The next commitment to reveal gets its
+    /// whole window from the slot it became the next one, if that ends after its own window.
+    pub fn reveal_deadline(&self, commitment: &StakeCommitment) -> u64 {
+        if commitment.index < self.ordered_from {
+            return commitment.deadline;
+        }
+        let window = commitment.deadline - commitment.slot;
+        commitment.deadline.max(self.head_since + window)
+    }
+    /// Takes a revealed or expired commitment off the queue at `slot`.
+    pub fn advance(&mut self, commitment: &StakeCommitment, slot: u64) {
+        self.pending_count = self.pending_count.saturating_sub(1);
+        if commitment.index >= self.ordered_from {
+            self.next_reveal += 1;
+            self.head_since = slot;
+        }
     }
 }
 
+#[account]
+pub struct StakeCommitment {
+    pub authority: Pubkey,                   // 32 bytes
+    pub hash: [u8; 32],                      // 32 bytes
+    pub index: u64,                          // 8 bytes
+    pub slot: u64,                           // 8 bytes
+    pub deadline: u64,                       // 8 bytes, last slot it can be revealed at
+    pub version: u8,                         // 1 byte
+    pub committed_at: i64,                   // 8 bytes, out of the reserved bytes
+    pub reserved: [u8; COMMITMENT_RESERVED], // room for new fields
+}
+
+#[account]
+pub struct Challenge {
+    pub challenger: Pubkey,              // 32 bytes
+    pub application: Pubkey,             // 32 bytes
+    pub bond: u64,                       // 8 bytes
+    pub challenged_status: JobStatus,    // 1 byte
+    pub created_at: i64,                 // 8 bytes
+    pub version: u8,                     // 1 byte
+    pub reserved: [u8; RECORD_RESERVED], // room for new fields
+}
+
+/// The hash a staker commits to: `sha256(amount || salt || authority)`.
+pub fn commitment_hash(amount: u64, salt: &[u8; 32], authority: &Pubkey) -> [u8; 32] {
+    hashv(&[&amount.to_le_bytes(), salt, authority.as_ref()]).to_bytes()
+}
+
+#[event]
+pub struct StakePositionCreated {
+    pub candidate: Pubkey,
+    pub authority: Pubkey,
+    pub index: u64,
+    pub amount: u64,
+    pub tier_amounts: [u64; 3],
+    pub reward_amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ChallengeSettled {
+    pub application: Pubkey,
+    pub challenger: Pubkey,
+    pub bond: u64,
+    pub upheld: bool,
+}
+
+#[event]
+pub struct ReferralPaid {
+    pub referrer: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PositionsSettled {
+    pub candidate: Pubkey,
+    pub authority: Pubkey,
+    pub position_count: u64,
+    pub status: JobStatus,
+    pub amount: u64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("You can deposit only USDC token. ")]
@@ -370,4 +2642,612 @@ pub enum ErrorCode {
     RewardAmountOverflow,
     #[msg("You have already unstaked")]
     AlreadyUnstaked,
+    #[msg("Receipts have to be enabled before staking")]
+    ReceiptsAfterStaking,
+    #[msg("The position is backed by receipts, burn them to get paid")]
+    ReceiptRequired,
+    #[msg("The receipt mint and the receipt token account are required")]
+    MissingReceiptAccounts,
+    #[msg("The receipt mint doesn't belong to this position")]
+    InvalidReceiptMint,
+    #[msg("The amount of receipts to redeem is invalid")]
+    InvalidReceiptAmount,
+    #[msg("The principal of this position already goes to another wallet")]
+    PrincipalRecipientMismatch,
+    #[msg("The stake is below the minimum stake amount of the job")]
+    StakeBelowMinimum,
+    #[msg("The stake exceeds the maximum a staker can stake on an application")]
+    MaxStakePerStakerExceeded,
+    #[msg("The stake exceeds the share of tier 1 a staker can take")]
+    MaxTierOneShareExceeded,
+    #[msg("This job only accepts stakes through commit and reveal")]
+    CommitRevealRequired,
+    #[msg("This job doesn't use commit and reveal")]
+    CommitRevealDisabled,
+    #[msg("Status isn't Pending")]
+    StatusNotPending,
+    #[msg("The revealed amount and salt don't match the commitment")]
+    InvalidReveal,
+    #[msg("Earlier commitments have to be revealed or expired first")]
+    RevealOutOfOrder,
+    #[msg("The reveal window of the commitment is over")]
+    CommitmentExpired,
+    #[msg("The reveal window of the commitment isn't over yet")]
+    CommitmentNotExpired,
+    #[msg("Every position needs its destination token accounts")]
+    MissingSettlementAccounts,
+    #[msg("The destination doesn't belong to the position owner")]
+    InvalidSettlementDestination,
+    #[msg("The candidate account doesn't belong to this application")]
+    InvalidCandidateAccount,
+    #[msg("The application doesn't belong to this job")]
+    InvalidJob,
+    #[msg("The job has made all its hires")]
+    JobFilled,
+    #[msg("Withdrawals are locked at the current status of the application")]
+    WithdrawLocked,
+    #[msg("Staking is closed at the current status of the application")]
+    StakingClosed,
+    #[msg("Only stakers of the application can challenge its status")]
+    NotAStaker,
+    #[msg("Only final statuses can be challenged")]
+    StatusNotFinal,
+    #[msg("The dispute window of the status is over")]
+    DisputeWindowClosed,
+    #[msg("Payouts wait for the dispute window of the status to close")]
+    DisputeWindowOpen,
+    #[msg("Payouts are frozen while the status is challenged")]
+    PayoutsFrozen,
+    #[msg("The challenge hasn't been resolved yet")]
+    ChallengeNotResolved,
+    #[msg("The account already has the current layout")]
+    AlreadyMigrated,
+    #[msg("The staker profile doesn't belong to the position owner")]
+    InvalidStakerProfile,
+    #[msg("Stakers can't refer themselves")]
+    SelfReferral,
+    #[msg("The referrer can only be set by the first stake")]
+    ReferrerAlreadySet,
+    #[msg("The referrer account doesn't belong to the referrer of the position")]
+    InvalidReferrerAccount,
+    #[msg("There are no referral rewards to claim")]
+    NothingToClaim,
+    #[msg("The job only takes stakes from approved stakers")]
+    StakerNotApproved,
+    #[msg("The job only takes stakes from holders of its credential")]
+    MissingCredential,
+    #[msg("Candidates can't stake on their own application")]
+    CandidateCannotStake,
+    #[msg("Employers can't stake on the applications of their job")]
+    EmployerCannotStake,
+    #[msg("The wallet is blocked from staking on this job")]
+    StakerBlocked,
+    #[msg("The position has to wait for the cooldown of the job before staking again")]
+    StakeCooldown,
+    #[msg("The position took as many stakes as the job allows")]
+    MaxStakesPerPositionReached,
+    #[msg("The beneficiary has to sign to send the principal of their position to the payer")]
+    BeneficiaryConsentRequired,
+    #[msg("The referral vault isn't the vault of the referrer of the position")]
+    InvalidReferralVault,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    fn stake_limits(
+        min_stake_amount: u64,
+        max_stake_per_staker: u64,
+        max_tier_one_share_bps: u16,
+    ) -> StakeLimits {
+        StakeLimits {
+            min_stake_amount,
+            max_stake_per_staker,
+            max_tier_one_share_bps,
+        }
+    }
+
+    #[test]
+    fn stake_limits_disabled_by_default() {
+        assert!(check_stake_limits(&StakeLimits::default(), 300, 1, 300, 100).is_ok());
+    }
+
+    #[test]
+    fn stake_limits_minimum_stake() {
+        let limits = stake_limits(10, 0, 0);
+        assert!(check_stake_limits(&limits, 300, 10, 10, 10).is_ok());
+        assert_eq!(
+            check_stake_limits(&limits, 300, 9, 9, 9).unwrap_err(),
+            error!(ErrorCode::StakeBelowMinimum)
+        );
+    }
+
+    #[test]
+    fn stake_limits_max_stake_per_staker() {
+        let limits = stake_limits(0, 50, 0);
+        assert!(check_stake_limits(&limits, 300, 20, 50, 50).is_ok());
+        assert_eq!(
+            check_stake_limits(&limits, 300, 20, 51, 51).unwrap_err(),
+            error!(ErrorCode::MaxStakePerStakerExceeded)
+        );
+    }
+
+    #[test]
+    fn stake_limits_max_tier_one_share() {
+        // tier 1 holds 100, a staker can take a quarter of it
+        let limits = stake_limits(0, 0, 2_500);
+        assert!(check_stake_limits(&limits, 300, 25, 25, 25).is_ok());
+        assert_eq!(
+            check_stake_limits(&limits, 300, 26, 26, 26).unwrap_err(),
+            error!(ErrorCode::MaxTierOneShareExceeded)
+        );
+        // stakes landing past tier 1 aren't affected
+        assert!(check_stake_limits(&limits, 300, 100, 125, 25).is_ok());
+    }
+
+    #[test]
+    fn commitment_hash_binds_amount_salt_and_staker() {
+        let staker = Pubkey::new_unique();
+        let salt = [7; 32];
+        let hash = commitment_hash(100, &salt, &staker);
+
+        assert_eq!(hash, commitment_hash(100, &salt, &staker));
+        assert_ne!(hash, commitment_hash(101, &salt, &staker));
+        assert_ne!(hash, commitment_hash(100, &[8; 32], &staker));
+        assert_ne!(hash, commitment_hash(100, &salt, &Pubkey::new_unique()));
+    }
+
+    fn job_with_haircut(rejection_haircut_bps: u16) -> JobStakingParameter {
+        JobStakingParameter {
+            authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            job_ad_id: String::new(),
+            max_amount_per_application: 300,
+            total_reward_to_be_given: 0,
+            reward_half_life: 0,
+            stake_limits: StakeLimits::default(),
+            reveal_window: 0,
+            max_hires: 0,
+            hires: 0,
+            is_filled: false,
+            status_policies: job::default_status_policies(),
+            version: job::JOB_VERSION,
+            bump: 0,
+            rejection_haircut_bps,
+            forfeited_amount: 0,
+            stake_cooldown: 0,
+            max_stakes_per_position: 0,
+            gating: JobGating::Open,
+            employers: [Pubkey::default(); job::MAX_EMPLOYERS],
+            employer_count: 0,
+            blocked_stakers: [Pubkey::default(); job::MAX_BLOCKED_STAKERS],
+            blocked_staker_count: 0,
+            reserved: [0; job::JOB_RESERVED],
+        }
+    }
+
+    #[test]
+    fn stake_rate_limits() {
+        let mut job = job_with_haircut(0);
+        let mut candidate = staked_candidate(100, 300);
+        candidate.last_stake_ts = 1_000;
+        assert!(check_stake_rate(&job, &candidate, 1_000).is_ok());
+
+        job.stake_cooldown = 60;
+        assert_eq!(
+            check_stake_rate(&job, &candidate, 1_059).unwrap_err(),
+            error!(ErrorCode::StakeCooldown)
+        );
+        assert!(check_stake_rate(&job, &candidate, 1_060).is_ok());
+        // a commitment made before the last stake was rate limited when it was committed
+        assert!(check_stake_rate(&job, &candidate, 999).is_ok());
+
+        job.max_stakes_per_position = 2;
+        assert!(check_stake_rate(&job, &candidate, 1_060).is_ok());
+        candidate.position_count = 2;
+        assert_eq!(
+            check_stake_rate(&job, &candidate, 1_060).unwrap_err(),
+            error!(ErrorCode::MaxStakesPerPositionReached)
+        );
+        // deposits made for the staker by someone else don't use up their stakes
+        candidate.position_count = 5;
+        candidate.sponsored_stake_count = 4;
+        assert!(check_stake_rate(&job, &candidate, 1_060).is_ok());
+
+        // the first stake of a position isn't held back by the cooldown
+        let mut candidate = staked_candidate(0, 0);
+        candidate.position_count = 0;
+        assert!(check_stake_rate(&job, &candidate, 1).is_ok());
+    }
+
+    #[test]
+    fn conflicted_wallets_cant_stake() {
+        let candidate = Pubkey::new_unique();
+        let employer = Pubkey::new_unique();
+        let blocked = Pubkey::new_unique();
+        let mut job = job_with_haircut(0);
+        job.employers[0] = employer;
+        job.employer_count = 1;
+        job.blocked_stakers[0] = blocked;
+        job.blocked_staker_count = 1;
+
+        assert!(check_conflict_of_interest(&job, &candidate, &Pubkey::new_unique()).is_ok());
+        assert_eq!(
+            check_conflict_of_interest(&job, &candidate, &candidate).unwrap_err(),
+            error!(ErrorCode::CandidateCannotStake)
+        );
+        assert_eq!(
+            check_conflict_of_interest(&job, &candidate, &employer).unwrap_err(),
+            error!(ErrorCode::EmployerCannotStake)
+        );
+        assert_eq!(
+            check_conflict_of_interest(&job, &candidate, &blocked).unwrap_err(),
+            error!(ErrorCode::StakerBlocked)
+        );
+
+        // only the recorded part of the lists counts
+        job.blocked_staker_count = 0;
+        assert!(check_conflict_of_interest(&job, &candidate, &blocked).is_ok());
+        assert!(check_conflict_of_interest(&job, &candidate, &Pubkey::default()).is_ok());
+    }
+
+    fn staked_candidate(staked_amount: u64, reward_amount: u64) -> CandidateParameter {
+        let authority = Pubkey::new_unique();
+        CandidateParameter {
+            authority,
+            staked_amount,
+            reward_amount,
+            position_count: 1,
+            settled_position_count: 0,
+            receipt_mint: Pubkey::default(),
+            principal_recipient: authority,
+            tier_one_amount: staked_amount,
+            version: CANDIDATE_VERSION,
+            bump: 0,
+            last_stake_ts: 0,
+            haircut_amount: 0,
+            sponsored_stake_count: 0,
+            reserved: [0; CANDIDATE_RESERVED],
+            referrer: Pubkey::default(),
+        }
+    }
+
+    #[test]
+    fn payout_refunds_rejections_minus_the_haircut() {
+        let mut candidate = staked_candidate(1_000, 3_000);
+
+        let job = job_with_haircut(2_500);
+        assert_eq!(
+            payout_for(&JobStatus::Rejected, &job, &candidate).unwrap(),
+            (1_000, 0, 0)
+        );
+
+        // the haircut is the one of the job when the stakes were made
+        candidate.haircut_amount = job.rejection_haircut(1_000);
+        let job = job_with_haircut(0);
+        assert_eq!(
+            payout_for(&JobStatus::Rejected, &job, &candidate).unwrap(),
+            (750, 0, 250)
+        );
+        // withdrawn applications and hires aren't cut
+        assert_eq!(
+            payout_for(&JobStatus::Withdrawn, &job, &candidate).unwrap(),
+            (1_000, 0, 0)
+        );
+        assert_eq!(
+            payout_for(&JobStatus::Hired, &job, &candidate).unwrap(),
+            (1_000, 2_000, 0)
+        );
+    }
+
+    #[test]
+    fn haircut_share_follows_the_redeemed_principal() {
+        let mut candidate = staked_candidate(1_000, 3_000);
+        candidate.haircut_amount = 250;
+
+        assert_eq!(candidate.haircut_share(1_000), 250);
+        assert_eq!(candidate.haircut_share(400), 100);
+        assert_eq!(
+            rejection_haircut(&JobStatus::Rejected, &candidate, 400),
+            100
+        );
+        assert_eq!(rejection_haircut(&JobStatus::Withdrawn, &candidate, 400), 0);
+
+        candidate.staked_amount = 0;
+        assert_eq!(candidate.haircut_share(0), 0);
+    }
+
+    #[test]
+    fn referrer_is_only_set_by_the_first_stake() {
+        let mut candidate = staked_candidate(0, 0);
+        candidate.position_count = 0;
+        let payer = candidate.authority;
+        let referrer = Pubkey::new_unique();
+
+        assert_eq!(
+            candidate
+                .set_referrer(candidate.authority, &payer)
+                .unwrap_err(),
+            error!(ErrorCode::SelfReferral)
+        );
+        candidate.set_referrer(referrer, &payer).unwrap();
+        assert_eq!(candidate.referrer, referrer);
+
+        candidate.position_count = 1;
+        // passing the same referrer again is harmless, changing it isn't
+        candidate.set_referrer(referrer, &payer).unwrap();
+        assert_eq!(
+            candidate
+                .set_referrer(Pubkey::new_unique(), &payer)
+                .unwrap_err(),
+            error!(ErrorCode::ReferrerAlreadySet)
+        );
+
+        // stakers who staked without a referrer can't add one later
+        let mut candidate = staked_candidate(100, 300);
+        assert_eq!(
+            candidate.set_referrer(referrer, &payer).unwrap_err(),
+            error!(ErrorCode::ReferrerAlreadySet)
+        );
+    }
+
+    #[test]
+    fn wallets_behind_the_stake_cant_refer_it() {
+        let mut candidate = staked_candidate(0, 0);
+        candidate.position_count = 0;
+        let principal_recipient = Pubkey::new_unique();
+        candidate.principal_recipient = principal_recipient;
+        let payer = Pubkey::new_unique();
+
+        assert_eq!(
+            candidate
+                .set_referrer(principal_recipient, &payer)
+                .unwrap_err(),
+            error!(ErrorCode::SelfReferral)
+        );
+        assert_eq!(
+            candidate.set_referrer(payer, &payer).unwrap_err(),
+            error!(ErrorCode::SelfReferral)
+        );
+        assert_eq!(candidate.referrer, Pubkey::default());
+    }
+
+    #[test]
+    fn referral_share_of_the_reward() {
+        let mut candidate = staked_candidate(1_000, 3_000);
+        assert_eq!(referral_share(1_000, &candidate, 2_000), 0);
+
+        candidate.referrer = Pubkey::new_unique();
+        assert_eq!(referral_share(1_000, &candidate, 2_000), 200);
+        assert_eq!(referral_share(1_000, &candidate, 0), 0);
+        assert_eq!(referral_share(0, &candidate, 2_000), 0);
+    }
+
+    fn staker_profile() -> StakerProfile {
+        let mut profile = StakerProfile {
+            authority: Pubkey::default(),
+            total_staked: 0,
+            total_outstanding: 0,
+            lifetime_rewards: 0,
+            correct_picks: 0,
+            incorrect_picks: 0,
+            open_positions: 0,
+            version: 0,
+            bump: 0,
+            reserved: [0; PROFILE_RESERVED],
+        };
+        profile.initialize_if_new(Pubkey::new_unique(), 254);
+        profile
+    }
+
+    #[test]
+    fn staker_profile_is_only_initialized_once() {
+        let mut profile = staker_profile();
+        let authority = profile.authority;
+
+        profile.initialize_if_new(Pubkey::new_unique(), 253);
+        assert_eq!(profile.authority, authority);
+        assert_eq!(profile.bump, 254);
+        assert_eq!(profile.version, PROFILE_VERSION);
+    }
+
+    #[test]
+    fn staker_profile_tracks_stakes_and_settlements() {
+        let mut profile = staker_profile();
+
+        profile.record_stake(100).unwrap();
+        profile.record_stake(50).unwrap();
+        profile.record_stake(30).unwrap();
+        assert_eq!(profile.total_staked, 180);
+        assert_eq!(profile.total_outstanding, 180);
+        assert_eq!(profile.open_positions, 3);
+
+        // two positions on a hired candidate
+        profile.record_payout(150, 300);
+        profile.record_settlement(&JobStatus::Hired, 2, 150, 0);
+        // one on a rejected candidate
+        profile.record_payout(30, 0);
+        profile.record_settlement(&JobStatus::Rejected, 1, 30, 0);
+
+        assert_eq!(profile.total_staked, 180);
+        assert_eq!(profile.total_outstanding, 0);
+        assert_eq!(profile.lifetime_rewards, 300);
+        assert_eq!(profile.correct_picks, 1);
+        assert_eq!(profile.incorrect_picks, 1);
+        assert_eq!(profile.open_positions, 0);
+    }
+
+    #[test]
+    fn staker_profile_skips_withdrawn_applications_and_older_positions() {
+        let mut profile = staker_profile();
+
+        // positions staked before the profile existed were never recorded
+        profile.record_payout(500, 0);
+        profile.record_settlement(&JobStatus::Withdrawn, 4, 500, 0);
+
+        assert_eq!(profile.total_outstanding, 0);
+        assert_eq!(profile.open_positions, 0);
+        assert_eq!(profile.correct_picks, 0);
+        assert_eq!(profile.incorrect_picks, 0);
+    }
+
+    #[test]
+    fn staker_profile_skips_stakes_below_the_minimum_pick() {
+        let mut profile = staker_profile();
+
+        profile.record_stake(1).unwrap();
+        profile.record_payout(1, 2);
+        profile.record_settlement(&JobStatus::Hired, 1, 1, 100);
+
+        assert_eq!(profile.open_positions, 0);
+        assert_eq!(profile.correct_picks, 0);
+
+        profile.record_stake(100).unwrap();
+        profile.record_payout(100, 200);
+        profile.record_settlement(&JobStatus::Hired, 1, 100, 100);
+        assert_eq!(profile.correct_picks, 1);
+    }
+
+    #[test]
+    fn staker_profile_boost_follows_its_picks() {
+        let mut profile = staker_profile();
+        let reputation = ReputationConfig {
+            prior_correct: 1,
+            prior_incorrect: 1,
+            max_boost_bps: 5_000,
+        };
+        assert_eq!(profile.reputation_boost_bps(&reputation), 0);
+
+        profile.record_settlement(&JobStatus::Hired, 1, 100, 0);
+        // 2/3 is a third of the way from 1/2 to 1
+        assert_eq!(profile.reputation_boost_bps(&reputation), 1_666);
+
+        profile.record_settlement(&JobStatus::Rejected, 1, 100, 0);
+        assert_eq!(profile.reputation_boost_bps(&reputation), 0);
+        assert_eq!(
+            profile.reputation_boost_bps(&ReputationConfig::default()),
+            0
+        );
+    }
+
+    /// The layout of `CandidateParameter` before accounts were versioned.
+    #[derive(AnchorSerialize)]
+    struct CandidateParameterV0 {
+        authority: Pubkey,
+        staked_amount: u64,
+        reward_amount: u64,
+    }
+
+    #[test]
+    fn v0_candidate_pays_its_staker_once_migrated() {
+        let v0 = CandidateParameterV0 {
+            authority: Pubkey::new_unique(),
+            staked_amount: 4_000,
+            reward_amount: 11_333,
+        };
+        let mut data = CandidateParameter::discriminator().to_vec();
+        v0.serialize(&mut data).unwrap();
+        // what migrate_candidate leaves in the account before upgrading it: the v0 bytes
+        // followed by zeros
+        data.resize(CANDIDATE_SPACE, 0);
+
+        let mut parameters = CandidateParameter::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(parameters.version, 0);
+        parameters.upgrade();
+
+        assert_eq!(parameters.version, CANDIDATE_VERSION);
+        assert_eq!(parameters.authority, v0.authority);
+        assert_eq!(parameters.staked_amount, 4_000);
+        assert_eq!(parameters.reward_amount, 11_333);
+        assert_eq!(parameters.position_count, 1);
+        assert_eq!(parameters.open_position_count(), 1);
+        assert_eq!(parameters.receipt_mint, Pubkey::default());
+        assert_eq!(parameters.principal_recipient, v0.authority);
+        assert_eq!(parameters.tier_one_amount, 0);
+        assert_eq!(parameters.bump, 0);
+        assert_eq!(parameters.last_stake_ts, 0);
+        assert_eq!(parameters.haircut_amount, 0);
+        assert_eq!(parameters.sponsored_stake_count, 0);
+        assert_eq!(parameters.reserved, [0; CANDIDATE_RESERVED]);
+        assert_eq!(parameters.referrer, Pubkey::default());
+    }
+
+    /// The layout of `CommitQueue` before accounts were versioned.
+    #[derive(AnchorSerialize)]
+    struct CommitQueueV0 {
+        commit_count: u64,
+        pending_count: u64,
+    }
+
+    #[test]
+    fn v0_commit_queue_keeps_its_counters_once_migrated() {
+        let v0 = CommitQueueV0 {
+            commit_count: 5,
+            pending_count: 2,
+        };
+        let mut data = CommitQueue::discriminator().to_vec();
+        v0.serialize(&mut data).unwrap();
+        data.resize(COMMIT_QUEUE_SPACE, 0);
+
+        let mut commit_queue = CommitQueue::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(commit_queue.version, 0);
+        commit_queue.upgrade();
+
+        assert_eq!(commit_queue.version, COMMIT_QUEUE_VERSION);
+        assert_eq!(commit_queue.commit_count, 5);
+        assert_eq!(commit_queue.pending_count, 2);
+        assert_eq!(commit_queue.next_reveal, 5);
+        assert_eq!(commit_queue.ordered_from, 5);
+        assert_eq!(commit_queue.reserved, [0; COMMIT_QUEUE_RESERVED]);
+    }
+
+    fn commitment(index: u64, slot: u64, deadline: u64) -> StakeCommitment {
+        StakeCommitment {
+            authority: Pubkey::new_unique(),
+            hash: [0; 32],
+            index,
+            slot,
+            deadline,
+            version: COMMITMENT_VERSION,
+            committed_at: 0,
+            reserved: [0; COMMITMENT_RESERVED],
+        }
+    }
+
+    #[test]
+    fn commitments_are_revealed_in_commit_order() {
+        let mut data = CommitQueue::discriminator().to_vec();
+        CommitQueueV0 {
+            commit_count: 1,
+            pending_count: 1,
+        }
+        .serialize(&mut data)
+        .unwrap();
+        data.resize(COMMIT_QUEUE_SPACE, 0);
+        let mut commit_queue = CommitQueue::try_deserialize(&mut data.as_slice()).unwrap();
+        commit_queue.upgrade();
+        commit_queue.commit_count = 3;
+        commit_queue.pending_count = 3;
+
+        // made before reveals were ordered, it holds back no one
+        let legacy = commitment(0, 10, 110);
+        let first = commitment(1, 20, 120);
+        let second = commitment(2, 30, 130);
+        assert!(commit_queue.is_next(&legacy));
+        assert!(commit_queue.is_next(&first));
+        assert!(!commit_queue.is_next(&second));
+
+        // the second commitment gets a whole window once the first is expired
+        assert_eq!(commit_queue.reveal_deadline(&first), 120);
+        commit_queue.advance(&first, 500);
+        assert!(commit_queue.is_next(&second));
+        assert_eq!(commit_queue.reveal_deadline(&second), 600);
+
+        commit_queue.advance(&legacy, 510);
+        assert_eq!(commit_queue.next_reveal, 2);
+        assert_eq!(commit_queue.head_since, 500);
+        assert_eq!(commit_queue.pending_count, 1);
+    }
 }
@@ -1,10 +1,32 @@
+pub mod reputation;
+pub mod versioning;
+
 use anchor_lang::prelude::*;
+use anchor_lang::AccountsExit;
 use anchor_spl::token::Mint;    
+use versioning::RESERVED_SPACE;
+pub use reputation::ReputationConfig;
 
 
 declare_id!("23yfjFQCZoBgNoBrP7JwFUfGoPc4GYcxJXL9NxLensBt");
 
 const GENERAL_SEED: &'static [u8] = b"general";
+pub const STAKER_APPROVAL_SEED: &'static [u8] = b"stakerapproval";
+
+/// How many wallets can approve status changes that release rewards.
+pub const MAX_APPROVERS: usize = 8;
+/// A referrer can at most earn as much as the premium of the stakers they referred.
+pub const MAX_REFERRAL_SHARE_BPS: u16 = 10_000;
+
+pub const GENERAL_VERSION: u8 = 2;
+const GENERAL_SPACE: usize = 8 + 32 + 32 + 8 + 32 + 8 + 8 + 32 * MAX_APPROVERS + 1 + 1 + 32 + 1 + RESERVED_SPACE;
+/// The reserved bytes left once the bump, the reputation config, the referral share, the approver nonce and the
+/// minimum pick amount are carved out of them.
+pub const GENERAL_RESERVED: usize = RESERVED_SPACE - 1 - 6 - 2 - 4 - 8;
+pub const STAKER_APPROVAL_VERSION: u8 = 1;
+const STAKER_APPROVAL_SPACE: usize = 8 + 32 + 8 + 1 + RESERVED_SPACE;
+/// The reserved bytes of a staker approval left once the bump is carved out of them.
+pub const STAKER_APPROVAL_RESERVED: usize = RESERVED_SPACE - 1;
 
 
 #[program]
@@ -17,12 +39,27 @@ pub mod general {
 
         parameters.mint = ctx.accounts.token_mint.key();
         parameters.authority = ctx.accounts.authority.key();
+        parameters.crank_reward = 0;
+        parameters.resolver = Pubkey::default();
+        parameters.dispute_window = 0;
+        parameters.challenge_bond = 0;
+        parameters.approvers = [Pubkey::default(); MAX_APPROVERS];
+        parameters.approver_count = 0;
+        parameters.approval_threshold = 0;
+        parameters.attester = Pubkey::default();
+        parameters.version = GENERAL_VERSION;
+        parameters.bump = *ctx.bumps.get("base_account").unwrap();
+        parameters.reputation = ReputationConfig::default();
+        parameters.referral_share_bps = 0;
+        parameters.approver_nonce = 0;
+        parameters.min_pick_amount = 0;
+        parameters.reserved = [0; GENERAL_RESERVED];
 
 
         Ok(())
     }
 
-    pub fn change_mint(ctx: Context<ChangeMint>, _general_bump: u8) -> Result<()> {
+    pub fn change_mint(ctx: Context<ChangeMint>) -> Result<()> {
 
         let parameters = &mut ctx.accounts.base_account;
 
@@ -30,11 +67,145 @@ pub mod general {
 
         Ok(())
     }
+
+    /// Sets what a crank earns for every position it settles, paid by the crank treasury of candidate_staking.
+    pub fn update_crank_reward(ctx: Context<UpdateConfig>, crank_reward: u64) -> Result<()> {
+
+        let parameters = &mut ctx.accounts.base_account;
+
+        parameters.crank_reward = crank_reward;
+
+        Ok(())
+    }
+
+    /// Stakers can challenge a final status for `dispute_window` seconds by bonding `challenge_bond`,
+    /// the `resolver` then decides whether the status stands. A window needs a resolver, otherwise
+    /// challenged payouts would stay frozen.
+    pub fn update_dispute_config(ctx: Context<UpdateConfig>, resolver: Pubkey, dispute_window: u64, challenge_bond: u64) -> Result<()> {
+
+        if dispute_window > 0 && resolver == Pubkey::default() {
+            return Err(error!(ErrorCode::MissingResolver));
+        }
+
+        let parameters = &mut ctx.accounts.base_account;
+
+        parameters.resolver = resolver;
+        parameters.dispute_window = dispute_window;
+        parameters.challenge_bond = challenge_bond;
+
+        Ok(())
+    }
+
+    /// Upgrades the general account created with an older layout to the current one.
+    pub fn migrate_general(ctx: Context<MigrateGeneral>) -> Result<()> {
+
+        let account_info = ctx.accounts.base_account.to_account_info();
+        versioning::grow_account(&account_info, &ctx.accounts.payer.to_account_info(), &ctx.accounts.system_program.to_account_info(), GENERAL_SPACE)?;
+
+        let mut parameters = Account::<GeneralParameter>::try_from(&account_info)?;
+        if parameters.version >= GENERAL_VERSION {
+            return Err(error!(ErrorCode::AlreadyMigrated));
+        }
+        parameters.version = GENERAL_VERSION;
+        parameters.bump = *ctx.bumps.get("base_account").unwrap();
+        parameters.exit(ctx.program_id)?;
+
+        Ok(())
+    }
+
+    /// Sets the key whose signed hiring outcomes anyone can submit to `application::update_status_attested`,
+    /// the default key disables attestations.
+    pub fn update_attester(ctx: Context<UpdateConfig>, attester: Pubkey) -> Result<()> {
+
+        let parameters = &mut ctx.accounts.base_account;
+
+        parameters.attester = attester;
+
+        Ok(())
+    }
+
+    /// Requires `approval_threshold` of the `approvers` to agree before an application is selected,
+    /// a threshold of 0 lets the application's authority select on its own.
+    pub fn update_approvers(ctx: Context<UpdateConfig>, approvers: Vec<Pubkey>, approval_threshold: u8) -> Result<()> {
+
+        if approvers.len() > MAX_APPROVERS {
+            return Err(error!(ErrorCode::TooManyApprovers));
+        }
+        if approval_threshold as usize > approvers.len() {
+            return Err(error!(ErrorCode::InvalidThreshold));
+        }
+        for (index, approver) in approvers.iter().enumerate() {
+            if approvers[..index].contains(approver) {
+                return Err(error!(ErrorCode::DuplicateApprover));
+            }
+        }
+
+        let parameters = &mut ctx.accounts.base_account;
+
+        parameters.approvers = [Pubkey::default(); MAX_APPROVERS];
+        parameters.approvers[..approvers.len()].copy_from_slice(&approvers);
+        parameters.approver_count = approvers.len() as u8;
+        parameters.approval_threshold = approval_threshold;
+        // the approvals of pending proposals were given by the previous set
+        parameters.approver_nonce = parameters.approver_nonce.wrapping_add(1);
+
+        Ok(())
+    }
+
+    /// Sets how the track record of stakers boosts the premium of their stakes, see `ReputationConfig`.
+    /// Stakes below `min_pick_amount` don't count as picks, so dust stakes can't farm a track record.
+    pub fn update_reputation_config(ctx: Context<UpdateConfig>, reputation: ReputationConfig, min_pick_amount: u64) -> Result<()> {
+
+        if !reputation.is_valid() {
+            return Err(error!(ErrorCode::InvalidReputationConfig));
+        }
+
+        let parameters = &mut ctx.accounts.base_account;
+
+        parameters.reputation = reputation;
+        parameters.min_pick_amount = min_pick_amount;
+
+        Ok(())
+    }
+
+    /// Share of the premium of hired stakes that is paid to the referrer of the staker, out of that premium.
+    pub fn update_referral_share(ctx: Context<UpdateConfig>, referral_share_bps: u16) -> Result<()> {
+
+        if referral_share_bps > MAX_REFERRAL_SHARE_BPS {
+            return Err(error!(ErrorCode::InvalidReferralShare));
+        }
+
+        let parameters = &mut ctx.accounts.base_account;
+
+        parameters.referral_share_bps = referral_share_bps;
+
+        Ok(())
+    }
+
+    /// Adds `staker` to the allowlist of the jobs gated by one, once they've been verified.
+    pub fn approve_staker(ctx: Context<ApproveStaker>, staker: Pubkey) -> Result<()> {
+
+        let approval = &mut ctx.accounts.staker_approval;
+
+        approval.staker = staker;
+        approval.approved_at = Clock::get()?.unix_timestamp;
+        approval.version = STAKER_APPROVAL_VERSION;
+        approval.bump = *ctx.bumps.get("staker_approval").unwrap();
+        approval.reserved = [0; STAKER_APPROVAL_RESERVED];
+
+        Ok(())
+    }
+
+    /// Takes `staker` off the allowlist. Their stakes stay, they just can't stake on allowlisted
+    /// jobs anymore.
+    pub fn revoke_staker(_ctx: Context<RevokeStaker>, _staker: Pubkey) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
 pub struct Initialize<'info> {
-    #[account(init, payer = authority, seeds = [GENERAL_SEED], bump, space = 32 + 32 + 8 )]
+    #[account(init, payer = authority, seeds = [GENERAL_SEED], bump, space = GENERAL_SPACE )]
     pub base_account: Account<'info, GeneralParameter>,
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -43,17 +214,112 @@ pub struct Initialize<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(general_bump: u8)]
 pub struct ChangeMint<'info> {
-    #[account(mut, seeds = [GENERAL_SEED], bump = general_bump, has_one = authority)]
+    #[account(mut, seeds = [GENERAL_SEED], bump = base_account.bump, has_one = authority)]
     pub base_account: Account<'info, GeneralParameter>,
     #[account(mut)]
     pub authority: Signer<'info>,
     pub token_mint: Account<'info, Mint>
 }
 
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [GENERAL_SEED], bump = base_account.bump, has_one = authority)]
+    pub base_account: Account<'info, GeneralParameter>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(staker: Pubkey)]
+pub struct ApproveStaker<'info> {
+    #[account(init, payer = authority, seeds = [STAKER_APPROVAL_SEED, staker.as_ref()], bump, space = STAKER_APPROVAL_SPACE)]
+    pub staker_approval: Account<'info, StakerApproval>,
+    #[account(seeds = [GENERAL_SEED], bump = base_account.bump, has_one = authority)]
+    pub base_account: Account<'info, GeneralParameter>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(staker: Pubkey)]
+pub struct RevokeStaker<'info> {
+    #[account(mut, seeds = [STAKER_APPROVAL_SEED, staker.as_ref()], bump = staker_approval.bump, close = authority)]
+    pub staker_approval: Account<'info, StakerApproval>,
+    #[account(seeds = [GENERAL_SEED], bump = base_account.bump, has_one = authority)]
+    pub base_account: Account<'info, GeneralParameter>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGeneral<'info> {
+    /// CHECK: only deserialized once it has the size of the current layout, which is also
+    /// when its canonical bump gets stored
+    #[account(mut, seeds = [GENERAL_SEED], bump, owner = crate::ID)]
+    pub base_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[account]
 pub struct GeneralParameter {
     pub mint: Pubkey, // 32 bytes
-    pub authority: Pubkey // 32 bytes
+    pub authority: Pubkey, // 32 bytes
+    pub crank_reward: u64, // 8 bytes
+    pub resolver: Pubkey, // 32 bytes
+    pub dispute_window: u64, // 8 bytes, seconds; 0 disables challenges
+    pub challenge_bond: u64, // 8 bytes
+    pub approvers: [Pubkey; 8], // 32 * 8 bytes, only the first approver_count are set
+    pub approver_count: u8, // 1 byte
+    pub approval_threshold: u8, // 1 byte, 0 disables the approvals
+    pub attester: Pubkey, // 32 bytes
+    pub version: u8, // 1 byte
+    pub bump: u8, // 1 byte, out of the reserved bytes
+    pub reputation: ReputationConfig, // 6 bytes, out of the reserved bytes
+    pub referral_share_bps: u16, // 2 bytes, out of the reserved bytes; 0 disables referrals
+    pub approver_nonce: u32, // 4 bytes, out of the reserved bytes; bumped every time the approvers are set
+    pub min_pick_amount: u64, // 8 bytes, out of the reserved bytes; smaller stakes don't count as picks
+    pub reserved: [u8; GENERAL_RESERVED] // the rest of the reserved bytes
+}
+
+impl GeneralParameter {
+    pub fn requires_approval(&self) -> bool {
+        self.approval_threshold > 0
+    }
+
+    /// Position of `approver` in the approver set, if it is one.
+    pub fn approver_index(&self, approver: &Pubkey) -> Option<usize> {
+        self.approvers[..self.approver_count as usize].iter().position(|key| key == approver)
+    }
+}
+
+/// Issued by the general authority to a verified staker, seeded by the staker. Jobs gated by an
+/// allowlist only take stakes from wallets that have one.
+#[account]
+pub struct StakerApproval {
+    pub staker: Pubkey, // 32 bytes
+    pub approved_at: i64, // 8 bytes
+    pub version: u8, // 1 byte
+    pub bump: u8, // 1 byte, out of the reserved bytes
+    pub reserved: [u8; STAKER_APPROVAL_RESERVED] // the rest of the reserved bytes
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Too many approvers")]
+    TooManyApprovers,
+    #[msg("The threshold can't exceed the number of approvers")]
+    InvalidThreshold,
+    #[msg("An approver is listed twice")]
+    DuplicateApprover,
+    #[msg("The account already has the current layout")]
+    AlreadyMigrated,
+    #[msg("The reputation boost exceeds its cap or can never apply")]
+    InvalidReputationConfig,
+    #[msg("The referral share can't exceed 10000 basis points")]
+    InvalidReferralShare,
+    #[msg("Challenges need a resolver to decide on them")]
+    MissingResolver,
 }
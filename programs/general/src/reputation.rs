@@ -0,0 +1,104 @@
+use anchor_lang::prelude::*;
+
+const MAX_BPS: u64 = 10_000;
+/// A reputation can at most double the premium of a stake.
+pub const MAX_REPUTATION_BOOST_BPS: u16 = 10_000;
+
+/// How the picks of a staker turn into a boost of the premium of their next stakes.
+///
+/// The score is the Bayesian accuracy `(correct + prior_correct) / (picks + prior_correct +
+/// prior_incorrect)`, so the score of a staker with few settled picks stays close to the prior
+/// accuracy. Only the part of the score above the prior accuracy is boosted, linearly up to
+/// `max_boost_bps` for a perfect score.
+#[derive(Debug, Clone, Copy, Default, AnchorSerialize, AnchorDeserialize, PartialEq)]
+pub struct ReputationConfig {
+    pub prior_correct: u16,   // 2 bytes
+    pub prior_incorrect: u16, // 2 bytes
+    pub max_boost_bps: u16,   // 2 bytes, 0 disables the boost
+}
+
+impl ReputationConfig {
+    /// The boost has to stay under `MAX_REPUTATION_BOOST_BPS` and needs a prior accuracy below
+    /// 100%, otherwise nobody could ever score above it.
+    pub fn is_valid(&self) -> bool {
+        self.max_boost_bps <= MAX_REPUTATION_BOOST_BPS
+            && (self.max_boost_bps == 0 || self.prior_incorrect > 0)
+    }
+
+    /// The Bayesian accuracy of a staker, in basis points.
+    pub fn score_bps(&self, correct_picks: u64, incorrect_picks: u64) -> u64 {
+        let hits = correct_picks as u128 + self.prior_correct as u128;
+        let total = hits + incorrect_picks as u128 + self.prior_incorrect as u128;
+        if total == 0 {
+            return 0;
+        }
+
+        (hits * MAX_BPS as u128 / total) as u64
+    }
+
+    /// How much the premium of the next stake of a staker is boosted, in basis points.
+    pub fn boost_bps(&self, correct_picks: u64, incorrect_picks: u64) -> u16 {
+        if self.max_boost_bps == 0 {
+            return 0;
+        }
+
+        let prior_bps = self.score_bps(0, 0);
+        let score_bps = self.score_bps(correct_picks, incorrect_picks);
+        if prior_bps >= MAX_BPS || score_bps <= prior_bps {
+            return 0;
+        }
+
+        (self.max_boost_bps as u64 * (score_bps - prior_bps) / (MAX_BPS - prior_bps)) as u16
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(prior_correct: u16, prior_incorrect: u16, max_boost_bps: u16) -> ReputationConfig {
+        ReputationConfig {
+            prior_correct,
+            prior_incorrect,
+            max_boost_bps,
+        }
+    }
+
+    #[test]
+    fn reputation_disabled_by_default() {
+        let config = ReputationConfig::default();
+        assert!(config.is_valid());
+        assert_eq!(config.boost_bps(100, 0), 0);
+    }
+
+    #[test]
+    fn reputation_score_is_pulled_towards_the_prior() {
+        // a prior accuracy of 20% worth 10 picks
+        let config = config(2, 8, 5_000);
+        assert_eq!(config.score_bps(0, 0), 2_000);
+        assert_eq!(config.score_bps(2, 0), 3_333);
+        assert_eq!(config.score_bps(90, 0), 9_200);
+        assert_eq!(config.score_bps(0, 10), 1_000);
+    }
+
+    #[test]
+    fn reputation_boosts_only_above_the_prior() {
+        let config = config(2, 8, 5_000);
+        assert_eq!(config.boost_bps(0, 0), 0);
+        assert_eq!(config.boost_bps(1, 9), 0);
+        // 3_333 is a sixth of the way from 2_000 to 10_000
+        assert_eq!(config.boost_bps(2, 0), 833);
+        // the boost stays below the cap
+        assert_eq!(config.boost_bps(10_000, 0), 4_995);
+        assert!(config.boost_bps(u64::MAX / 2, 0) <= 5_000);
+    }
+
+    #[test]
+    fn reputation_config_bounds() {
+        assert!(config(0, 1, MAX_REPUTATION_BOOST_BPS).is_valid());
+        assert!(!config(0, 1, MAX_REPUTATION_BOOST_BPS + 1).is_valid());
+        // with a perfect prior accuracy nobody can score above it
+        assert!(!config(1, 0, 1_000).is_valid());
+        assert_eq!(config(1, 0, 1_000).boost_bps(10, 0), 0);
+    }
+}
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{program::invoke, system_instruction};
+
+/// Size of the reserved bytes at the end of every versioned account. New fields are carved out
+/// of them, so adding a field doesn't change the size of the account.
+pub const RESERVED_SPACE: usize = 32;
+
+/// Grows an account created with an older layout to `space` bytes, topping up its rent from
+/// `payer`. Fields are only ever appended to a layout, so the new bytes are zeroed and read as
+/// the defaults of the fields the old layout didn't have, starting with a version of 0.
+pub fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+) -> Result<()> {
+    if account.data_len() >= space {
+        return Ok(());
+    }
+
+    let top_up = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    account.realloc(space, true)?;
+
+    Ok(())
+}
@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::sysvar::instructions as tx_instructions;
+use anchor_lang::AccountsExit;
 use anchor_spl::token::{Mint, Token, TokenAccount, Transfer};
 use general::program::General;
+use general::versioning::{self, RESERVED_SPACE};
 use general::{self, GeneralParameter};
 
 declare_id!("2hfgvbWKSzzFYRSeMSBHhA5qBYSFYPojVsf81hXVFbMk");
@@ -12,6 +14,30 @@ const GENERAL_SEED: &'static [u8] = b"general";
 const CANDIDATE_STAKING_PROGRAM_ID: &'static str = "GCPpQABMRJ7bhRCqaYtBo7G1b5popATvcXDs2c1bK4hW";
 const APPLICATION_PROGRAM_ID: &'static str = "7NBejsPH8cgtSkmqgSKyUffx8fYnrGCDp9fxePdjRXe7";
 
+pub const MAX_BPS: u16 = 10_000;
+pub const MAX_MULTIPLIER_BPS: u16 = 2 * MAX_BPS;
+/// Number of `application::JobStatus` variants, there's one `StatusPolicy` per status. The
+/// application program fails to build if they ever disagree.
+pub const STATUS_COUNT: usize = 11;
+/// How many employer wallets a job can record.
+pub const MAX_EMPLOYERS: usize = 4;
+/// How many wallets can be blocked from staking on a job on top of its employers.
+pub const MAX_BLOCKED_STAKERS: usize = 16;
+
+pub const JOB_VERSION: u8 = 5;
+/// Where the version byte sits. Every field before it has a fixed size.
+const JOB_VERSION_OFFSET: usize = 8 + 32 + 32 + 40 + 8 * 3 + 18 + 8 + 4 + 4 + 1 + 4 * STATUS_COUNT;
+const JOB_SPACE: usize = JOB_VERSION_OFFSET + 1 + RESERVED_SPACE + GROWN_SPACE;
+/// Fields the job grew by: the gating in version 3, the employers and the blocked stakers in
+/// version 4. They sit before the rest of the reserved bytes.
+const GROWN_SPACE: usize = 33 + 32 * MAX_EMPLOYERS + 1 + 32 * MAX_BLOCKED_STAKERS + 1;
+/// The reserved bytes left once the bump, the haircut, the forfeited amount, the cooldown and the
+/// stakes per position are carved out of them.
+pub const JOB_RESERVED: usize = RESERVED_SPACE - 1 - 2 - 8 - 4 - 2;
+/// Where the rest of the reserved bytes started before version 5, when the fields the job grew by
+/// were appended after them.
+const JOB_RESERVED_OFFSET_V4: usize = JOB_VERSION_OFFSET + 1 + RESERVED_SPACE - JOB_RESERVED;
+
 #[program]
 pub mod job {
     use super::*;
@@ -19,9 +45,14 @@ pub mod job {
     pub fn initialize(
         ctx: Context<Initialize>,
         job_ad_id: String,
-        _general_bump: u8,
         max_amount_per_application: u64,
+        reward_half_life: i64,
+        max_hires: u32,
     ) -> Result<()> {
+        if reward_half_life < 0 {
+            return Err(error!(ErrorCode::InvalidHalfLife));
+        }
+
         let parameters = &mut ctx.accounts.base_account;
 
         parameters.authority = ctx.accounts.authority.key();
@@ -29,6 +60,55 @@ pub mod job {
         parameters.max_amount_per_application = max_amount_per_application;
         parameters.total_reward_to_be_given = 0;
         parameters.mint = ctx.accounts.general_account.mint;
+        parameters.reward_half_life = reward_half_life;
+        parameters.stake_limits = StakeLimits::default();
+        parameters.reveal_window = 0;
+        parameters.max_hires = max_hires;
+        parameters.hires = 0;
+        parameters.is_filled = false;
+        parameters.status_policies = default_status_policies();
+        parameters.version = JOB_VERSION;
+        parameters.bump = *ctx.bumps.get("base_account").unwrap();
+        parameters.rejection_haircut_bps = 0;
+        parameters.forfeited_amount = 0;
+        parameters.stake_cooldown = 0;
+        parameters.max_stakes_per_position = 0;
+        parameters.gating = JobGating::Open;
+        parameters.employers = [Pubkey::default(); MAX_EMPLOYERS];
+        parameters.employer_count = 0;
+        parameters.blocked_stakers = [Pubkey::default(); MAX_BLOCKED_STAKERS];
+        parameters.blocked_staker_count = 0;
+        parameters.reserved = [0; JOB_RESERVED];
+
+        Ok(())
+    }
+
+    /// Upgrades a job created with an older layout to the current one. Anyone can pay for it,
+    /// it grows the account, moves the reserved bytes after the fields the job grew by, fills in
+    /// the fields a job from before versioning lacks and bumps its version.
+    pub fn migrate_job(ctx: Context<MigrateJob>, _job_ad_id: String) -> Result<()> {
+        let account_info = ctx.accounts.base_account.to_account_info();
+        versioning::grow_account(
+            &account_info,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            JOB_SPACE,
+        )?;
+
+        {
+            let mut data = account_info.try_borrow_mut_data()?;
+            if data[JOB_VERSION_OFFSET] >= JOB_VERSION {
+                return Err(error!(ErrorCode::AlreadyMigrated));
+            }
+            if data[JOB_VERSION_OFFSET] < 5 {
+                move_reserved_last(&mut data);
+            }
+        }
+
+        let mut parameters = Account::<JobStakingParameter>::try_from(&account_info)?;
+        parameters.upgrade();
+        parameters.bump = *ctx.bumps.get("base_account").unwrap();
+        parameters.exit(ctx.program_id)?;
 
         Ok(())
     }
@@ -36,7 +116,6 @@ pub mod job {
     pub fn update_rewards(
         ctx: Context<UpdateRewards>,
         _job_ad_id: String,
-        _job_bump: u8,
         reward_amount: u64,
     ) -> Result<()> {
         let ixns = ctx.accounts.instructions.to_account_info();
@@ -56,21 +135,57 @@ pub mod job {
         } else {
             let parameters = &mut ctx.accounts.job_account;
 
+            // an application's rewards are committed when it gets selected, and released by
+            // `release_rewards` if it stops being a hire
+            parameters.record_hire()?;
             parameters.total_reward_to_be_given = parameters
                 .total_reward_to_be_given
                 .checked_add(reward_amount)
                 .ok_or_else(|| ErrorCode::TotalRewardAmountOverflow)?;
+
+            if parameters.is_filled {
+                emit!(JobFilled {
+                    job: parameters.key(),
+                    hires: parameters.hires,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Undoes `update_rewards` when a selected application moves to a status that isn't a hire,
+    /// which gives the hire back and reopens the job if it was filled.
+    pub fn release_rewards(
+        ctx: Context<UpdateRewards>,
+        _job_ad_id: String,
+        reward_amount: u64,
+    ) -> Result<()> {
+        let ixns = ctx.accounts.instructions.to_account_info();
+        let current_index = tx_instructions::load_current_index_checked(&ixns)? as usize;
+        let current_ixn = tx_instructions::load_instruction_at_checked(current_index, &ixns)?;
+
+        if APPLICATION_PROGRAM_ID != current_ixn.program_id.to_string() {
+            return Err(error!(ErrorCode::InvalidCall));
         }
 
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.release_hire();
+        parameters.total_reward_to_be_given = parameters
+            .total_reward_to_be_given
+            .saturating_sub(reward_amount);
+
         Ok(())
     }
 
+    /// Pays `amount` out of the escrow into a wallet of the signer, or of `destination_owner` when
+    /// candidate_staking pays someone else, like the payer of a delegated stake.
     pub fn unstake(
         ctx: Context<UnstakeToken>,
         job_ad_id: String,
-        job_bump: u8,
-        _wallet_bump: u8,
         amount: u64,
+        _destination_owner: Option<Pubkey>,
     ) -> Result<()> {
         msg!("CPI call happening successfully");
 
@@ -87,7 +202,7 @@ pub mod job {
         if CANDIDATE_STAKING_PROGRAM_ID != current_ixn.program_id.to_string() {
             return Err(error!(ErrorCode::InvalidCall));
         } else {
-            let bump_vector = job_bump.to_le_bytes();
+            let bump_vector = ctx.accounts.job_account.bump.to_le_bytes();
             let inner = vec![
                 JOB_FACTORY_SEED,
                 job_ad_id.as_bytes()[..18].as_ref(),
@@ -116,14 +231,235 @@ pub mod job {
         }
         Ok(())
     }
+
+    /// Records principal that a rejected application's staker forfeited. It stays in the escrow
+    /// until the job authority claims it with `claim_forfeits`.
+    pub fn record_forfeit(
+        ctx: Context<RecordForfeit>,
+        _job_ad_id: String,
+        amount: u64,
+    ) -> Result<()> {
+        let ixns = ctx.accounts.instructions.to_account_info();
+        let current_index = tx_instructions::load_current_index_checked(&ixns)? as usize;
+        let current_ixn = tx_instructions::load_instruction_at_checked(current_index, &ixns)?;
+
+        if CANDIDATE_STAKING_PROGRAM_ID != current_ixn.program_id.to_string() {
+            return Err(error!(ErrorCode::InvalidCall));
+        }
+
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.forfeited_amount = parameters
+            .forfeited_amount
+            .checked_add(amount)
+            .ok_or_else(|| ErrorCode::TotalRewardAmountOverflow)?;
+
+        Ok(())
+    }
+
+    /// Pays `amount` of the forfeited principal out of the escrow into a wallet of the job
+    /// authority. The rest of the escrow backs the stakes, so nothing beyond the forfeits can be
+    /// claimed.
+    pub fn claim_forfeits(
+        ctx: Context<ClaimForfeits>,
+        job_ad_id: String,
+        amount: u64,
+    ) -> Result<()> {
+        let parameters = &mut ctx.accounts.job_account;
+
+        if amount > parameters.forfeited_amount {
+            return Err(error!(ErrorCode::ForfeitsExceeded));
+        }
+        parameters.forfeited_amount -= amount;
+
+        let bump_vector = parameters.bump.to_le_bytes();
+        let inner = vec![
+            JOB_FACTORY_SEED,
+            job_ad_id.as_bytes()[..18].as_ref(),
+            job_ad_id.as_bytes()[18..].as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let outer = vec![inner.as_slice()];
+
+        let transfer_instruction = Transfer {
+            from: ctx.accounts.escrow_wallet_state.to_account_info(),
+            to: ctx.accounts.wallet_to_deposit_to.to_account_info(),
+            authority: ctx.accounts.job_account.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            outer.as_slice(),
+        );
+
+        anchor_spl::token::transfer(cpi_ctx, amount)
+    }
+
+    pub fn update_stake_limits(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        stake_limits: StakeLimits,
+    ) -> Result<()> {
+        if stake_limits.max_tier_one_share_bps > MAX_BPS {
+            return Err(error!(ErrorCode::InvalidBps));
+        }
+
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.stake_limits = stake_limits;
+
+        Ok(())
+    }
+
+    /// Share of the principal that stakers of rejected applications forfeit, 0 refunds them in full.
+    pub fn update_rejection_haircut(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        rejection_haircut_bps: u16,
+    ) -> Result<()> {
+        if rejection_haircut_bps > MAX_BPS {
+            return Err(error!(ErrorCode::InvalidBps));
+        }
+
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.rejection_haircut_bps = rejection_haircut_bps;
+
+        Ok(())
+    }
+
+    /// Restricts who can stake on the job, see `JobGating`.
+    pub fn update_gating(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        gating: JobGating,
+    ) -> Result<()> {
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.gating = gating;
+
+        Ok(())
+    }
+
+    /// Slows down wallets splitting their stake into many transactions: `stake_cooldown` is the
+    /// time in seconds between two stakes on the same position and `max_stakes_per_position` caps
+    /// how many stakes it takes. 0 disables either.
+    pub fn update_stake_rate_limits(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        stake_cooldown: u32,
+        max_stakes_per_position: u16,
+    ) -> Result<()> {
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.stake_cooldown = stake_cooldown;
+        parameters.max_stakes_per_position = max_stakes_per_position;
+
+        Ok(())
+    }
+
+    /// Records the wallets of the people hiring for the job. They decide the outcome of its
+    /// applications, so they can't stake on them.
+    pub fn update_employers(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        employers: Vec<Pubkey>,
+    ) -> Result<()> {
+        if employers.len() > MAX_EMPLOYERS {
+            return Err(error!(ErrorCode::TooManyEmployers));
+        }
+
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.employers = [Pubkey::default(); MAX_EMPLOYERS];
+        parameters.employers[..employers.len()].copy_from_slice(&employers);
+        parameters.employer_count = employers.len() as u8;
+
+        Ok(())
+    }
+
+    /// Blocks `staker` from staking on the applications of the job.
+    pub fn block_staker(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        staker: Pubkey,
+    ) -> Result<()> {
+        let parameters = &mut ctx.accounts.job_account;
+
+        if parameters.is_blocked(&staker) {
+            return Ok(());
+        }
+        let count = parameters.blocked_staker_count as usize;
+        if count >= MAX_BLOCKED_STAKERS {
+            return Err(error!(ErrorCode::TooManyBlockedStakers));
+        }
+        parameters.blocked_stakers[count] = staker;
+        parameters.blocked_staker_count += 1;
+
+        Ok(())
+    }
+
+    pub fn unblock_staker(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        staker: Pubkey,
+    ) -> Result<()> {
+        let parameters = &mut ctx.accounts.job_account;
+
+        let count = parameters.blocked_staker_count as usize;
+        let index = parameters.blocked_stakers[..count]
+            .iter()
+            .position(|key| *key == staker)
+            .ok_or_else(|| ErrorCode::StakerNotBlocked)?;
+        // the last blocked wallet takes the place of the unblocked one
+        parameters.blocked_stakers[index] = parameters.blocked_stakers[count - 1];
+        parameters.blocked_stakers[count - 1] = Pubkey::default();
+        parameters.blocked_staker_count -= 1;
+
+        Ok(())
+    }
+
+    /// A non-zero reveal window (in slots) switches the job to commit-reveal staking.
+    pub fn update_reveal_window(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        reveal_window: u64,
+    ) -> Result<()> {
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.reveal_window = reveal_window;
+
+        Ok(())
+    }
+
+    /// `status` is the index of the `application::JobStatus` variant the policy applies to.
+    pub fn update_status_policy(
+        ctx: Context<UpdateJobConfig>,
+        _job_ad_id: String,
+        status: u8,
+        status_policy: StatusPolicy,
+    ) -> Result<()> {
+        if status as usize >= STATUS_COUNT {
+            return Err(error!(ErrorCode::InvalidStatus));
+        }
+        if status_policy.multiplier_bps > MAX_MULTIPLIER_BPS {
+            return Err(error!(ErrorCode::InvalidBps));
+        }
+
+        let parameters = &mut ctx.accounts.job_account;
+
+        parameters.status_policies[status as usize] = status_policy;
+
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
-#[instruction(job_ad_id: String, general_bump: u8)]
+#[instruction(job_ad_id: String)]
 pub struct Initialize<'info> {
-    #[account(init, payer = authority, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump, constraint = authority.key() == general_account.authority @ ErrorCode::InvalidAuthority, space = 8 + 32 + 32 + 40 + 8 + 8 )]
-    pub base_account: Account<'info, JobStakingParameter>,
-    #[account(mut, seeds = [GENERAL_SEED], bump = general_bump, seeds::program = general_program.key())]
+    #[account(init, payer = authority, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump, constraint = authority.key() == general_account.authority @ ErrorCode::InvalidAuthority, space = JOB_SPACE)]
+    pub base_account: Box<Account<'info, JobStakingParameter>>,
+    #[account(mut, seeds = [GENERAL_SEED], bump = general_account.bump, seeds::program = general_program.key())]
     pub general_account: Account<'info, GeneralParameter>,
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -132,9 +468,21 @@ pub struct Initialize<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(job_ad_id: String, job_bump: u8, wallet_bump: u8)]
+#[instruction(job_ad_id: String)]
+pub struct MigrateJob<'info> {
+    /// CHECK: only deserialized once it has the size of the current layout, which is also
+    /// when its canonical bump gets stored
+    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump, owner = crate::ID)]
+    pub base_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String, amount: u64, destination_owner: Option<Pubkey>)]
 pub struct UnstakeToken<'info> {
-    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_bump)]
+    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump)]
     pub job_account: Box<Account<'info, JobStakingParameter>>,
     #[account(constraint = token_mint.key() == job_account.mint @ ErrorCode::InvalidTokenMint)]
     pub token_mint: Account<'info, Mint>,
@@ -144,7 +492,7 @@ pub struct UnstakeToken<'info> {
     pub escrow_wallet_state: Account<'info, TokenAccount>,
     #[account(
         mut,
-        constraint=wallet_to_deposit_to.owner == authority.key(),
+        constraint=wallet_to_deposit_to.owner == destination_owner.unwrap_or_else(|| authority.key()),
         constraint=wallet_to_deposit_to.mint == token_mint.key()
     )]
     pub wallet_to_deposit_to: Account<'info, TokenAccount>,
@@ -157,10 +505,10 @@ pub struct UnstakeToken<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(job_ad_id: String, job_bump: u8)]
+#[instruction(job_ad_id: String)]
 pub struct UpdateRewards<'info> {
-    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_bump)]
-    pub job_account: Account<'info, JobStakingParameter>,
+    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
     #[account(mut)]
     pub authority: Signer<'info>,
     #[account(address  = tx_instructions::ID)]
@@ -168,13 +516,194 @@ pub struct UpdateRewards<'info> {
     pub instructions: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(job_ad_id: String)]
+pub struct RecordForfeit<'info> {
+    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    pub authority: Signer<'info>,
+    #[account(address = tx_instructions::ID)]
+    ///CHECK:
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String)]
+pub struct ClaimForfeits<'info> {
+    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, has_one = authority)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    pub authority: Signer<'info>,
+    #[account(mut, constraint = escrow_wallet_state.owner == job_account.key())]
+    pub escrow_wallet_state: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint=wallet_to_deposit_to.owner == authority.key(),
+        constraint=wallet_to_deposit_to.mint == job_account.mint @ ErrorCode::InvalidTokenMint
+    )]
+    pub wallet_to_deposit_to: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(job_ad_id: String)]
+pub struct UpdateJobConfig<'info> {
+    #[account(mut, seeds = [JOB_FACTORY_SEED, job_ad_id.as_bytes()[..18].as_ref(), job_ad_id.as_bytes()[18..].as_ref()], bump = job_account.bump, has_one = authority)]
+    pub job_account: Box<Account<'info, JobStakingParameter>>,
+    pub authority: Signer<'info>,
+}
+
+/// Limits on how much of an application a single staker can take. A value of 0 disables the limit.
+#[derive(Debug, Clone, Default, AnchorSerialize, AnchorDeserialize, PartialEq)]
+pub struct StakeLimits {
+    pub min_stake_amount: u64,       // 8 bytes
+    pub max_stake_per_staker: u64,   // 8 bytes
+    pub max_tier_one_share_bps: u16, // 2 bytes, share of the tier 1 capacity
+}
+
+/// Who can stake on the applications of a job. Employers with compliance requirements can
+/// limit staking to verified wallets.
+#[derive(Debug, Clone, AnchorSerialize, AnchorDeserialize, PartialEq)]
+pub enum JobGating {
+    /// Anyone can stake.
+    Open,
+    /// Only wallets with a `general::StakerApproval` can stake.
+    Allowlist,
+    /// Only wallets holding a token of `mint` can stake.
+    Credential { mint: Pubkey },
+}
+
+/// What stakers can do while an application is at a given status.
+#[derive(Debug, Clone, AnchorSerialize, AnchorDeserialize, PartialEq)]
+pub struct StatusPolicy {
+    pub allow_stake: bool,    // 1 byte
+    pub allow_withdraw: bool, // 1 byte
+    pub multiplier_bps: u16,  // 2 bytes, applied to the premium of stakes made at this status
+}
+
+impl StatusPolicy {
+    pub const fn new(allow_stake: bool, allow_withdraw: bool) -> Self {
+        Self {
+            allow_stake,
+            allow_withdraw,
+            multiplier_bps: MAX_BPS,
+        }
+    }
+}
+
+/// Staking is open until the candidate gets an offer, stakers can only withdraw once the
+/// application is settled.
+pub fn default_status_policies() -> [StatusPolicy; STATUS_COUNT] {
+    [
+        StatusPolicy::new(false, true),  // Rejected
+        StatusPolicy::new(false, false), // SelectedButCantWithdraw
+        StatusPolicy::new(false, true),  // Selected
+        StatusPolicy::new(true, false),  // Pending
+        StatusPolicy::new(false, true),  // Withdrawn
+        StatusPolicy::new(true, false),  // Screening
+        StatusPolicy::new(true, false),  // Interviewing
+        StatusPolicy::new(false, false), // Offered
+        StatusPolicy::new(false, false), // OfferAccepted
+        StatusPolicy::new(false, false), // InProbation
+        StatusPolicy::new(false, true),  // Hired
+    ]
+}
+
+/// Moves the rest of the reserved bytes of a job from before version 5 after the fields it grew
+/// by, which used to be appended after them. The fields up to the reserved bytes have the same
+/// size in every layout, and an older job only has zeros where it hadn't grown yet.
+fn move_reserved_last(data: &mut [u8]) {
+    data[JOB_RESERVED_OFFSET_V4..JOB_SPACE].rotate_left(JOB_RESERVED);
+}
+
 #[account]
 pub struct JobStakingParameter {
-    pub authority: Pubkey,               // 32 bytes
-    pub mint: Pubkey,                    // 32 bytes
-    pub job_ad_id: String,               // 40 bytes
-    pub max_amount_per_application: u64, // 8 bytes
-    pub total_reward_to_be_given: u64,   // 8 bytes
+    pub authority: Pubkey,                   // 32 bytes
+    pub mint: Pubkey,                        // 32 bytes
+    pub job_ad_id: String,                   // 40 bytes
+    pub max_amount_per_application: u64,     // 8 bytes
+    pub total_reward_to_be_given: u64,       // 8 bytes
+    pub reward_half_life: i64,               // 8 bytes, seconds; 0 disables the time decay
+    pub stake_limits: StakeLimits,           // 18 bytes
+    pub reveal_window: u64,                  // 8 bytes, slots; 0 disables commit-reveal
+    pub max_hires: u32,                      // 4 bytes, 0 means no limit
+    pub hires: u32,                          // 4 bytes
+    pub is_filled: bool,                     // 1 byte
+    pub status_policies: [StatusPolicy; 11], // 4 * 11 bytes, indexed by status
+    pub version: u8,                         // 1 byte
+    pub bump: u8,                            // 1 byte, out of the reserved bytes
+    pub rejection_haircut_bps: u16,          // 2 bytes, out of the reserved bytes
+    pub forfeited_amount: u64,               // 8 bytes, out of the reserved bytes; not claimed yet
+    pub stake_cooldown: u32,                 // 4 bytes, out of the reserved bytes; seconds
+    pub max_stakes_per_position: u16,        // 2 bytes, out of the reserved bytes
+    pub gating: JobGating,                   // 33 bytes, since version 3
+    pub employers: [Pubkey; 4],              // 32 * 4 bytes, since version 4
+    pub employer_count: u8,                  // 1 byte
+    pub blocked_stakers: [Pubkey; 16],       // 32 * 16 bytes, on top of the employers
+    pub blocked_staker_count: u8,            // 1 byte
+    pub reserved: [u8; JOB_RESERVED],        // the rest of the reserved bytes
+}
+
+impl JobStakingParameter {
+    /// Brings a grown account to the current version. The fields after the layout of a job from
+    /// before versioning read as zeros, which are the right defaults for everything but the
+    /// status policies: all zeros would lock every status. The reserved bytes of an older job
+    /// may hold what's left of a shorter gating once moved, they're cleared.
+    pub fn upgrade(&mut self) {
+        if self.version == 0 {
+            self.status_policies = default_status_policies();
+        }
+        if self.version < 5 {
+            self.reserved = [0; JOB_RESERVED];
+        }
+        self.version = JOB_VERSION;
+    }
+
+    pub fn is_employer(&self, wallet: &Pubkey) -> bool {
+        self.employers[..self.employer_count as usize].contains(wallet)
+    }
+
+    pub fn is_blocked(&self, wallet: &Pubkey) -> bool {
+        self.blocked_stakers[..self.blocked_staker_count as usize].contains(wallet)
+    }
+
+    /// What the staker of a rejected application forfeits out of `principal`.
+    pub fn rejection_haircut(&self, principal: u64) -> u64 {
+        (principal as u128 * self.rejection_haircut_bps as u128 / MAX_BPS as u128) as u64
+    }
+
+    /// Counts a selected application, the job is filled once it reaches `max_hires`.
+    pub fn record_hire(&mut self) -> Result<()> {
+        if self.is_filled {
+            return Err(error!(ErrorCode::MaxHiresReached));
+        }
+
+        self.hires = self
+            .hires
+            .checked_add(1)
+            .ok_or_else(|| ErrorCode::MaxHiresReached)?;
+        if self.max_hires != 0 && self.hires >= self.max_hires {
+            self.is_filled = true;
+        }
+
+        Ok(())
+    }
+
+    /// Takes back a hire of `record_hire`, the job isn't filled anymore once it's below
+    /// `max_hires`.
+    pub fn release_hire(&mut self) {
+        self.hires = self.hires.saturating_sub(1);
+        if self.max_hires == 0 || self.hires < self.max_hires {
+            self.is_filled = false;
+        }
+    }
+}
+
+/// Emitted when the last hire of a job is made. The applications still pending are refunded
+/// from then on.
+#[event]
+pub struct JobFilled {
+    pub job: Pubkey,
+    pub hires: u32,
 }
 
 #[account]
@@ -194,4 +723,187 @@ pub enum ErrorCode {
     TotalRewardAmountOverflow,
     #[msg("The token mint should should match to the one which was deposited")]
     InvalidTokenMint,
+    #[msg("The reward half-life can't be negative")]
+    InvalidHalfLife,
+    #[msg("Basis points can't exceed 10000")]
+    InvalidBps,
+    #[msg("The job already has all the hires it can make")]
+    MaxHiresReached,
+    #[msg("Invalid status value")]
+    InvalidStatus,
+    #[msg("The account already has the current layout")]
+    AlreadyMigrated,
+    #[msg("Too many employers")]
+    TooManyEmployers,
+    #[msg("Too many blocked stakers")]
+    TooManyBlockedStakers,
+    #[msg("The wallet isn't blocked")]
+    StakerNotBlocked,
+    #[msg("The job doesn't have that much forfeited principal to claim")]
+    ForfeitsExceeded,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    /// The layout of `JobStakingParameter` before accounts were versioned.
+    #[derive(AnchorSerialize)]
+    struct JobStakingParameterV0 {
+        authority: Pubkey,
+        mint: Pubkey,
+        job_ad_id: String,
+        max_amount_per_application: u64,
+        total_reward_to_be_given: u64,
+    }
+
+    #[test]
+    fn v0_job_gets_the_default_policies_once_migrated() {
+        let v0 = JobStakingParameterV0 {
+            authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            job_ad_id: "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61".to_string(),
+            max_amount_per_application: 10_000,
+            total_reward_to_be_given: 2_500,
+        };
+        let mut data = JobStakingParameter::discriminator().to_vec();
+        v0.serialize(&mut data).unwrap();
+        // what migrate_job leaves in the account before upgrading it: the v0 bytes followed by
+        // zeros
+        data.resize(JOB_SPACE, 0);
+        move_reserved_last(&mut data);
+
+        let mut parameters = JobStakingParameter::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(parameters.version, 0);
+        parameters.upgrade();
+
+        assert_eq!(parameters.version, JOB_VERSION);
+        assert_eq!(parameters.authority, v0.authority);
+        assert_eq!(parameters.mint, v0.mint);
+        assert_eq!(parameters.job_ad_id, v0.job_ad_id);
+        assert_eq!(parameters.max_amount_per_application, 10_000);
+        assert_eq!(parameters.total_reward_to_be_given, 2_500);
+        assert_eq!(parameters.status_policies, default_status_policies());
+        assert_eq!(parameters.stake_limits, StakeLimits::default());
+        assert_eq!(parameters.max_hires, 0);
+        assert!(!parameters.is_filled);
+        assert_eq!(parameters.gating, JobGating::Open);
+        assert_eq!(parameters.employer_count, 0);
+        assert_eq!(parameters.blocked_staker_count, 0);
+        assert_eq!(parameters.reserved, [0; JOB_RESERVED]);
+    }
+
+    /// The layout of `JobStakingParameter` in version 4, with the fields the job grew by after
+    /// the reserved bytes.
+    #[derive(AnchorSerialize)]
+    struct JobStakingParameterV4 {
+        authority: Pubkey,
+        mint: Pubkey,
+        job_ad_id: String,
+        max_amount_per_application: u64,
+        total_reward_to_be_given: u64,
+        reward_half_life: i64,
+        stake_limits: StakeLimits,
+        reveal_window: u64,
+        max_hires: u32,
+        hires: u32,
+        is_filled: bool,
+        status_policies: [StatusPolicy; STATUS_COUNT],
+        version: u8,
+        bump: u8,
+        rejection_haircut_bps: u16,
+        forfeited_amount: u64,
+        stake_cooldown: u32,
+        max_stakes_per_position: u16,
+        reserved: [u8; JOB_RESERVED],
+        gating: JobGating,
+        employers: [Pubkey; MAX_EMPLOYERS],
+        employer_count: u8,
+        blocked_stakers: [Pubkey; MAX_BLOCKED_STAKERS],
+        blocked_staker_count: u8,
+    }
+
+    #[test]
+    fn v4_job_keeps_its_lists_once_the_reserved_bytes_move_last() {
+        let credential = Pubkey::new_unique();
+        let employer = Pubkey::new_unique();
+        let blocked = Pubkey::new_unique();
+        let mut employers = [Pubkey::default(); MAX_EMPLOYERS];
+        employers[0] = employer;
+        let mut blocked_stakers = [Pubkey::default(); MAX_BLOCKED_STAKERS];
+        blocked_stakers[0] = blocked;
+        let v4 = JobStakingParameterV4 {
+            authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            job_ad_id: "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61".to_string(),
+            max_amount_per_application: 10_000,
+            total_reward_to_be_given: 2_500,
+            reward_half_life: 3_600,
+            stake_limits: StakeLimits::default(),
+            reveal_window: 100,
+            max_hires: 2,
+            hires: 1,
+            is_filled: false,
+            status_policies: default_status_policies(),
+            version: 4,
+            bump: 254,
+            rejection_haircut_bps: 500,
+            forfeited_amount: 50,
+            stake_cooldown: 60,
+            max_stakes_per_position: 3,
+            reserved: [0; JOB_RESERVED],
+            gating: JobGating::Credential { mint: credential },
+            employers,
+            employer_count: 1,
+            blocked_stakers,
+            blocked_staker_count: 1,
+        };
+        let mut data = JobStakingParameter::discriminator().to_vec();
+        v4.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), JOB_SPACE);
+        assert_eq!(data[JOB_VERSION_OFFSET], 4);
+        move_reserved_last(&mut data);
+
+        let mut parameters = JobStakingParameter::try_deserialize(&mut data.as_slice()).unwrap();
+        parameters.upgrade();
+
+        assert_eq!(parameters.version, JOB_VERSION);
+        assert_eq!(parameters.bump, 254);
+        assert_eq!(parameters.rejection_haircut_bps, 500);
+        assert_eq!(parameters.forfeited_amount, 50);
+        assert_eq!(parameters.stake_cooldown, 60);
+        assert_eq!(parameters.max_stakes_per_position, 3);
+        assert_eq!(
+            parameters.gating,
+            JobGating::Credential { mint: credential }
+        );
+        assert!(parameters.is_employer(&employer));
+        assert!(parameters.is_blocked(&blocked));
+        assert_eq!(parameters.reserved, [0; JOB_RESERVED]);
+    }
+
+    #[test]
+    fn upgrade_keeps_the_policies_of_versioned_jobs() {
+        let mut data = JobStakingParameter::discriminator().to_vec();
+        JobStakingParameterV0 {
+            authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            job_ad_id: "0e3d8fc2-0b7f-4c4e-a7e4-6f7f5ddc3a61".to_string(),
+            max_amount_per_application: 10_000,
+            total_reward_to_be_given: 0,
+        }
+        .serialize(&mut data)
+        .unwrap();
+        data.resize(JOB_SPACE, 0);
+        let mut parameters = JobStakingParameter::try_deserialize(&mut data.as_slice()).unwrap();
+        parameters.version = 1;
+        parameters.status_policies[0].allow_withdraw = true;
+
+        parameters.upgrade();
+
+        assert_eq!(parameters.version, JOB_VERSION);
+        assert!(parameters.status_policies[0].allow_withdraw);
+        assert!(!parameters.status_policies[1].allow_withdraw);
+    }
 }
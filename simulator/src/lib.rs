@@ -0,0 +1,11 @@
+//! Off-chain market simulator used to tune the reward tiers.
+//!
+//! A scenario describes jobs, the stakes that reach their applications and how those
+//! applications end. The stakes are priced by the application program's `RewardCalculator`,
+//! then every application is settled over seeded Monte Carlo runs to see what the escrows owe,
+//! how often they run short, what they keep and what the stakers earn.
+
+pub mod report;
+pub mod rng;
+pub mod scenario;
+pub mod simulation;
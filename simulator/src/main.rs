@@ -0,0 +1,67 @@
+use simulator::scenario::ScenarioFile;
+use simulator::simulation::simulate;
+use std::str::FromStr;
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: simulator <scenario.json> [--runs N] [--seed S]";
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut path = None;
+    let mut runs = None;
+    let mut seed = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => runs = Some(parse_flag(&arg, args.next())?),
+            "--seed" => seed = Some(parse_flag(&arg, args.next())?),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or_else(|| USAGE.to_string())?;
+
+    let contents =
+        fs::read_to_string(&path).map_err(|error| format!("can't read {}: {}", path, error))?;
+    let mut file: ScenarioFile = serde_json::from_str(&contents)
+        .map_err(|error| format!("invalid scenario {}: {}", path, error))?;
+    // The flags override the whole file, scenarios included.
+    if let Some(runs) = runs {
+        file.runs = runs;
+        file.scenarios
+            .iter_mut()
+            .for_each(|scenario| scenario.runs = None);
+    }
+    if let Some(seed) = seed {
+        file.seed = seed;
+        file.scenarios
+            .iter_mut()
+            .for_each(|scenario| scenario.seed = None);
+    }
+
+    let reports: Vec<_> = (0..file.scenarios.len())
+        .map(|index| {
+            let (runs, seed) = file.runs_and_seed(index);
+            simulate(&file.scenarios[index], runs, seed)
+        })
+        .collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&reports).map_err(|error| error.to_string())?
+    );
+
+    Ok(())
+}
+
+fn parse_flag<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .ok_or_else(|| format!("{} needs a value", flag))?
+        .parse()
+        .map_err(|_| format!("{} needs a number", flag))
+}
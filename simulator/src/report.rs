@@ -0,0 +1,78 @@
+use serde::Serialize;
+
+/// The spread of a value across the runs of a scenario.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    /// Sorts `values` in place and summarizes them, all zeros when there's nothing to summarize.
+    pub fn of(values: &mut [f64]) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        Self {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p10: percentile(values, 10),
+            p50: percentile(values, 50),
+            p90: percentile(values, 90),
+            min: values[0],
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], percent: usize) -> f64 {
+    let rank = (percent * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub runs: u32,
+    pub seed: u64,
+    /// Stakes the applications refused, they're left out of every run.
+    pub rejected_stakes: u64,
+    pub liabilities: Summary,
+    pub shortfall: Summary,
+    /// The share of the runs where at least one escrow ran short.
+    pub shortfall_probability: f64,
+    pub treasury_income: Summary,
+    /// What the referrers took out of the premiums, it's part of the liabilities.
+    pub referral_shares: Summary,
+    /// What the crank treasury pays to settle every position, outside of the escrows.
+    pub crank_rewards: Summary,
+    /// The return of every staker in every run, in basis points.
+    pub staker_roi_bps: Summary,
+    /// The share of those returns that lost money.
+    pub losing_staker_share: f64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summarizes_with_nearest_rank_percentiles() {
+        let mut values: Vec<f64> = (1..=10).rev().map(|value| value as f64).collect();
+        let summary = Summary::of(&mut values);
+
+        assert_eq!(summary.mean, 5.5);
+        assert_eq!(summary.p10, 1.0);
+        assert_eq!(summary.p50, 5.0);
+        assert_eq!(summary.p90, 9.0);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.max, 10.0);
+        assert_eq!(Summary::of(&mut []), Summary::default());
+    }
+}
@@ -0,0 +1,140 @@
+use serde::Deserialize;
+
+/// A file of scenarios. Scenarios without their own `runs` and `seed` take the ones of the file,
+/// the seed offset by the position of the scenario so no two scenarios share their draws.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioFile {
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_runs")]
+    pub runs: u32,
+    pub scenarios: Vec<Scenario>,
+}
+
+fn default_runs() -> u32 {
+    1
+}
+
+/// A scenario runs under the general config it names: the `referral_share_bps` referrers take
+/// out of the premium of the hired stakers they referred, and the `crank_reward` the crank
+/// treasury pays for every position it settles. Both default to 0, like on-chain.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub runs: Option<u32>,
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub referral_share_bps: u16,
+    #[serde(default)]
+    pub crank_reward: u64,
+    pub jobs: Vec<JobScenario>,
+}
+
+/// The parts of `job::JobStakingParameter` that change what a job pays out, plus what the
+/// employer funded its escrow with.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobScenario {
+    pub max_amount_per_application: u64,
+    #[serde(default)]
+    pub reward_half_life: i64,
+    #[serde(default)]
+    pub rejection_haircut_bps: u16,
+    #[serde(default)]
+    pub reward_pool: u64,
+    pub applications: Vec<ApplicationScenario>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApplicationScenario {
+    pub outcome: Outcome,
+    pub stakes: Vec<StakeArrival>,
+}
+
+/// How an application ends. Fixed outcomes are the same in every run, a hire probability is
+/// drawn again in each run.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Hired,
+    Rejected,
+    HireProbability(f64),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StakeArrival {
+    pub staker: String,
+    /// Seconds since the application was created.
+    pub at: i64,
+    pub amount: u64,
+    /// Whether the staker named a referrer with their first stake.
+    #[serde(default)]
+    pub referred: bool,
+}
+
+impl ScenarioFile {
+    /// The runs and the seed of the scenario at `index`.
+    pub fn runs_and_seed(&self, index: usize) -> (u32, u64) {
+        let scenario = &self.scenarios[index];
+
+        (
+            scenario.runs.unwrap_or(self.runs),
+            scenario
+                .seed
+                .unwrap_or_else(|| self.seed.wrapping_add(index as u64)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scenario_file_defaults() {
+        let file: ScenarioFile = serde_json::from_str(
+            r#"{
+                "seed": 7,
+                "scenarios": [
+                    {
+                        "name": "fixed",
+                        "jobs": [{
+                            "max_amount_per_application": 300,
+                            "applications": [{
+                                "outcome": "hired",
+                                "stakes": [{ "staker": "alice", "at": 0, "amount": 100 }]
+                            }]
+                        }]
+                    },
+                    {
+                        "name": "drawn",
+                        "runs": 500,
+                        "seed": 1,
+                        "jobs": [{
+                            "max_amount_per_application": 300,
+                            "reward_pool": 1000,
+                            "applications": [{
+                                "outcome": { "hire_probability": 0.25 },
+                                "stakes": []
+                            }]
+                        }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(file.runs_and_seed(0), (1, 7));
+        assert_eq!(file.runs_and_seed(1), (500, 1));
+        assert_eq!(file.scenarios[0].referral_share_bps, 0);
+        assert_eq!(file.scenarios[0].crank_reward, 0);
+        assert!(!file.scenarios[0].jobs[0].applications[0].stakes[0].referred);
+        let job = &file.scenarios[0].jobs[0];
+        assert_eq!(job.reward_half_life, 0);
+        assert_eq!(job.rejection_haircut_bps, 0);
+        assert_eq!(job.applications[0].outcome, Outcome::Hired);
+        assert_eq!(
+            file.scenarios[1].jobs[0].applications[0].outcome,
+            Outcome::HireProbability(0.25)
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use crate::report::{ScenarioReport, Summary};
+use crate::rng::SplitMix64;
+use crate::scenario::{Outcome, Scenario, StakeArrival};
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use application::{ApplicationParameter, ChallengeState, JobStatus, RewardCalculator};
+use job::MAX_BPS;
+use std::collections::BTreeMap;
+use std::sync::Once;
+
+/// The calculator logs through `msg!`, which would print every step of every stake.
+struct QuietStubs;
+
+impl SyscallStubs for QuietStubs {
+    fn sol_log(&self, _message: &str) {}
+}
+
+static QUIET: Once = Once::new();
+
+/// A stake the application accepted, with the reward it was priced at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricedStake {
+    pub staker: String,
+    pub amount: u64,
+    pub reward: u64,
+    pub referred: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PricedApplication {
+    pub outcome: Outcome,
+    pub stakes: Vec<PricedStake>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PricedJob {
+    pub reward_pool: u64,
+    pub rejection_haircut_bps: u16,
+    pub referral_share_bps: u16,
+    pub crank_reward: u64,
+    pub applications: Vec<PricedApplication>,
+}
+
+/// What a single run of a scenario ended with, summed over its jobs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunResult {
+    /// The premiums owed to the stakers of hired applications.
+    pub liabilities: u64,
+    /// How much of the liabilities the escrows couldn't cover.
+    pub shortfall: u64,
+    /// What's left in the escrows once every staker was paid: the unused pools and the haircuts.
+    pub treasury_income: u64,
+    /// The part of the liabilities paid to referrers rather than to the stakers.
+    pub referral_shares: u64,
+    /// What the crank treasury pays for settling every position, one per staker and application.
+    pub crank_rewards: u64,
+    /// The return of each staker over everything they staked, in basis points.
+    pub staker_roi_bps: BTreeMap<String, i64>,
+}
+
+/// Replays the stakes of every application in arrival order through `RewardCalculator`, the
+/// same way `candidate_staking` would have priced them. Stakes the application would have
+/// refused are dropped and counted.
+pub fn price_scenario(scenario: &Scenario) -> (Vec<PricedJob>, u64) {
+    QUIET.call_once(|| {
+        set_syscall_stubs(Box::new(QuietStubs));
+    });
+
+    let mut rejected_stakes = 0;
+    let jobs = scenario
+        .jobs
+        .iter()
+        .map(|job| PricedJob {
+            reward_pool: job.reward_pool,
+            rejection_haircut_bps: job.rejection_haircut_bps,
+            referral_share_bps: scenario.referral_share_bps,
+            crank_reward: scenario.crank_reward,
+            applications: job
+                .applications
+                .iter()
+                .map(|application| {
+                    let (stakes, rejected) = price_stakes(
+                        job.max_amount_per_application,
+                        job.reward_half_life,
+                        &application.stakes,
+                    );
+                    rejected_stakes += rejected;
+                    PricedApplication {
+                        outcome: application.outcome.clone(),
+                        stakes,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    (jobs, rejected_stakes)
+}
+
+fn price_stakes(
+    max_allowed_staked: u64,
+    reward_half_life: i64,
+    arrivals: &[StakeArrival],
+) -> (Vec<PricedStake>, u64) {
+    let mut application = new_application(max_allowed_staked);
+    let mut arrivals: Vec<&StakeArrival> = arrivals.iter().collect();
+    arrivals.sort_by_key(|arrival| arrival.at);
+
+    let mut stakes = Vec::with_capacity(arrivals.len());
+    let mut rejected = 0;
+    for arrival in arrivals {
+        // Mirrors the room check of `record_stake`.
+        let fits = application
+            .staked_amount
+            .checked_add(arrival.amount)
+            .map_or(false, |staked| staked < application.max_allowed_staked);
+        let reward = if fits {
+            RewardCalculator::new(&application)
+                .with_time_decay(reward_half_life, arrival.at)
+                .calculate_reward(arrival.amount)
+                .ok()
+        } else {
+            None
+        };
+
+        match reward {
+            Some(reward) => {
+                application.staked_amount += arrival.amount;
+                stakes.push(PricedStake {
+                    staker: arrival.staker.clone(),
+                    amount: arrival.amount,
+                    reward,
+                    referred: arrival.referred,
+                });
+            }
+            None => rejected += 1,
+        }
+    }
+
+    (stakes, rejected)
+}
+
+fn new_application(max_allowed_staked: u64) -> ApplicationParameter {
+    ApplicationParameter {
+        authority: Pubkey::default(),
+        status: JobStatus::Pending,
+        staked_amount: 0,
+        max_allowed_staked,
+        total_reward_amount: 0,
+        update_reward_value_in_job: false,
+        created_at: 0,
+        job: Pubkey::default(),
+        candidate: Pubkey::default(),
+        status_change_count: 0,
+        status_updated_at: 0,
+        challenge: ChallengeState::Unchallenged,
+        version: application::APPLICATION_VERSION,
+        bump: 0,
+        committed_reward: 0,
+        reserved: [0; application::APPLICATION_RESERVED],
+    }
+}
+
+/// Settles every application once. Hired stakers are paid their reward out of their principal
+/// and the job's pool, minus the share of their referrer, everyone else gets their principal
+/// back minus the job's haircut, which stays in the escrow. Every position settled earns the
+/// crank its reward, paid by the crank treasury.
+pub fn run_once(jobs: &[PricedJob], rng: &mut SplitMix64) -> RunResult {
+    let mut result = RunResult::default();
+    let mut stakers: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+
+    for job in jobs {
+        let mut liabilities = 0u64;
+        let mut forfeited = 0u64;
+        for application in &job.applications {
+            let hired = match application.outcome {
+                Outcome::Hired => true,
+                Outcome::Rejected => false,
+                Outcome::HireProbability(probability) => rng.next_f64() < probability,
+            };
+            let mut positions: Vec<&str> = Vec::new();
+            for stake in &application.stakes {
+                // a staker's stakes on an application are a single position
+                if !positions.contains(&stake.staker.as_str()) {
+                    positions.push(&stake.staker);
+                    result.crank_rewards += job.crank_reward;
+                }
+                let paid = if hired {
+                    let premium = stake.reward - stake.amount;
+                    let share = if stake.referred {
+                        (premium as u128 * job.referral_share_bps as u128 / MAX_BPS as u128) as u64
+                    } else {
+                        0
+                    };
+                    liabilities += premium;
+                    result.referral_shares += share;
+                    stake.reward - share
+                } else {
+                    let haircut = (stake.amount as u128 * job.rejection_haircut_bps as u128
+                        / MAX_BPS as u128) as u64;
+                    forfeited += haircut;
+                    stake.amount - haircut
+                };
+                let totals = stakers.entry(&stake.staker).or_default();
+                totals.0 += stake.amount;
+                totals.1 += paid;
+            }
+        }
+
+        let funds = job.reward_pool + forfeited;
+        result.liabilities += liabilities;
+        result.shortfall += liabilities.saturating_sub(funds);
+        result.treasury_income += funds.saturating_sub(liabilities);
+    }
+
+    result.staker_roi_bps = stakers
+        .into_iter()
+        .map(|(staker, (staked, paid))| {
+            let roi = (paid as i128 - staked as i128) * MAX_BPS as i128 / staked as i128;
+            (staker.to_string(), roi as i64)
+        })
+        .collect();
+
+    result
+}
+
+/// Prices the scenario once and settles it `runs` times from `seed`, so the same seed always
+/// yields the same report.
+pub fn simulate(scenario: &Scenario, runs: u32, seed: u64) -> ScenarioReport {
+    let (jobs, rejected_stakes) = price_scenario(scenario);
+    let mut rng = SplitMix64::new(seed);
+    let runs = runs.max(1);
+
+    let mut liabilities = Vec::with_capacity(runs as usize);
+    let mut shortfall = Vec::with_capacity(runs as usize);
+    let mut treasury_income = Vec::with_capacity(runs as usize);
+    let mut referral_shares = Vec::with_capacity(runs as usize);
+    let mut crank_rewards = Vec::with_capacity(runs as usize);
+    let mut staker_roi_bps = Vec::new();
+    for _ in 0..runs {
+        let result = run_once(&jobs, &mut rng);
+        liabilities.push(result.liabilities as f64);
+        shortfall.push(result.shortfall as f64);
+        treasury_income.push(result.treasury_income as f64);
+        referral_shares.push(result.referral_shares as f64);
+        crank_rewards.push(result.crank_rewards as f64);
+        staker_roi_bps.extend(result.staker_roi_bps.values().map(|roi| *roi as f64));
+    }
+
+    let short_runs = shortfall.iter().filter(|amount| **amount > 0.0).count();
+    let losing_stakers = staker_roi_bps.iter().filter(|roi| **roi < 0.0).count();
+    ScenarioReport {
+        name: scenario.name.clone(),
+        runs,
+        seed,
+        rejected_stakes,
+        liabilities: Summary::of(&mut liabilities),
+        shortfall: Summary::of(&mut shortfall),
+        shortfall_probability: short_runs as f64 / runs as f64,
+        treasury_income: Summary::of(&mut treasury_income),
+        referral_shares: Summary::of(&mut referral_shares),
+        crank_rewards: Summary::of(&mut crank_rewards),
+        losing_staker_share: if staker_roi_bps.is_empty() {
+            0.0
+        } else {
+            losing_stakers as f64 / staker_roi_bps.len() as f64
+        },
+        staker_roi_bps: Summary::of(&mut staker_roi_bps),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scenario::{ApplicationScenario, JobScenario};
+
+    fn stake(staker: &str, at: i64, amount: u64) -> StakeArrival {
+        StakeArrival {
+            staker: staker.to_string(),
+            at,
+            amount,
+            referred: false,
+        }
+    }
+
+    fn scenario(outcome: Outcome, reward_pool: u64, rejection_haircut_bps: u16) -> Scenario {
+        Scenario {
+            name: "test".to_string(),
+            runs: None,
+            seed: None,
+            referral_share_bps: 0,
+            crank_reward: 0,
+            jobs: vec![JobScenario {
+                max_amount_per_application: 300,
+                reward_half_life: 0,
+                rejection_haircut_bps,
+                reward_pool,
+                applications: vec![ApplicationScenario {
+                    outcome,
+                    // Out of order on purpose, stakes are priced by arrival.
+                    stakes: vec![
+                        stake("bob", 20, 100),
+                        stake("alice", 10, 100),
+                        stake("carol", 30, 100),
+                    ],
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn prices_stakes_in_arrival_order() {
+        let (jobs, rejected_stakes) = price_scenario(&scenario(Outcome::Hired, 0, 0));
+
+        // The third stake would fill the application, which `record_stake` refuses.
+        assert_eq!(rejected_stakes, 1);
+        assert_eq!(
+            jobs[0].applications[0].stakes,
+            vec![
+                PricedStake {
+                    staker: "alice".to_string(),
+                    amount: 100,
+                    reward: 300,
+                    referred: false,
+                },
+                PricedStake {
+                    staker: "bob".to_string(),
+                    amount: 100,
+                    reward: 200,
+                    referred: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn hires_run_short_of_a_small_pool() {
+        let (jobs, _) = price_scenario(&scenario(Outcome::Hired, 250, 0));
+        let result = run_once(&jobs, &mut SplitMix64::new(0));
+
+        assert_eq!(result.liabilities, 300);
+        assert_eq!(result.shortfall, 50);
+        assert_eq!(result.treasury_income, 0);
+        assert_eq!(result.staker_roi_bps["alice"], 20_000);
+        assert_eq!(result.staker_roi_bps["bob"], 10_000);
+    }
+
+    #[test]
+    fn rejections_keep_the_pool_and_the_haircuts() {
+        let (jobs, _) = price_scenario(&scenario(Outcome::Rejected, 250, 1_000));
+        let result = run_once(&jobs, &mut SplitMix64::new(0));
+
+        assert_eq!(result.liabilities, 0);
+        assert_eq!(result.shortfall, 0);
+        assert_eq!(result.treasury_income, 270);
+        assert_eq!(result.staker_roi_bps["alice"], -1_000);
+    }
+
+    #[test]
+    fn referrers_and_cranks_are_paid() {
+        let mut scenario = scenario(Outcome::Hired, 1_000, 0);
+        scenario.referral_share_bps = 1_000;
+        scenario.crank_reward = 5;
+        scenario.jobs[0].applications[0].stakes[1].referred = true;
+        let (jobs, _) = price_scenario(&scenario);
+        let result = run_once(&jobs, &mut SplitMix64::new(0));
+
+        // alice's share comes out of her premium, the escrow owes the same
+        assert_eq!(result.liabilities, 300);
+        assert_eq!(result.referral_shares, 20);
+        assert_eq!(result.staker_roi_bps["alice"], 18_000);
+        assert_eq!(result.staker_roi_bps["bob"], 10_000);
+        // the refused stake of carol never made a position
+        assert_eq!(result.crank_rewards, 10);
+    }
+
+    #[test]
+    fn runs_are_reproducible_from_the_seed() {
+        let scenario = scenario(Outcome::HireProbability(0.5), 250, 1_000);
+
+        let report = simulate(&scenario, 200, 9);
+        assert_eq!(report, simulate(&scenario, 200, 9));
+        assert!(report.shortfall_probability > 0.3 && report.shortfall_probability < 0.7);
+        assert_eq!(report.shortfall.max, 50.0);
+        assert_eq!(report.treasury_income.max, 270.0);
+    }
+}
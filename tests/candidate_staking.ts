@@ -8,6 +8,8 @@ import { v4 as uuidv4 } from "uuid";
 const assert = require("assert");
 import * as spl from "@solana/spl-token";
 import bs58 from "bs58";
+import { createHash } from "crypto";
+import * as statusHistory from "../client/status_history";
 
 describe("candidate_staking", () => {
   // Configure the client to use the local cluster.
@@ -37,6 +39,8 @@ describe("candidate_staking", () => {
   let initialMintAmount = 100000000;
   const stakeAmount = 4000;
   const maxAmountPerApplication = 10000;
+  const rewardHalfLife = 0; // seconds, 0 disables the time decay of rewards
+  const maxHires = 0; // 0 lets a job hire any number of applications
 
   if (provider.connection.rpcEndpoint == "http://localhost:8899") {
     alice = anchor.web3.Keypair.generate(); // HR
@@ -283,6 +287,76 @@ describe("candidate_staking", () => {
 
     return { candidatePDA, candidateBump };
   };
+  const getPositionPDA = async (
+    candidatePDA: anchor.web3.PublicKey,
+    index: anchor.BN
+  ) => {
+    const [positionPDA, positionBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          Buffer.from("position"),
+          candidatePDA.toBuffer(),
+          index.toArrayLike(Buffer, "le", 8),
+        ],
+        candidateStakingProgram.programId
+      );
+
+    return { positionPDA, positionBump };
+  };
+  const getNextPositionPDA = async (candidatePDA: anchor.web3.PublicKey) => {
+    const candidateState =
+      await candidateStakingProgram.account.candidateParameter.fetchNullable(
+        candidatePDA
+      );
+    const positionCount = candidateState
+      ? candidateState.positionCount
+      : new anchor.BN(0);
+
+    return await getPositionPDA(candidatePDA, positionCount);
+  };
+  const getStakerProfilePDA = async (userAccount: anchor.web3.PublicKey) => {
+    const [stakerProfilePDA, stakerProfileBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("profile"), userAccount.toBuffer()],
+        candidateStakingProgram.programId
+      );
+
+    return { stakerProfilePDA, stakerProfileBump };
+  };
+  const getStakerApprovalPDA = async (userAccount: anchor.web3.PublicKey) => {
+    const [stakerApprovalPDA, stakerApprovalBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("stakerapproval"), userAccount.toBuffer()],
+        generalProgram.programId
+      );
+
+    return { stakerApprovalPDA, stakerApprovalBump };
+  };
+  const getReferrerPDA = async (referrer: anchor.web3.PublicKey) => {
+    const [referrerPDA, referrerBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("referrer"), referrer.toBuffer()],
+        candidateStakingProgram.programId
+      );
+    const [referralVaultPDA, referralVaultBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("referralvault"), referrer.toBuffer()],
+        candidateStakingProgram.programId
+      );
+
+    return { referrerPDA, referrerBump, referralVaultPDA, referralVaultBump };
+  };
+  // The referral accounts a payout of the position of `user` expects.
+  const getPositionReferrerPDA = async (
+    candidatePDA: anchor.web3.PublicKey
+  ) => {
+    const candidateState =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+
+    return await getReferrerPDA(candidateState.referrer);
+  };
   const getWalletPDA = async (jobAdId: String) => {
     const [walletPDA, walletBump] =
       await anchor.web3.PublicKey.findProgramAddress(
@@ -300,27 +374,35 @@ describe("candidate_staking", () => {
   const changeApplicationStatus = async (
     jobAdId: string,
     applicationId: string,
-    status: any
+    status: any,
+    reasonCode: number = 0,
+    evidenceHash: number[] = new Array(32).fill(0)
   ) => {
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
+    const { statusChangePDA } = await getNextStatusChangePDA(applicationPDA);
+
     const tx = await applicationProgram.methods
       .updateStatus(
         applicationId,
-        applicationBump,
         jobAdId,
-        jobFactoryBump,
-        status
+        status,
+        reasonCode,
+        evidenceHash
       )
       .accounts({
         baseAccount: applicationPDA,
         authority: admin.publicKey,
         jobAccount: jobFactoryPDA,
+        statusChange: statusChangePDA,
+        generalAccount: (await getGeneralPDA()).generalPDA,
+        generalProgram: generalProgram.programId,
         jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
         instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
       })
       .signers([admin])
@@ -330,28 +412,31 @@ describe("candidate_staking", () => {
   const allProgramInitialize = async (
     jobAdId: string,
     applicationId: string,
-    user: anchor.web3.Keypair
+    user: anchor.web3.Keypair,
+    jobMaxHires: number = maxHires,
+    gate: anchor.web3.PublicKey | null = null
   ) => {
-    const { candidatePDA, candidateBump } = await getCandidatePDA(
+    const { candidatePDA } = await getCandidatePDA(
       applicationId,
       user.publicKey
     );
 
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
-    const { generalPDA, generalBump } = await getGeneralPDA();
+    const { generalPDA } = await getGeneralPDA();
 
-    const { walletPDA, walletBump } = await getWalletPDA(jobAdId);
+    const { walletPDA } = await getWalletPDA(jobAdId);
     try {
       await jobProgram.methods
         .initialize(
           jobAdId,
-          generalBump,
-          new anchor.BN(maxAmountPerApplication)
+          new anchor.BN(maxAmountPerApplication),
+          new anchor.BN(rewardHalfLife),
+          jobMaxHires
         )
         .accounts({
           baseAccount: jobFactoryPDA,
@@ -368,14 +453,16 @@ describe("candidate_staking", () => {
         .initialize(
           jobAdId,
           applicationId,
-          generalBump,
-          new anchor.BN(maxAmountPerApplication)
+          new anchor.BN(maxAmountPerApplication),
+          bob.publicKey
         )
         .accounts({
           baseAccount: applicationPDA,
           authority: admin.publicKey,
           generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
           generalProgram: generalProgram.programId,
+          jobProgram: jobProgram.programId,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
         .signers([admin])
@@ -383,13 +470,16 @@ describe("candidate_staking", () => {
     } catch (error) {}
 
     await candidateStakingProgram.methods
-      .initialize(jobAdId, applicationId, jobFactoryBump)
+      .initialize(jobAdId, applicationId)
       .accounts({
         baseAccount: candidatePDA,
         jobAccount: jobFactoryPDA,
         escrowWalletState: walletPDA,
         tokenMint: USDCMint,
         authority: user.publicKey,
+        gate:
+          gate ??
+          (await getStakerApprovalPDA(user.publicKey)).stakerApprovalPDA,
         jobProgram: jobProgram.programId,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
@@ -404,37 +494,44 @@ describe("candidate_staking", () => {
     applicationId: string,
     amountToBeStaked: number,
     user: anchor.web3.Keypair,
-    userTokenAccount: anchor.web3.PublicKey
+    userTokenAccount: anchor.web3.PublicKey,
+    remainingAccounts: anchor.web3.AccountMeta[] = [],
+    referrer: anchor.web3.PublicKey | null = null,
+    gate: anchor.web3.PublicKey | null = null
   ) => {
-    const { candidatePDA, candidateBump } = await getCandidatePDA(
+    const { candidatePDA } = await getCandidatePDA(
       applicationId,
       user.publicKey
     );
 
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
-    const { generalPDA, generalBump } = await getGeneralPDA();
+    const { generalPDA } = await getGeneralPDA();
 
-    const { walletPDA, walletBump } = await getWalletPDA(jobAdId);
+    const { walletPDA } = await getWalletPDA(jobAdId);
+
+    const { positionPDA } = await getNextPositionPDA(candidatePDA);
 
     const tx = await candidateStakingProgram.methods
       .stake(
         jobAdId,
         applicationId,
-        candidateBump,
-        generalBump,
-        applicationBump,
-        jobFactoryBump,
-        walletBump,
-        new anchor.BN(amountToBeStaked)
+        new anchor.BN(amountToBeStaked),
+        referrer
       )
       .accounts({
         baseAccount: candidatePDA,
+        positionAccount: positionPDA,
+        stakerProfile: (await getStakerProfilePDA(user.publicKey))
+          .stakerProfilePDA,
         authority: user.publicKey,
+        gate:
+          gate ??
+          (await getStakerApprovalPDA(user.publicKey)).stakerApprovalPDA,
         tokenMint: USDCMint,
         generalAccount: generalPDA,
         jobAccount: jobFactoryPDA,
@@ -449,12 +546,173 @@ describe("candidate_staking", () => {
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
       })
+      .remainingAccounts(remainingAccounts)
+      .signers([user])
+      .rpc();
+  };
+
+  const getCommitmentPDAs = async (
+    applicationPDA: anchor.web3.PublicKey,
+    staker: anchor.web3.PublicKey
+  ) => {
+    const [commitQueuePDA] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from("commitqueue"), applicationPDA.toBuffer()],
+      candidateStakingProgram.programId
+    );
+    const [commitmentPDA] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from("commitment"), applicationPDA.toBuffer(), staker.toBuffer()],
+      candidateStakingProgram.programId
+    );
+
+    return { commitQueuePDA, commitmentPDA };
+  };
+
+  // sha256(amount || salt || staker), the amount as a little-endian u64
+  const commitmentHash = (
+    amount: number,
+    salt: Buffer,
+    staker: anchor.web3.PublicKey
+  ) =>
+    createHash("sha256")
+      .update(new anchor.BN(amount).toArrayLike(Buffer, "le", 8))
+      .update(salt)
+      .update(staker.toBuffer())
+      .digest();
+
+  const commitStakeFunction = async (
+    jobAdId: string,
+    applicationId: string,
+    amount: number,
+    salt: Buffer,
+    user: anchor.web3.Keypair
+  ) => {
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
+    const { applicationPDA } = await getApplicationPDA(applicationId);
+    const { commitQueuePDA, commitmentPDA } = await getCommitmentPDAs(
+      applicationPDA,
+      user.publicKey
+    );
+    const { candidatePDA } = await getCandidatePDA(
+      applicationId,
+      user.publicKey
+    );
+
+    await candidateStakingProgram.methods
+      .commitStake(jobAdId, applicationId, [
+        ...commitmentHash(amount, salt, user.publicKey),
+      ])
+      .accounts({
+        commitQueue: commitQueuePDA,
+        commitment: commitmentPDA,
+        baseAccount: candidatePDA,
+        authority: user.publicKey,
+        jobAccount: jobFactoryPDA,
+        applicationAccount: applicationPDA,
+        applicationProgram: applicationProgram.programId,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+  };
+
+  const revealStakeFunction = async (
+    jobAdId: string,
+    applicationId: string,
+    amount: number,
+    salt: Buffer,
+    user: anchor.web3.Keypair,
+    userTokenAccount: anchor.web3.PublicKey
+  ) => {
+    const { candidatePDA } = await getCandidatePDA(
+      applicationId,
+      user.publicKey
+    );
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
+    const { applicationPDA } = await getApplicationPDA(applicationId);
+    const { commitQueuePDA, commitmentPDA } = await getCommitmentPDAs(
+      applicationPDA,
+      user.publicKey
+    );
+
+    await candidateStakingProgram.methods
+      .revealStake(jobAdId, applicationId, new anchor.BN(amount), [...salt])
+      .accounts({
+        stake: {
+          baseAccount: candidatePDA,
+          positionAccount: (await getNextPositionPDA(candidatePDA)).positionPDA,
+          stakerProfile: (await getStakerProfilePDA(user.publicKey))
+            .stakerProfilePDA,
+          authority: user.publicKey,
+          gate: (await getStakerApprovalPDA(user.publicKey)).stakerApprovalPDA,
+          tokenMint: USDCMint,
+          generalAccount: (await getGeneralPDA()).generalPDA,
+          jobAccount: jobFactoryPDA,
+          applicationAccount: applicationPDA,
+          generalProgram: generalProgram.programId,
+          applicationProgram: applicationProgram.programId,
+          jobProgram: jobProgram.programId,
+          escrowWalletState: (await getWalletPDA(jobAdId)).walletPDA,
+          walletToWithdrawFrom: userTokenAccount,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        },
+        commitQueue: commitQueuePDA,
+        commitment: commitmentPDA,
+      })
       .signers([user])
       .rpc();
   };
 
+  const updateRevealWindow = async (jobAdId: string, revealWindow: number) =>
+    await jobProgram.methods
+      .updateRevealWindow(jobAdId, new anchor.BN(revealWindow))
+      .accounts({
+        jobAccount: (await getJobPDA(jobAdId)).jobFactoryPDA,
+        authority: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+
+  const getReceiptMintPDA = async (candidatePDA: anchor.web3.PublicKey) => {
+    const [receiptMintPDA, receiptMintBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("receipt"), candidatePDA.toBuffer()],
+        candidateStakingProgram.programId
+      );
+
+    return { receiptMintPDA, receiptMintBump };
+  };
+
+  const getStatusChangePDA = (
+    applicationPDA: anchor.web3.PublicKey,
+    index: number
+  ) =>
+    statusHistory.getStatusChangePDA(applicationProgram, applicationPDA, index);
+
+  const getNextStatusChangePDA = async (
+    applicationPDA: anchor.web3.PublicKey
+  ) => {
+    const applicationState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+
+    return getStatusChangePDA(
+      applicationPDA,
+      applicationState.statusChangeCount.toNumber()
+    );
+  };
+
+  const fetchStatusHistory = async (applicationId: string) => {
+    const { applicationPDA } = await getApplicationPDA(applicationId);
+    return statusHistory.fetchStatusHistory(applicationProgram, applicationPDA);
+  };
+
   const fundPoolWallet = async (jobAdId) => {
-    const { walletPDA, walletBump } = await getWalletPDA(jobAdId);
+    const { walletPDA } = await getWalletPDA(jobAdId);
 
     await spl.mintTo(
       provider.connection,
@@ -470,39 +728,48 @@ describe("candidate_staking", () => {
     jobAdId,
     applicationId,
     user: anchor.web3.Keypair,
-    userTokenAccount: anchor.web3.PublicKey
+    userTokenAccount: anchor.web3.PublicKey,
+    principalTokenAccount: anchor.web3.PublicKey = userTokenAccount,
+    referralVault?: anchor.web3.PublicKey
   ) => {
-    const { candidatePDA, candidateBump } = await getCandidatePDA(
+    const { candidatePDA } = await getCandidatePDA(
       applicationId,
       user.publicKey
     );
 
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
-    const { walletPDA, walletBump } = await getWalletPDA(jobAdId);
+    const { walletPDA } = await getWalletPDA(jobAdId);
+
+    const { referrerPDA, referralVaultPDA } = await getPositionReferrerPDA(
+      candidatePDA
+    );
 
     await candidateStakingProgram.methods
       .unstake(
-        candidateBump,
-        applicationBump,
-        walletBump,
         applicationId,
-        jobAdId,
-        jobFactoryBump
+        jobAdId
       )
       .accounts({
         baseAccount: candidatePDA,
+        stakerProfile: (await getStakerProfilePDA(user.publicKey))
+          .stakerProfilePDA,
+        referrerAccount: referrerPDA,
+        referralVault: referralVault ?? referralVaultPDA,
         jobAccount: jobFactoryPDA,
         authority: user.publicKey,
         tokenMint: USDCMint,
         applicationAccount: applicationPDA,
         applicationProgram: applicationProgram.programId,
+        generalAccount: (await getGeneralPDA()).generalPDA,
+        generalProgram: generalProgram.programId,
         escrowWalletState: walletPDA,
         walletToDepositTo: userTokenAccount,
+        principalWallet: principalTokenAccount,
         jobProgram: jobProgram.programId,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
@@ -524,7 +791,7 @@ describe("candidate_staking", () => {
   };
 
   it("Initializing General Program", async () => {
-    const { generalPDA, generalBump } = await getGeneralPDA();
+    const { generalPDA } = await getGeneralPDA();
 
     try {
       const tx = await generalProgram.methods
@@ -539,7 +806,7 @@ describe("candidate_staking", () => {
         .rpc();
     } catch (error) {
       const tx = await generalProgram.methods
-        .changeMint(generalBump)
+        .changeMint()
         .accounts({
           baseAccount: generalPDA,
           authority: admin.publicKey,
@@ -557,17 +824,18 @@ describe("candidate_staking", () => {
   });
 
   it("Initializing Job Program", async () => {
-    const { generalPDA, generalBump } = await getGeneralPDA();
+    const { generalPDA } = await getGeneralPDA();
 
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
     // creating job by the person who is not the authority which should throw an error
     try {
       const tx = await jobProgram.methods
         .initialize(
           jobAdId,
-          generalBump,
-          new anchor.BN(maxAmountPerApplication)
+          new anchor.BN(maxAmountPerApplication),
+          new anchor.BN(rewardHalfLife),
+          maxHires
         )
         .accounts({
           baseAccount: jobFactoryPDA,
@@ -585,7 +853,12 @@ describe("candidate_staking", () => {
     }
 
     const tx = await jobProgram.methods
-      .initialize(jobAdId, generalBump, new anchor.BN(maxAmountPerApplication))
+      .initialize(
+        jobAdId,
+        new anchor.BN(maxAmountPerApplication),
+        new anchor.BN(rewardHalfLife),
+        maxHires
+      )
       .accounts({
         baseAccount: jobFactoryPDA,
         authority: admin.publicKey,
@@ -601,8 +874,9 @@ describe("candidate_staking", () => {
       const tx = await jobProgram.methods
         .initialize(
           jobAdId,
-          generalBump,
-          new anchor.BN(maxAmountPerApplication)
+          new anchor.BN(maxAmountPerApplication),
+          new anchor.BN(rewardHalfLife),
+          maxHires
         )
         .accounts({
           baseAccount: jobFactoryPDA,
@@ -640,9 +914,11 @@ describe("candidate_staking", () => {
   });
 
   it("Initializing Application Program", async () => {
-    const { generalPDA, generalBump } = await getGeneralPDA();
+    const { generalPDA } = await getGeneralPDA();
 
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
+
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
@@ -652,14 +928,16 @@ describe("candidate_staking", () => {
         .initialize(
           jobAdId,
           applicationId,
-          generalBump,
-          new anchor.BN(maxAmountPerApplication)
+          new anchor.BN(maxAmountPerApplication),
+          bob.publicKey
         )
         .accounts({
           baseAccount: applicationPDA,
           authority: alice.publicKey,
           generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
           generalProgram: generalProgram.programId,
+          jobProgram: jobProgram.programId,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
         .signers([alice])
@@ -674,14 +952,16 @@ describe("candidate_staking", () => {
       .initialize(
         jobAdId,
         applicationId,
-        generalBump,
-        new anchor.BN(maxAmountPerApplication)
+        new anchor.BN(maxAmountPerApplication),
+        bob.publicKey
       )
       .accounts({
         baseAccount: applicationPDA,
         authority: admin.publicKey,
         generalAccount: generalPDA,
+        jobAccount: jobFactoryPDA,
         generalProgram: generalProgram.programId,
+        jobProgram: jobProgram.programId,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
       .signers([admin])
@@ -693,14 +973,16 @@ describe("candidate_staking", () => {
         .initialize(
           jobAdId,
           applicationId,
-          generalBump,
-          new anchor.BN(maxAmountPerApplication)
+          new anchor.BN(maxAmountPerApplication),
+          bob.publicKey
         )
         .accounts({
           baseAccount: applicationPDA,
           authority: admin.publicKey,
           generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
           generalProgram: generalProgram.programId,
+          jobProgram: jobProgram.programId,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
         .signers([admin])
@@ -725,24 +1007,25 @@ describe("candidate_staking", () => {
   });
 
   it("intialize candidate_staking program", async () => {
-    const { candidatePDA, candidateBump } = await getCandidatePDA(
+    const { candidatePDA } = await getCandidatePDA(
       applicationId,
       cas.publicKey
     );
 
-    const { walletPDA, walletBump } = await getWalletPDA(jobAdId);
+    const { walletPDA } = await getWalletPDA(jobAdId);
 
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
     try {
       const tx = await candidateStakingProgram.methods
-        .initialize(jobAdId, applicationId, jobFactoryBump)
+        .initialize(jobAdId, applicationId)
         .accounts({
           baseAccount: candidatePDA,
           jobAccount: jobFactoryPDA,
           escrowWalletState: walletPDA,
           tokenMint: USDCMint,
           authority: cas.publicKey,
+          gate: (await getStakerApprovalPDA(cas.publicKey)).stakerApprovalPDA,
           jobProgram: jobProgram.programId,
           systemProgram: anchor.web3.SystemProgram.programId,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
@@ -765,7 +1048,7 @@ describe("candidate_staking", () => {
   });
 
   it("Stakes token", async () => {
-    const { candidatePDA, candidateBump } = await getCandidatePDA(
+    const { candidatePDA } = await getCandidatePDA(
       applicationId,
       cas.publicKey
     );
@@ -798,6 +1081,19 @@ describe("candidate_staking", () => {
     );
 
     assert.equal(_casTokenWallet.amount, initialMintAmount - stakeAmount);
+
+    // every stake leaves a position record behind
+    const { positionPDA } = await getPositionPDA(candidatePDA, new anchor.BN(0));
+    const position =
+      await candidateStakingProgram.account.stakePosition.fetch(positionPDA);
+
+    assert.equal(state.positionCount.toNumber(), 1);
+    assert.equal(position.candidate.toBase58(), candidatePDA.toBase58());
+    assert.equal(position.amount.toNumber(), stakeAmount);
+    assert.equal(
+      position.rewardAmount.toNumber(),
+      state.rewardAmount.toNumber()
+    );
   });
 
   it("Staking on application and job which does not exist, should fail", async () => {
@@ -852,7 +1148,7 @@ describe("candidate_staking", () => {
   });
 
   it("updates application status", async () => {
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
@@ -893,23 +1189,23 @@ describe("candidate_staking", () => {
         casTokenAccount
       );
     } catch (error) {
-      assert.equal(error.error.errorCode.code, "StatusNotPending");
+      assert.equal(error.error.errorCode.code, "StakingClosed");
     }
   });
 
   it("gets reward if selected or initial if not", async () => {
-    const { candidatePDA, candidateBump } = await getCandidatePDA(
+    const { candidatePDA } = await getCandidatePDA(
       applicationId,
       cas.publicKey
     );
 
-    const { jobFactoryPDA, jobFactoryBump } = await getJobPDA(jobAdId);
+    const { jobFactoryPDA } = await getJobPDA(jobAdId);
 
-    const { applicationPDA, applicationBump } = await getApplicationPDA(
+    const { applicationPDA } = await getApplicationPDA(
       applicationId
     );
 
-    const { walletPDA, walletBump } = await getWalletPDA(jobAdId);
+    const { walletPDA } = await getWalletPDA(jobAdId);
 
     const candidateState =
       await candidateStakingProgram.account.candidateParameter.fetch(
@@ -969,7 +1265,7 @@ describe("candidate_staking", () => {
 
     try {
       const tx1 = await jobProgram.methods
-        .unstake(jobAdId, jobFactoryBump, walletBump, new anchor.BN(10))
+        .unstake(jobAdId, new anchor.BN(10))
         .accounts({
           jobAccount: jobFactoryPDA,
           tokenMint: USDCMint,
@@ -1386,4 +1682,2490 @@ describe("candidate_staking", () => {
     // console.log(aliceTokenAccountAfter.amount - aliceTokenAccountBefore.amount);
 
   });
+
+  it("Receipts can be transferred and redeemed by their holder", async () => {
+    const jobAdIdReceipt = uuidv4();
+    const applicationIdReceipt = uuidv4();
+    const receiptAmount = 2000;
+    const receiptReward = 6000; // the whole amount lies in tier 1
+
+    await allProgramInitialize(jobAdIdReceipt, applicationIdReceipt, alice);
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdReceipt,
+      alice.publicKey
+    );
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdReceipt);
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdReceipt
+    );
+    const { walletPDA } = await getWalletPDA(jobAdIdReceipt);
+    const { receiptMintPDA } = await getReceiptMintPDA(candidatePDA);
+
+    await candidateStakingProgram.methods
+      .enableReceipts(
+        jobAdIdReceipt,
+        applicationIdReceipt
+      )
+      .accounts({
+        baseAccount: candidatePDA,
+        receiptMint: receiptMintPDA,
+        jobAccount: jobFactoryPDA,
+        tokenMint: USDCMint,
+        authority: alice.publicKey,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+
+    const aliceReceiptAccount = await spl.createAccount(
+      provider.connection,
+      alice,
+      receiptMintPDA,
+      alice.publicKey
+    );
+    const casReceiptAccount = await spl.createAccount(
+      provider.connection,
+      cas,
+      receiptMintPDA,
+      cas.publicKey
+    );
+
+    await stakeAmountFunction(
+      jobAdIdReceipt,
+      applicationIdReceipt,
+      receiptAmount,
+      alice,
+      aliceTokenAccount,
+      [
+        { pubkey: receiptMintPDA, isWritable: true, isSigner: false },
+        { pubkey: aliceReceiptAccount, isWritable: true, isSigner: false },
+      ]
+    );
+
+    let aliceReceipts = await spl.getAccount(
+      provider.connection,
+      aliceReceiptAccount
+    );
+    assert.equal(aliceReceipts.amount, receiptAmount);
+
+    await fundPoolWallet(jobAdIdReceipt);
+    await changeApplicationStatus(jobAdIdReceipt, applicationIdReceipt, {
+      selected: {},
+    });
+
+    // the original staker can't unstake, the receipts are the claim
+    try {
+      await unstakeFunction(
+        jobAdIdReceipt,
+        applicationIdReceipt,
+        alice,
+        aliceTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ReceiptRequired");
+    }
+
+    await spl.transfer(
+      provider.connection,
+      alice,
+      aliceReceiptAccount,
+      casReceiptAccount,
+      alice,
+      receiptAmount
+    );
+
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+
+    await candidateStakingProgram.methods
+      .redeemReceipt(
+        applicationIdReceipt,
+        jobAdIdReceipt,
+        new anchor.BN(receiptAmount)
+      )
+      .accounts({
+        baseAccount: candidatePDA,
+        receiptMint: receiptMintPDA,
+        stakerProfile: (await getStakerProfilePDA(alice.publicKey))
+          .stakerProfilePDA,
+        referrerAccount: (await getPositionReferrerPDA(candidatePDA))
+          .referrerPDA,
+        referralVault: (await getPositionReferrerPDA(candidatePDA))
+          .referralVaultPDA,
+        receiptTokenAccount: casReceiptAccount,
+        jobAccount: jobFactoryPDA,
+        authority: cas.publicKey,
+        tokenMint: USDCMint,
+        applicationAccount: applicationPDA,
+        applicationProgram: applicationProgram.programId,
+        generalAccount: (await getGeneralPDA()).generalPDA,
+        generalProgram: generalProgram.programId,
+        escrowWalletState: walletPDA,
+        walletToDepositTo: casTokenAccount,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+      })
+      .signers([cas])
+      .rpc();
+
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    assert.equal(
+      casTokenAccountAfter.amount - casTokenAccountBefore.amount,
+      receiptReward
+    );
+  });
+
+  it("Stakes on behalf of another wallet and splits the payout", async () => {
+    const jobAdIdDelegated = uuidv4();
+    const applicationIdDelegated = uuidv4();
+    const delegatedAmount = 2000;
+    const delegatedReward = 6000; // the whole amount lies in tier 1
+
+    await allProgramInitialize(jobAdIdDelegated, applicationIdDelegated, alice);
+
+    const danTokenAccount = await spl.createAccount(
+      provider.connection,
+      dan,
+      USDCMint,
+      dan.publicKey
+    );
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdDelegated,
+      dan.publicKey
+    );
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdDelegated);
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdDelegated
+    );
+    const { generalPDA } = await getGeneralPDA();
+    const { walletPDA } = await getWalletPDA(jobAdIdDelegated);
+    const { positionPDA } = await getNextPositionPDA(candidatePDA);
+
+    // alice funds the stake, dan owns the position and the principal goes back to alice
+    await candidateStakingProgram.methods
+      .stakeFor(
+        jobAdIdDelegated,
+        applicationIdDelegated,
+        dan.publicKey,
+        new anchor.BN(delegatedAmount),
+        true
+      )
+      .accounts({
+        baseAccount: candidatePDA,
+        positionAccount: positionPDA,
+        stakerProfile: (await getStakerProfilePDA(dan.publicKey))
+          .stakerProfilePDA,
+        payer: alice.publicKey,
+        beneficiaryWallet: dan.publicKey,
+        gate: (await getStakerApprovalPDA(dan.publicKey)).stakerApprovalPDA,
+        tokenMint: USDCMint,
+        generalAccount: generalPDA,
+        jobAccount: jobFactoryPDA,
+        applicationAccount: applicationPDA,
+        generalProgram: generalProgram.programId,
+        applicationProgram: applicationProgram.programId,
+        jobProgram: jobProgram.programId,
+        escrowWalletState: walletPDA,
+        walletToWithdrawFrom: aliceTokenAccount,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+      })
+      .signers([alice, dan])
+      .rpc();
+
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(state.authority.toBase58(), dan.publicKey.toBase58());
+    assert.equal(
+      state.principalRecipient.toBase58(),
+      alice.publicKey.toBase58()
+    );
+
+    await fundPoolWallet(jobAdIdDelegated);
+    await changeApplicationStatus(jobAdIdDelegated, applicationIdDelegated, {
+      selected: {},
+    });
+
+    const aliceTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      aliceTokenAccount
+    );
+
+    await unstakeFunction(
+      jobAdIdDelegated,
+      applicationIdDelegated,
+      dan,
+      danTokenAccount,
+      aliceTokenAccount
+    );
+
+    const aliceTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      aliceTokenAccount
+    );
+    const danTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      danTokenAccount
+    );
+
+    assert.equal(
+      aliceTokenAccountAfter.amount - aliceTokenAccountBefore.amount,
+      delegatedAmount
+    );
+    assert.equal(
+      danTokenAccountAfter.amount,
+      delegatedReward - delegatedAmount
+    );
+  });
+
+  it("Settles every staker of an application in one batch", async () => {
+    const jobAdIdBatch = uuidv4();
+    const applicationIdBatch = uuidv4();
+    const batchAmount = 1000;
+    const crankReward = 10;
+
+    await allProgramInitialize(jobAdIdBatch, applicationIdBatch, alice);
+    await allProgramInitialize(jobAdIdBatch, applicationIdBatch, cas);
+    await stakeAmountFunction(
+      jobAdIdBatch,
+      applicationIdBatch,
+      batchAmount,
+      alice,
+      aliceTokenAccount
+    );
+    await stakeAmountFunction(
+      jobAdIdBatch,
+      applicationIdBatch,
+      batchAmount,
+      cas,
+      casTokenAccount
+    );
+    await changeApplicationStatus(jobAdIdBatch, applicationIdBatch, {
+      rejected: {},
+    });
+
+    const { generalPDA } = await getGeneralPDA();
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdBatch);
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdBatch
+    );
+    const { walletPDA } = await getWalletPDA(jobAdIdBatch);
+    const { candidatePDA: aliceCandidatePDA } = await getCandidatePDA(
+      applicationIdBatch,
+      alice.publicKey
+    );
+    const { candidatePDA: casCandidatePDA } = await getCandidatePDA(
+      applicationIdBatch,
+      cas.publicKey
+    );
+    const { stakerProfilePDA: aliceProfilePDA } = await getStakerProfilePDA(
+      alice.publicKey
+    );
+    const { stakerProfilePDA: casProfilePDA } = await getStakerProfilePDA(
+      cas.publicKey
+    );
+
+    await fundPoolWallet(jobAdIdBatch);
+    await generalProgram.methods
+      .updateCrankReward(new anchor.BN(crankReward))
+      .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+      .signers([admin])
+      .rpc();
+
+    // the crank is paid by its own treasury, funded apart from the escrows
+    const [crankTreasuryAuthorityPDA] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("cranktreasury")],
+        candidateStakingProgram.programId
+      );
+    const [crankTreasuryPDA] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from("cranktreasury"), USDCMint.toBuffer()],
+      candidateStakingProgram.programId
+    );
+    await candidateStakingProgram.methods
+      .initializeCrankTreasury()
+      .accounts({
+        crankTreasury: crankTreasuryPDA,
+        crankTreasuryAuthority: crankTreasuryAuthorityPDA,
+        payer: admin.publicKey,
+        tokenMint: USDCMint,
+        generalAccount: generalPDA,
+        generalProgram: generalProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([admin])
+      .rpc();
+    await spl.mintTo(
+      provider.connection,
+      admin,
+      USDCMint,
+      crankTreasuryPDA,
+      admin,
+      crankReward
+    );
+
+    const adminTokenAccount = await spl.createAccount(
+      provider.connection,
+      admin,
+      USDCMint,
+      admin.publicKey
+    );
+    const aliceTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      aliceTokenAccount
+    );
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    const escrowBefore = await spl.getAccount(provider.connection, walletPDA);
+
+    await candidateStakingProgram.methods
+      .settleBatch(
+        applicationIdBatch,
+        jobAdIdBatch
+      )
+      .accounts({
+        jobAccount: jobFactoryPDA,
+        cranker: admin.publicKey,
+        crankerWallet: adminTokenAccount,
+        crankTreasury: crankTreasuryPDA,
+        crankTreasuryAuthority: crankTreasuryAuthorityPDA,
+        tokenMint: USDCMint,
+        generalAccount: generalPDA,
+        applicationAccount: applicationPDA,
+        generalProgram: generalProgram.programId,
+        applicationProgram: applicationProgram.programId,
+        escrowWalletState: walletPDA,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+      })
+      .remainingAccounts([
+        { pubkey: aliceCandidatePDA, isWritable: true, isSigner: false },
+        { pubkey: aliceProfilePDA, isWritable: true, isSigner: false },
+        { pubkey: aliceTokenAccount, isWritable: true, isSigner: false },
+        { pubkey: casCandidatePDA, isWritable: true, isSigner: false },
+        { pubkey: casProfilePDA, isWritable: true, isSigner: false },
+        { pubkey: casTokenAccount, isWritable: true, isSigner: false },
+      ])
+      .signers([admin])
+      .rpc();
+
+    await generalProgram.methods
+      .updateCrankReward(new anchor.BN(0))
+      .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+      .signers([admin])
+      .rpc();
+
+    const aliceTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      aliceTokenAccount
+    );
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    const adminTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      adminTokenAccount
+    );
+
+    assert.equal(
+      aliceTokenAccountAfter.amount - aliceTokenAccountBefore.amount,
+      batchAmount
+    );
+    assert.equal(
+      casTokenAccountAfter.amount - casTokenAccountBefore.amount,
+      batchAmount
+    );
+    // two positions settled, but the treasury only had enough for one
+    assert.equal(adminTokenAccountAfter.amount, crankReward);
+    const escrowAfter = await spl.getAccount(provider.connection, walletPDA);
+    assert.equal(escrowBefore.amount - escrowAfter.amount, 2 * batchAmount);
+
+    // the positions are settled, so they can't be paid twice
+    try {
+      await unstakeFunction(
+        jobAdIdBatch,
+        applicationIdBatch,
+        alice,
+        aliceTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "AlreadyUnstaked");
+    }
+  });
+
+  it("Fills the job at max hires and refunds the pending applications", async () => {
+    const jobAdIdFilled = uuidv4();
+    const hiredApplicationId = uuidv4();
+    const pendingApplicationId = uuidv4();
+
+    await allProgramInitialize(jobAdIdFilled, hiredApplicationId, alice, 1);
+    await allProgramInitialize(jobAdIdFilled, pendingApplicationId, cas, 1);
+    await stakeAmountFunction(
+      jobAdIdFilled,
+      pendingApplicationId,
+      stakeAmount,
+      cas,
+      casTokenAccount
+    );
+
+    await changeApplicationStatus(jobAdIdFilled, hiredApplicationId, {
+      selected: {},
+    });
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdFilled);
+    const jobState = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(jobState.hires, 1);
+    assert.equal(jobState.isFilled, true);
+
+    try {
+      await changeApplicationStatus(jobAdIdFilled, pendingApplicationId, {
+        selected: {},
+      });
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "MaxHiresReached");
+    }
+
+    try {
+      await stakeAmountFunction(
+        jobAdIdFilled,
+        pendingApplicationId,
+        stakeAmount,
+        cas,
+        casTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "JobFilled");
+    }
+
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    await unstakeFunction(
+      jobAdIdFilled,
+      pendingApplicationId,
+      cas,
+      casTokenAccount
+    );
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+
+    assert.equal(
+      casTokenAccountAfter.amount - casTokenAccountBefore.amount,
+      stakeAmount
+    );
+  });
+
+  it("Rejects the other pending applications when the job is finalized", async () => {
+    const jobAdIdFinal = uuidv4();
+    const hiredApplicationId = uuidv4();
+    const otherApplicationIds = [uuidv4(), uuidv4()];
+
+    await allProgramInitialize(jobAdIdFinal, hiredApplicationId, alice);
+    for (const otherApplicationId of otherApplicationIds) {
+      await allProgramInitialize(jobAdIdFinal, otherApplicationId, alice);
+    }
+    await changeApplicationStatus(jobAdIdFinal, hiredApplicationId, {
+      selected: {},
+    });
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdFinal);
+    const { applicationPDA } = await getApplicationPDA(
+      hiredApplicationId
+    );
+    const otherApplicationPDAs = [];
+    const remainingAccounts = [];
+    for (const otherApplicationId of otherApplicationIds) {
+      const { applicationPDA } = await getApplicationPDA(otherApplicationId);
+      const { statusChangePDA } = await getNextStatusChangePDA(applicationPDA);
+      otherApplicationPDAs.push(applicationPDA);
+      remainingAccounts.push(
+        { pubkey: applicationPDA, isWritable: true, isSigner: false },
+        { pubkey: statusChangePDA, isWritable: true, isSigner: false }
+      );
+    }
+
+    await applicationProgram.methods
+      .finalizeJob(
+        hiredApplicationId,
+        jobAdIdFinal
+      )
+      .accounts({
+        baseAccount: applicationPDA,
+        authority: admin.publicKey,
+        jobAccount: jobFactoryPDA,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .remainingAccounts(remainingAccounts)
+      .signers([admin])
+      .rpc();
+
+    const hiredState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.deepEqual(hiredState.status, { selected: {} });
+    for (const otherApplicationPDA of otherApplicationPDAs) {
+      const otherState =
+        await applicationProgram.account.applicationParameter.fetch(
+          otherApplicationPDA
+        );
+      assert.deepEqual(otherState.status, { rejected: {} });
+      assert.equal(otherState.statusChangeCount.toNumber(), 1);
+    }
+  });
+
+  it("Candidate withdraws the application and stakers are refunded", async () => {
+    const jobAdIdWithdrawn = uuidv4();
+    const applicationIdWithdrawn = uuidv4();
+
+    await allProgramInitialize(jobAdIdWithdrawn, applicationIdWithdrawn, cas);
+    await stakeAmountFunction(
+      jobAdIdWithdrawn,
+      applicationIdWithdrawn,
+      stakeAmount,
+      cas,
+      casTokenAccount
+    );
+
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdWithdrawn
+    );
+    const { statusChangePDA } = await getNextStatusChangePDA(applicationPDA);
+
+    // only the candidate of the application can withdraw it
+    try {
+      await applicationProgram.methods
+        .withdrawApplication(applicationIdWithdrawn)
+        .accounts({
+          baseAccount: applicationPDA,
+          candidate: alice.publicKey,
+          statusChange: statusChangePDA,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([alice])
+        .rpc();
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ConstraintHasOne");
+    }
+
+    await applicationProgram.methods
+      .withdrawApplication(applicationIdWithdrawn)
+      .accounts({
+        baseAccount: applicationPDA,
+        candidate: bob.publicKey,
+        statusChange: statusChangePDA,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([bob])
+      .rpc();
+
+    const applicationState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.deepEqual(applicationState.status, { withdrawn: {} });
+
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    await unstakeFunction(
+      jobAdIdWithdrawn,
+      applicationIdWithdrawn,
+      cas,
+      casTokenAccount
+    );
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+
+    assert.equal(
+      casTokenAccountAfter.amount - casTokenAccountBefore.amount,
+      stakeAmount
+    );
+  });
+
+  it("Opens staking at a status through the job's status policy", async () => {
+    const jobAdIdPolicy = uuidv4();
+    const applicationIdPolicy = uuidv4();
+    const offeredStatusIndex = 7; // index of JobStatus::Offered
+
+    await allProgramInitialize(jobAdIdPolicy, applicationIdPolicy, cas);
+    await changeApplicationStatus(jobAdIdPolicy, applicationIdPolicy, {
+      offered: {},
+    });
+
+    try {
+      await stakeAmountFunction(
+        jobAdIdPolicy,
+        applicationIdPolicy,
+        stakeAmount,
+        cas,
+        casTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakingClosed");
+    }
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdPolicy);
+    await jobProgram.methods
+      .updateStatusPolicy(jobAdIdPolicy, offeredStatusIndex, {
+        allowStake: true,
+        allowWithdraw: false,
+        multiplierBps: 5000,
+      })
+      .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+      .signers([admin])
+      .rpc();
+
+    await stakeAmountFunction(
+      jobAdIdPolicy,
+      applicationIdPolicy,
+      stakeAmount,
+      cas,
+      casTokenAccount
+    );
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdPolicy,
+      cas.publicKey
+    );
+    const candidateState =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    // 3333 in tier 1 and 667 in tier 2, with only half of the premium
+    const premium = 3333 * 2 + 667 * 1;
+    assert.equal(
+      candidateState.rewardAmount.toNumber(),
+      stakeAmount + Math.floor(premium / 2)
+    );
+  });
+
+  it("Keeps the status history of an application", async () => {
+    const jobAdIdHistory = uuidv4();
+    const applicationIdHistory = uuidv4();
+    const evidenceHash = Array.from(
+      Buffer.from(anchor.utils.sha256.hash("signed offer letter"), "hex")
+    );
+
+    await allProgramInitialize(jobAdIdHistory, applicationIdHistory, cas);
+    await changeApplicationStatus(
+      jobAdIdHistory,
+      applicationIdHistory,
+      { interviewing: {} },
+      10
+    );
+    await changeApplicationStatus(
+      jobAdIdHistory,
+      applicationIdHistory,
+      { selected: {} },
+      20,
+      evidenceHash
+    );
+
+    const history = await fetchStatusHistory(applicationIdHistory);
+
+    assert.equal(history.length, 2);
+    assert.deepEqual(history[0].previousStatus, { pending: {} });
+    assert.deepEqual(history[0].newStatus, { interviewing: {} });
+    assert.equal(history[0].reasonCode, 10);
+    assert.deepEqual(history[1].previousStatus, { interviewing: {} });
+    assert.deepEqual(history[1].newStatus, { selected: {} });
+    assert.equal(history[1].reasonCode, 20);
+    assert.equal(history[1].authority.toBase58(), admin.publicKey.toBase58());
+    assert.deepEqual(history[1].evidenceHash, evidenceHash);
+  });
+
+  it("Freezes payouts while a status is challenged and returns the bond once reverted", async () => {
+    const jobAdIdDispute = uuidv4();
+    const applicationIdDispute = uuidv4();
+    const disputeWindow = 1000; // seconds
+    const challengeBond = 100;
+
+    const { generalPDA } = await getGeneralPDA();
+    const setDisputeConfig = async (
+      resolver: anchor.web3.PublicKey,
+      window: number,
+      bond: number
+    ) => {
+      await generalProgram.methods
+        .updateDisputeConfig(
+          resolver,
+          new anchor.BN(window),
+          new anchor.BN(bond)
+        )
+        .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    };
+
+    await setDisputeConfig(dan.publicKey, disputeWindow, challengeBond);
+
+    await allProgramInitialize(jobAdIdDispute, applicationIdDispute, cas);
+    await stakeAmountFunction(
+      jobAdIdDispute,
+      applicationIdDispute,
+      stakeAmount,
+      cas,
+      casTokenAccount
+    );
+    await changeApplicationStatus(jobAdIdDispute, applicationIdDispute, {
+      rejected: {},
+    });
+
+    try {
+      await unstakeFunction(
+        jobAdIdDispute,
+        applicationIdDispute,
+        cas,
+        casTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "DisputeWindowOpen");
+    }
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdDispute,
+      cas.publicKey
+    );
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdDispute);
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdDispute
+    );
+    const { walletPDA } = await getWalletPDA(jobAdIdDispute);
+    const [challengePDA] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from("challenge"), applicationPDA.toBuffer()],
+      candidateStakingProgram.programId
+    );
+    const [challengeVaultPDA] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from("challengevault"), applicationPDA.toBuffer()],
+      candidateStakingProgram.programId
+    );
+
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+
+    await candidateStakingProgram.methods
+      .challengeStatus(
+        jobAdIdDispute,
+        applicationIdDispute
+      )
+      .accounts({
+        baseAccount: candidatePDA,
+        challenge: challengePDA,
+        challengeVault: challengeVaultPDA,
+        authority: cas.publicKey,
+        walletToWithdrawFrom: casTokenAccount,
+        tokenMint: USDCMint,
+        generalAccount: generalPDA,
+        jobAccount: jobFactoryPDA,
+        applicationAccount: applicationPDA,
+        generalProgram: generalProgram.programId,
+        applicationProgram: applicationProgram.programId,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+      })
+      .signers([cas])
+      .rpc();
+
+    let applicationState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.deepEqual(applicationState.challenge, { open: {} });
+
+    const { statusChangePDA: lastStatusChangePDA } = await getStatusChangePDA(
+      applicationPDA,
+      applicationState.statusChangeCount.toNumber() - 1
+    );
+    const { statusChangePDA } = await getNextStatusChangePDA(applicationPDA);
+
+    await applicationProgram.methods
+      .resolveChallenge(
+        applicationIdDispute,
+        jobAdIdDispute,
+        false
+      )
+      .accounts({
+        baseAccount: applicationPDA,
+        resolver: dan.publicKey,
+        generalAccount: generalPDA,
+        jobAccount: jobFactoryPDA,
+        lastStatusChange: lastStatusChangePDA,
+        statusChange: statusChangePDA,
+        generalProgram: generalProgram.programId,
+        jobProgram: jobProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+      })
+      .signers([dan])
+      .rpc();
+
+    applicationState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.deepEqual(applicationState.challenge, { reverted: {} });
+    assert.deepEqual(applicationState.status, { pending: {} });
+
+    await candidateStakingProgram.methods
+      .settleChallenge(
+        applicationIdDispute,
+        jobAdIdDispute
+      )
+      .accounts({
+        challenge: challengePDA,
+        challengeVault: challengeVaultPDA,
+        challenger: cas.publicKey,
+        challengerWallet: casTokenAccount,
+        tokenMint: USDCMint,
+        jobAccount: jobFactoryPDA,
+        applicationAccount: applicationPDA,
+        escrowWalletState: walletPDA,
+        applicationProgram: applicationProgram.programId,
+        jobProgram: jobProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    assert.equal(casTokenAccountAfter.amount, casTokenAccountBefore.amount);
+    assert.equal(
+      await provider.connection.getAccountInfo(challengePDA),
+      null
+    );
+
+    // a window without a resolver would freeze challenged payouts for good
+    try {
+      await setDisputeConfig(anchor.web3.PublicKey.default, disputeWindow, 0);
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "MissingResolver");
+    }
+
+    await setDisputeConfig(anchor.web3.PublicKey.default, 0, 0);
+  });
+
+  it("Selects an application only once enough approvers agree", async () => {
+    const jobAdIdApproval = uuidv4();
+    const applicationIdApproval = uuidv4();
+
+    const { generalPDA } = await getGeneralPDA();
+    const setApprovers = async (
+      approvers: anchor.web3.PublicKey[],
+      threshold: number
+    ) => {
+      await generalProgram.methods
+        .updateApprovers(approvers, threshold)
+        .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    };
+
+    await setApprovers([alice.publicKey, dan.publicKey], 2);
+    await allProgramInitialize(jobAdIdApproval, applicationIdApproval, cas);
+
+    try {
+      await changeApplicationStatus(jobAdIdApproval, applicationIdApproval, {
+        selected: {},
+      });
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ApprovalRequired");
+    }
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdApproval);
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdApproval
+    );
+    const [proposalPDA] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from("statusproposal"),
+        applicationPDA.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      applicationProgram.programId
+    );
+
+    const propose = async () => {
+      await applicationProgram.methods
+        .proposeStatus(
+          applicationIdApproval,
+          { selected: {} },
+          0,
+          new Array(32).fill(0)
+        )
+        .accounts({
+          baseAccount: applicationPDA,
+          authority: admin.publicKey,
+          proposal: proposalPDA,
+          generalAccount: generalPDA,
+          generalProgram: generalProgram.programId,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+    };
+    await propose();
+
+    const approve = async (approver: anchor.web3.Keypair) => {
+      await applicationProgram.methods
+        .approveStatus(applicationIdApproval)
+        .accounts({
+          baseAccount: applicationPDA,
+          proposal: proposalPDA,
+          approver: approver.publicKey,
+          generalAccount: generalPDA,
+          generalProgram: generalProgram.programId,
+        })
+        .signers([approver])
+        .rpc();
+    };
+    const { statusChangePDA } = await getNextStatusChangePDA(applicationPDA);
+    const execute = async () => {
+      await applicationProgram.methods
+        .executeStatus(
+          applicationIdApproval,
+          jobAdIdApproval
+        )
+        .accounts({
+          baseAccount: applicationPDA,
+          proposal: proposalPDA,
+          proposer: admin.publicKey,
+          executor: cas.publicKey,
+          generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
+          statusChange: statusChangePDA,
+          generalProgram: generalProgram.programId,
+          jobProgram: jobProgram.programId,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .signers([cas])
+        .rpc();
+    };
+
+    try {
+      await approve(bob);
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "NotAnApprover");
+    }
+
+    await approve(alice);
+    try {
+      await execute();
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ThresholdNotMet");
+    }
+
+    // replacing the set, even with the same approvers, voids the approvals so far
+    await setApprovers([dan.publicKey, alice.publicKey], 2);
+    try {
+      await approve(dan);
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ApproversChanged");
+    }
+    await applicationProgram.methods
+      .cancelStatusProposal(applicationIdApproval)
+      .accounts({
+        baseAccount: applicationPDA,
+        proposal: proposalPDA,
+        authority: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+    await propose();
+
+    await approve(alice);
+    await approve(dan);
+    await execute();
+
+    const applicationState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.deepEqual(applicationState.status, { selected: {} });
+    assert.equal(await provider.connection.getAccountInfo(proposalPDA), null);
+
+    const history = await fetchStatusHistory(applicationIdApproval);
+    assert.equal(history[0].authority.toBase58(), admin.publicKey.toBase58());
+
+    await setApprovers([], 0);
+  });
+
+  it("Applies a hiring outcome signed by the attester", async () => {
+    const jobAdIdAttested = uuidv4();
+    const applicationIdAttested = uuidv4();
+    const attester = anchor.web3.Keypair.generate();
+    const selectedStatusIndex = 2; // index of JobStatus::Selected
+
+    const { generalPDA } = await getGeneralPDA();
+    const setAttester = async (attesterKey: anchor.web3.PublicKey) => {
+      await generalProgram.methods
+        .updateAttester(attesterKey)
+        .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    };
+
+    await setAttester(attester.publicKey);
+    await allProgramInitialize(jobAdIdAttested, applicationIdAttested, cas);
+    // the outcome has to be newer than the creation of the application
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    const timestamp = await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    );
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdAttested);
+    const { applicationPDA } = await getApplicationPDA(
+      applicationIdAttested
+    );
+    const { statusChangePDA } = await getNextStatusChangePDA(applicationPDA);
+
+    const attestedStatusUpdate = (
+      signedStatusIndex: number,
+      signedJob: anchor.web3.PublicKey = jobFactoryPDA
+    ) =>
+      applicationProgram.methods
+        .updateStatusAttested(
+          applicationIdAttested,
+          jobAdIdAttested,
+          { selected: {} },
+          new anchor.BN(timestamp)
+        )
+        .accounts({
+          baseAccount: applicationPDA,
+          submitter: cas.publicKey,
+          generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
+          statusChange: statusChangePDA,
+          generalProgram: generalProgram.programId,
+          jobProgram: jobProgram.programId,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .preInstructions([
+          anchor.web3.Ed25519Program.createInstructionWithPrivateKey({
+            privateKey: attester.secretKey,
+            message: Buffer.concat([
+              Buffer.from("application-status-attestation"),
+              applicationProgram.programId.toBuffer(),
+              signedJob.toBuffer(),
+              Buffer.from(applicationIdAttested),
+              Buffer.from([signedStatusIndex]),
+              new anchor.BN(timestamp).toArrayLike(Buffer, "le", 8),
+            ]),
+          }),
+        ])
+        .signers([cas])
+        .rpc();
+
+    try {
+      // signed as rejected, submitted as selected
+      await attestedStatusUpdate(0);
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "InvalidAttestation");
+    }
+    try {
+      // signed for the application of another job
+      await attestedStatusUpdate(
+        selectedStatusIndex,
+        anchor.web3.Keypair.generate().publicKey
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "InvalidAttestation");
+    }
+
+    await attestedStatusUpdate(selectedStatusIndex);
+
+    const applicationState =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.deepEqual(applicationState.status, { selected: {} });
+    const history = await fetchStatusHistory(applicationIdAttested);
+    assert.equal(
+      history[0].authority.toBase58(),
+      attester.publicKey.toBase58()
+    );
+    assert.equal(history[0].reasonCode, 4);
+
+    await setAttester(anchor.web3.PublicKey.default);
+  });
+
+  it("Keeps the staker profile across applications", async () => {
+    const jobAdIdHired = uuidv4();
+    const applicationIdHired = uuidv4();
+    const jobAdIdRejected = uuidv4();
+    const applicationIdRejected = uuidv4();
+    const profileAmount = 1000;
+    const profileReward = 2000; // on top of the principal, the whole amount lies in tier 1
+
+    const { stakerProfilePDA } = await getStakerProfilePDA(cas.publicKey);
+    const before =
+      await candidateStakingProgram.account.stakerProfile.fetch(
+        stakerProfilePDA
+      );
+
+    await allProgramInitialize(jobAdIdHired, applicationIdHired, cas);
+    await allProgramInitialize(jobAdIdRejected, applicationIdRejected, cas);
+    await stakeAmountFunction(
+      jobAdIdHired,
+      applicationIdHired,
+      profileAmount,
+      cas,
+      casTokenAccount
+    );
+    await stakeAmountFunction(
+      jobAdIdRejected,
+      applicationIdRejected,
+      profileAmount,
+      cas,
+      casTokenAccount
+    );
+
+    let profile =
+      await candidateStakingProgram.account.stakerProfile.fetch(
+        stakerProfilePDA
+      );
+    assert.equal(profile.authority.toBase58(), cas.publicKey.toBase58());
+    assert.equal(
+      profile.totalStaked.sub(before.totalStaked).toNumber(),
+      2 * profileAmount
+    );
+    assert.equal(
+      profile.totalOutstanding.sub(before.totalOutstanding).toNumber(),
+      2 * profileAmount
+    );
+    assert.equal(
+      profile.openPositions.sub(before.openPositions).toNumber(),
+      2
+    );
+
+    await fundPoolWallet(jobAdIdHired);
+    await changeStatusAndUnstake(
+      jobAdIdHired,
+      applicationIdHired,
+      cas,
+      casTokenAccount
+    );
+    await changeApplicationStatus(jobAdIdRejected, applicationIdRejected, {
+      rejected: {},
+    });
+    await unstakeFunction(
+      jobAdIdRejected,
+      applicationIdRejected,
+      cas,
+      casTokenAccount
+    );
+
+    profile = await candidateStakingProgram.account.stakerProfile.fetch(
+      stakerProfilePDA
+    );
+    assert.equal(
+      profile.totalOutstanding.toNumber(),
+      before.totalOutstanding.toNumber()
+    );
+    assert.equal(
+      profile.openPositions.toNumber(),
+      before.openPositions.toNumber()
+    );
+    assert.equal(
+      profile.lifetimeRewards.sub(before.lifetimeRewards).toNumber(),
+      profileReward
+    );
+    assert.equal(profile.correctPicks.sub(before.correctPicks).toNumber(), 1);
+    assert.equal(
+      profile.incorrectPicks.sub(before.incorrectPicks).toNumber(),
+      1
+    );
+  });
+
+  it("Boosts the premium of stakers with a good track record", async () => {
+    const jobAdIdReputation = uuidv4();
+    const applicationIdReputation = uuidv4();
+    const reputationAmount = 1000;
+    const reputationPremium = 2000; // the whole amount lies in tier 1
+    const maxBoostBps = 5000;
+
+    const { generalPDA } = await getGeneralPDA();
+    const updateReputationConfig = async (reputation, minPickAmount = 0) =>
+      await generalProgram.methods
+        .updateReputationConfig(reputation, new anchor.BN(minPickAmount))
+        .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+
+    try {
+      await updateReputationConfig({
+        priorCorrect: 1,
+        priorIncorrect: 1,
+        maxBoostBps: 20000,
+      });
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "InvalidReputationConfig");
+    }
+    // a prior accuracy of 10% worth 10 picks
+    await updateReputationConfig({
+      priorCorrect: 1,
+      priorIncorrect: 9,
+      maxBoostBps,
+    });
+
+    const { stakerProfilePDA } = await getStakerProfilePDA(cas.publicKey);
+    const profile =
+      await candidateStakingProgram.account.stakerProfile.fetch(
+        stakerProfilePDA
+      );
+    const correctPicks = profile.correctPicks.toNumber();
+    const incorrectPicks = profile.incorrectPicks.toNumber();
+    const scoreBps = Math.floor(
+      ((correctPicks + 1) * 10000) / (correctPicks + incorrectPicks + 10)
+    );
+    const boostBps =
+      scoreBps > 1000 ? Math.floor((maxBoostBps * (scoreBps - 1000)) / 9000) : 0;
+    // cas picked the hires of the earlier tests
+    assert.ok(boostBps > 0);
+
+    await allProgramInitialize(jobAdIdReputation, applicationIdReputation, cas);
+    await stakeAmountFunction(
+      jobAdIdReputation,
+      applicationIdReputation,
+      reputationAmount,
+      cas,
+      casTokenAccount
+    );
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdReputation,
+      cas.publicKey
+    );
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(
+      state.rewardAmount.toNumber(),
+      reputationAmount +
+        Math.floor((reputationPremium * (10000 + boostBps)) / 10000)
+    );
+
+    await updateReputationConfig({
+      priorCorrect: 0,
+      priorIncorrect: 0,
+      maxBoostBps: 0,
+    });
+  });
+
+  it("Keeps a haircut of the stakes on rejected applications", async () => {
+    const jobAdIdHaircut = uuidv4();
+    const applicationIdHaircut = uuidv4();
+    const haircutAmount = 1000;
+    const haircutBps = 2500;
+
+    await allProgramInitialize(jobAdIdHaircut, applicationIdHaircut, cas);
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdHaircut);
+    const updateRejectionHaircut = async (bps) =>
+      await jobProgram.methods
+        .updateRejectionHaircut(jobAdIdHaircut, bps)
+        .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    await updateRejectionHaircut(haircutBps);
+
+    await stakeAmountFunction(
+      jobAdIdHaircut,
+      applicationIdHaircut,
+      haircutAmount,
+      cas,
+      casTokenAccount
+    );
+    // stakes keep the haircut they were made at
+    await updateRejectionHaircut(10000);
+    await changeApplicationStatus(jobAdIdHaircut, applicationIdHaircut, {
+      rejected: {},
+    });
+
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    await unstakeFunction(
+      jobAdIdHaircut,
+      applicationIdHaircut,
+      cas,
+      casTokenAccount
+    );
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+
+    const forfeited = (haircutAmount * haircutBps) / 10000;
+    assert.equal(
+      casTokenAccountAfter.amount - casTokenAccountBefore.amount,
+      haircutAmount - forfeited
+    );
+    const job = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(job.forfeitedAmount.toNumber(), forfeited);
+    // the forfeited principal stays in the escrow
+    const { walletPDA } = await getWalletPDA(jobAdIdHaircut);
+    let escrow = await spl.getAccount(provider.connection, walletPDA);
+    assert.equal(escrow.amount, forfeited);
+
+    // until the job authority claims it
+    const adminTokenAccount = (
+      await spl.getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        admin,
+        USDCMint,
+        admin.publicKey
+      )
+    ).address;
+    const claimForfeits = async (amount) =>
+      await jobProgram.methods
+        .claimForfeits(jobAdIdHaircut, new anchor.BN(amount))
+        .accounts({
+          jobAccount: jobFactoryPDA,
+          authority: admin.publicKey,
+          escrowWalletState: walletPDA,
+          walletToDepositTo: adminTokenAccount,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+    try {
+      await claimForfeits(forfeited + 1);
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ForfeitsExceeded");
+    }
+    const adminTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      adminTokenAccount
+    );
+    await claimForfeits(forfeited);
+    const adminTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      adminTokenAccount
+    );
+    assert.equal(
+      adminTokenAccountAfter.amount - adminTokenAccountBefore.amount,
+      forfeited
+    );
+    const jobAfterClaim = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(jobAfterClaim.forfeitedAmount.toNumber(), 0);
+    escrow = await spl.getAccount(provider.connection, walletPDA);
+    assert.equal(escrow.amount, 0);
+  });
+
+  it("Pays referrers a share of the rewards of the stakers they referred", async () => {
+    const jobAdIdReferral = uuidv4();
+    const applicationIdReferral = uuidv4();
+    const referralAmount = 1000;
+    const referralShareBps = 1000;
+
+    const { generalPDA } = await getGeneralPDA();
+    const updateReferralShare = async (referralShare: number) =>
+      await generalProgram.methods
+        .updateReferralShare(referralShare)
+        .accounts({ baseAccount: generalPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    await updateReferralShare(referralShareBps);
+
+    const { referrerPDA, referralVaultPDA } = await getReferrerPDA(
+      dan.publicKey
+    );
+    await candidateStakingProgram.methods
+      .registerReferrer()
+      .accounts({
+        referrerAccount: referrerPDA,
+        referralVault: referralVaultPDA,
+        referrer: dan.publicKey,
+        tokenMint: USDCMint,
+        generalAccount: generalPDA,
+        generalProgram: generalProgram.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([dan])
+      .rpc();
+
+    await allProgramInitialize(jobAdIdReferral, applicationIdReferral, cas);
+    await fundPoolWallet(jobAdIdReferral);
+
+    try {
+      await stakeAmountFunction(
+        jobAdIdReferral,
+        applicationIdReferral,
+        referralAmount,
+        cas,
+        casTokenAccount,
+        [],
+        cas.publicKey
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "SelfReferral");
+    }
+
+    await stakeAmountFunction(
+      jobAdIdReferral,
+      applicationIdReferral,
+      referralAmount,
+      cas,
+      casTokenAccount,
+      [],
+      dan.publicKey
+    );
+    try {
+      await stakeAmountFunction(
+        jobAdIdReferral,
+        applicationIdReferral,
+        referralAmount,
+        cas,
+        casTokenAccount,
+        [],
+        alice.publicKey
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ReferrerAlreadySet");
+    }
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdReferral,
+      cas.publicKey
+    );
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(state.referrer.toBase58(), dan.publicKey.toBase58());
+    const premium = state.rewardAmount.toNumber() - referralAmount;
+    const share = Math.floor((premium * referralShareBps) / 10000);
+
+    const { walletPDA } = await getWalletPDA(jobAdIdReferral);
+    const escrowBefore = await spl.getAccount(provider.connection, walletPDA);
+    const casTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    await changeApplicationStatus(jobAdIdReferral, applicationIdReferral, {
+      selected: {},
+    });
+
+    // the share only goes to the vault of the referrer, in the token of the job
+    const junkMint = await spl.createMint(
+      provider.connection,
+      admin,
+      admin.publicKey,
+      null,
+      0
+    );
+    const junkVault = await spl.createAccount(
+      provider.connection,
+      admin,
+      junkMint,
+      referrerPDA,
+      anchor.web3.Keypair.generate()
+    );
+    for (const wrongVault of [junkVault, casTokenAccount]) {
+      try {
+        await unstakeFunction(
+          jobAdIdReferral,
+          applicationIdReferral,
+          cas,
+          casTokenAccount,
+          casTokenAccount,
+          wrongVault
+        );
+        assert.equal(true, false);
+      } catch (error) {
+        assert.equal(error.error.errorCode.code, "InvalidReferralVault");
+      }
+    }
+
+    await unstakeFunction(
+      jobAdIdReferral,
+      applicationIdReferral,
+      cas,
+      casTokenAccount
+    );
+    const casTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      casTokenAccount
+    );
+    // the share comes out of the premium of the staker
+    assert.equal(
+      casTokenAccountAfter.amount - casTokenAccountBefore.amount,
+      state.rewardAmount.toNumber() - share
+    );
+    // so the escrow pays out no more than the staker was owed
+    const escrowAfter = await spl.getAccount(provider.connection, walletPDA);
+    assert.equal(
+      escrowBefore.amount - escrowAfter.amount,
+      state.rewardAmount.toNumber()
+    );
+
+    const referralVault = await spl.getAccount(
+      provider.connection,
+      referralVaultPDA
+    );
+    assert.equal(referralVault.amount, share);
+    let referrerAccount =
+      await candidateStakingProgram.account.referrerAccount.fetch(referrerPDA);
+    assert.equal(referrerAccount.accrued.toNumber(), share);
+
+    const danTokenAccount = await spl.getAssociatedTokenAddress(
+      USDCMint,
+      dan.publicKey
+    );
+    const danTokenAccountBefore = await spl.getAccount(
+      provider.connection,
+      danTokenAccount
+    );
+    const claim = async () =>
+      await candidateStakingProgram.methods
+        .claimReferralRewards()
+        .accounts({
+          referrerAccount: referrerPDA,
+          referralVault: referralVaultPDA,
+          referrer: dan.publicKey,
+          walletToDepositTo: danTokenAccount,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        })
+        .signers([dan])
+        .rpc();
+    await claim();
+    const danTokenAccountAfter = await spl.getAccount(
+      provider.connection,
+      danTokenAccount
+    );
+    assert.equal(
+      danTokenAccountAfter.amount - danTokenAccountBefore.amount,
+      share
+    );
+    referrerAccount =
+      await candidateStakingProgram.account.referrerAccount.fetch(referrerPDA);
+    assert.equal(referrerAccount.claimed.toNumber(), share);
+
+    try {
+      await claim();
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "NothingToClaim");
+    }
+
+    await updateReferralShare(0);
+  });
+
+  it("Gates staking behind an allowlist or a credential", async () => {
+    const jobAdIdGated = uuidv4();
+    const applicationIdGated = uuidv4();
+    const gatedAmount = 100;
+
+    await allProgramInitialize(jobAdIdGated, applicationIdGated, cas);
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdGated);
+    const updateGating = async (gating) =>
+      await jobProgram.methods
+        .updateGating(jobAdIdGated, gating)
+        .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    await updateGating({ allowlist: {} });
+
+    const { generalPDA } = await getGeneralPDA();
+    const { stakerApprovalPDA } = await getStakerApprovalPDA(alice.publicKey);
+
+    try {
+      await allProgramInitialize(jobAdIdGated, applicationIdGated, alice);
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakerNotApproved");
+    }
+
+    await generalProgram.methods
+      .approveStaker(alice.publicKey)
+      .accounts({
+        stakerApproval: stakerApprovalPDA,
+        baseAccount: generalPDA,
+        authority: admin.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+    await allProgramInitialize(jobAdIdGated, applicationIdGated, alice);
+    await stakeAmountFunction(
+      jobAdIdGated,
+      applicationIdGated,
+      gatedAmount,
+      alice,
+      aliceTokenAccount
+    );
+
+    // cas initialized before the job got gated, that doesn't let them stake
+    try {
+      await stakeAmountFunction(
+        jobAdIdGated,
+        applicationIdGated,
+        gatedAmount,
+        cas,
+        casTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakerNotApproved");
+    }
+    // neither does the approval of someone else
+    try {
+      await stakeAmountFunction(
+        jobAdIdGated,
+        applicationIdGated,
+        gatedAmount,
+        cas,
+        casTokenAccount,
+        [],
+        null,
+        stakerApprovalPDA
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakerNotApproved");
+    }
+
+    const credentialMint = await spl.createMint(
+      provider.connection,
+      admin,
+      admin.publicKey,
+      null,
+      0
+    );
+    await updateGating({ credential: { mint: credentialMint } });
+
+    const aliceCredentialAccount = await spl.createAccount(
+      provider.connection,
+      alice,
+      credentialMint,
+      alice.publicKey
+    );
+    // the token account has to actually hold the credential
+    try {
+      await stakeAmountFunction(
+        jobAdIdGated,
+        applicationIdGated,
+        gatedAmount,
+        alice,
+        aliceTokenAccount,
+        [],
+        null,
+        aliceCredentialAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "MissingCredential");
+    }
+
+    await spl.mintTo(
+      provider.connection,
+      admin,
+      credentialMint,
+      aliceCredentialAccount,
+      admin,
+      1
+    );
+    await stakeAmountFunction(
+      jobAdIdGated,
+      applicationIdGated,
+      gatedAmount,
+      alice,
+      aliceTokenAccount,
+      [],
+      null,
+      aliceCredentialAccount
+    );
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdGated,
+      alice.publicKey
+    );
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(state.stakedAmount.toNumber(), 2 * gatedAmount);
+
+    await generalProgram.methods
+      .revokeStaker(alice.publicKey)
+      .accounts({
+        stakerApproval: stakerApprovalPDA,
+        baseAccount: generalPDA,
+        authority: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+    assert.equal(
+      await generalProgram.account.stakerApproval.fetchNullable(
+        stakerApprovalPDA
+      ),
+      null
+    );
+  });
+
+  it("Keeps candidates, employers and blocked wallets from staking", async () => {
+    const jobAdIdConflict = uuidv4();
+    const applicationIdConflict = uuidv4();
+    const conflictAmount = 100;
+
+    // bob is the candidate of every application of the tests
+    await allProgramInitialize(jobAdIdConflict, applicationIdConflict, bob);
+    const bobTokenAccount = await spl.createAccount(
+      provider.connection,
+      bob,
+      USDCMint,
+      bob.publicKey
+    );
+    try {
+      await stakeAmountFunction(
+        jobAdIdConflict,
+        applicationIdConflict,
+        conflictAmount,
+        bob,
+        bobTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "CandidateCannotStake");
+    }
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdConflict);
+    await jobProgram.methods
+      .updateEmployers(jobAdIdConflict, [dan.publicKey])
+      .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+      .signers([admin])
+      .rpc();
+    await allProgramInitialize(jobAdIdConflict, applicationIdConflict, dan);
+    try {
+      await stakeAmountFunction(
+        jobAdIdConflict,
+        applicationIdConflict,
+        conflictAmount,
+        dan,
+        await spl.getAssociatedTokenAddress(USDCMint, dan.publicKey)
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "EmployerCannotStake");
+    }
+
+    const updateBlocked = async (method: string) =>
+      await jobProgram.methods[method](jobAdIdConflict, alice.publicKey)
+        .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    await updateBlocked("blockStaker");
+    const job = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(job.blockedStakerCount, 1);
+    assert.equal(job.blockedStakers[0].toBase58(), alice.publicKey.toBase58());
+
+    await allProgramInitialize(jobAdIdConflict, applicationIdConflict, alice);
+    try {
+      await stakeAmountFunction(
+        jobAdIdConflict,
+        applicationIdConflict,
+        conflictAmount,
+        alice,
+        aliceTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakerBlocked");
+    }
+
+    await updateBlocked("unblockStaker");
+    await stakeAmountFunction(
+      jobAdIdConflict,
+      applicationIdConflict,
+      conflictAmount,
+      alice,
+      aliceTokenAccount
+    );
+    try {
+      await updateBlocked("unblockStaker");
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakerNotBlocked");
+    }
+  });
+
+  it("Rate limits the stakes of a position", async () => {
+    const jobAdIdRateLimit = uuidv4();
+    const applicationIdRateLimit = uuidv4();
+    const rateLimitAmount = 100;
+
+    await allProgramInitialize(jobAdIdRateLimit, applicationIdRateLimit, cas);
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdRateLimit);
+    const updateStakeRateLimits = async (
+      stakeCooldown: number,
+      maxStakesPerPosition: number
+    ) =>
+      await jobProgram.methods
+        .updateStakeRateLimits(
+          jobAdIdRateLimit,
+          stakeCooldown,
+          maxStakesPerPosition
+        )
+        .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    const stake = async () =>
+      await stakeAmountFunction(
+        jobAdIdRateLimit,
+        applicationIdRateLimit,
+        rateLimitAmount,
+        cas,
+        casTokenAccount
+      );
+
+    await updateStakeRateLimits(3600, 0);
+    await stake();
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdRateLimit,
+      cas.publicKey
+    );
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.ok(state.lastStakeTs.toNumber() > 0);
+    try {
+      await stake();
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakeCooldown");
+    }
+
+    await updateStakeRateLimits(0, 2);
+    await stake();
+    try {
+      await stake();
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "MaxStakesPerPositionReached");
+    }
+  });
+
+  it("Rate limits commitments when they are made", async () => {
+    const jobAdIdCommitRate = uuidv4();
+    const applicationIdCommitRate = uuidv4();
+    const commitRateAmount = 100;
+    const salt = Buffer.alloc(32, 3);
+
+    await allProgramInitialize(jobAdIdCommitRate, applicationIdCommitRate, cas);
+    await updateRevealWindow(jobAdIdCommitRate, 1000);
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdCommitRate);
+    await jobProgram.methods
+      .updateStakeRateLimits(jobAdIdCommitRate, 3600, 0)
+      .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+      .signers([admin])
+      .rpc();
+
+    await commitStakeFunction(
+      jobAdIdCommitRate,
+      applicationIdCommitRate,
+      commitRateAmount,
+      salt,
+      cas
+    );
+    await revealStakeFunction(
+      jobAdIdCommitRate,
+      applicationIdCommitRate,
+      commitRateAmount,
+      salt,
+      cas,
+      casTokenAccount
+    );
+
+    // the next commitment is turned down right away rather than at its reveal
+    try {
+      await commitStakeFunction(
+        jobAdIdCommitRate,
+        applicationIdCommitRate,
+        commitRateAmount,
+        salt,
+        cas
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "StakeCooldown");
+    }
+  });
+
+  it("Doesn't let a payer use up the rate limits of someone else's position", async () => {
+    const jobAdIdSponsor = uuidv4();
+    const applicationIdSponsor = uuidv4();
+    const sponsorAmount = 100;
+
+    await allProgramInitialize(jobAdIdSponsor, applicationIdSponsor, cas);
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdSponsor,
+      cas.publicKey
+    );
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdSponsor);
+    const { applicationPDA } = await getApplicationPDA(applicationIdSponsor);
+    const { generalPDA } = await getGeneralPDA();
+    const { walletPDA } = await getWalletPDA(jobAdIdSponsor);
+    await jobProgram.methods
+      .updateStakeRateLimits(jobAdIdSponsor, 3600, 1)
+      .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+      .signers([admin])
+      .rpc();
+
+    // alice deposits for cas twice without cas signing
+    for (let i = 0; i < 2; i++) {
+      await candidateStakingProgram.methods
+        .stakeFor(
+          jobAdIdSponsor,
+          applicationIdSponsor,
+          cas.publicKey,
+          new anchor.BN(sponsorAmount),
+          false
+        )
+        .accounts({
+          baseAccount: candidatePDA,
+          positionAccount: (await getNextPositionPDA(candidatePDA)).positionPDA,
+          stakerProfile: (await getStakerProfilePDA(cas.publicKey))
+            .stakerProfilePDA,
+          payer: alice.publicKey,
+          beneficiaryWallet: cas.publicKey,
+          gate: (await getStakerApprovalPDA(cas.publicKey)).stakerApprovalPDA,
+          tokenMint: USDCMint,
+          generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
+          applicationAccount: applicationPDA,
+          generalProgram: generalProgram.programId,
+          applicationProgram: applicationProgram.programId,
+          jobProgram: jobProgram.programId,
+          escrowWalletState: walletPDA,
+          walletToWithdrawFrom: aliceTokenAccount,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+    }
+
+    // cas keeps the one stake the job allows and isn't held back by the cooldown
+    await stakeAmountFunction(
+      jobAdIdSponsor,
+      applicationIdSponsor,
+      sponsorAmount,
+      cas,
+      casTokenAccount
+    );
+    try {
+      await stakeAmountFunction(
+        jobAdIdSponsor,
+        applicationIdSponsor,
+        sponsorAmount,
+        cas,
+        casTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "MaxStakesPerPositionReached");
+    }
+
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(state.positionCount.toNumber(), 3);
+    assert.equal(state.sponsoredStakeCount.toNumber(), 2);
+  });
+
+  it("Doesn't let a payer claim the principal of someone else's position", async () => {
+    const jobAdIdConsent = uuidv4();
+    const applicationIdConsent = uuidv4();
+    const consentAmount = 100;
+
+    await allProgramInitialize(jobAdIdConsent, applicationIdConsent, cas);
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdConsent,
+      cas.publicKey
+    );
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdConsent);
+    const { applicationPDA } = await getApplicationPDA(applicationIdConsent);
+    const { generalPDA } = await getGeneralPDA();
+    const { walletPDA } = await getWalletPDA(jobAdIdConsent);
+
+    // alice tries to pin the principal of cas's position to herself with a dust stake
+    try {
+      await candidateStakingProgram.methods
+        .stakeFor(
+          jobAdIdConsent,
+          applicationIdConsent,
+          cas.publicKey,
+          new anchor.BN(1),
+          true
+        )
+        .accounts({
+          baseAccount: candidatePDA,
+          positionAccount: (await getNextPositionPDA(candidatePDA)).positionPDA,
+          stakerProfile: (await getStakerProfilePDA(cas.publicKey))
+            .stakerProfilePDA,
+          payer: alice.publicKey,
+          beneficiaryWallet: cas.publicKey,
+          gate: (await getStakerApprovalPDA(cas.publicKey)).stakerApprovalPDA,
+          tokenMint: USDCMint,
+          generalAccount: generalPDA,
+          jobAccount: jobFactoryPDA,
+          applicationAccount: applicationPDA,
+          generalProgram: generalProgram.programId,
+          applicationProgram: applicationProgram.programId,
+          jobProgram: jobProgram.programId,
+          escrowWalletState: walletPDA,
+          walletToWithdrawFrom: aliceTokenAccount,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          instruction: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "BeneficiaryConsentRequired");
+    }
+
+    // cas's own stakes are untouched
+    await stakeAmountFunction(
+      jobAdIdConsent,
+      applicationIdConsent,
+      consentAmount,
+      cas,
+      casTokenAccount
+    );
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(state.principalRecipient.toBase58(), cas.publicKey.toBase58());
+    assert.equal(state.stakedAmount.toNumber(), consentAmount);
+  });
+
+  it("Enforces the stake limits of the job", async () => {
+    const jobAdIdLimits = uuidv4();
+    const applicationIdLimits = uuidv4();
+
+    await allProgramInitialize(jobAdIdLimits, applicationIdLimits, alice);
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdLimits);
+    const updateStakeLimits = async (maxTierOneShareBps: number) =>
+      await jobProgram.methods
+        .updateStakeLimits(jobAdIdLimits, {
+          minStakeAmount: new anchor.BN(100),
+          maxStakePerStaker: new anchor.BN(3000),
+          maxTierOneShareBps,
+        })
+        .accounts({ jobAccount: jobFactoryPDA, authority: admin.publicKey })
+        .signers([admin])
+        .rpc();
+    const stake = async (amount: number) =>
+      await stakeAmountFunction(
+        jobAdIdLimits,
+        applicationIdLimits,
+        amount,
+        alice,
+        aliceTokenAccount
+      );
+    const expectError = async (amount: number, code: string) => {
+      try {
+        await stake(amount);
+        assert.equal(true, false);
+      } catch (error) {
+        assert.equal(error.error.errorCode.code, code);
+      }
+    };
+
+    // half of the 3333 of tier 1 at most
+    await updateStakeLimits(5000);
+    await expectError(50, "StakeBelowMinimum");
+    await stake(1500);
+    await expectError(500, "MaxTierOneShareExceeded");
+
+    await updateStakeLimits(0);
+    await expectError(1600, "MaxStakePerStakerExceeded");
+    await stake(1500);
+
+    const { candidatePDA } = await getCandidatePDA(
+      applicationIdLimits,
+      alice.publicKey
+    );
+    const state =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        candidatePDA
+      );
+    assert.equal(state.stakedAmount.toNumber(), 3000);
+  });
+
+  it("Stakes through commit and reveal, in commit order", async () => {
+    const jobAdIdCommit = uuidv4();
+    const applicationIdCommit = uuidv4();
+    const aliceSalt = Buffer.alloc(32, 1);
+    const casSalt = Buffer.alloc(32, 2);
+
+    await allProgramInitialize(jobAdIdCommit, applicationIdCommit, alice);
+    await allProgramInitialize(jobAdIdCommit, applicationIdCommit, cas);
+    await updateRevealWindow(jobAdIdCommit, 1000);
+
+    try {
+      await stakeAmountFunction(
+        jobAdIdCommit,
+        applicationIdCommit,
+        1000,
+        alice,
+        aliceTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "CommitRevealRequired");
+    }
+
+    await commitStakeFunction(
+      jobAdIdCommit,
+      applicationIdCommit,
+      2000,
+      aliceSalt,
+      alice
+    );
+    await commitStakeFunction(
+      jobAdIdCommit,
+      applicationIdCommit,
+      2000,
+      casSalt,
+      cas
+    );
+
+    // cas committed last so can't reveal ahead of alice and take her tier
+    try {
+      await revealStakeFunction(
+        jobAdIdCommit,
+        applicationIdCommit,
+        2000,
+        casSalt,
+        cas,
+        casTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "RevealOutOfOrder");
+    }
+    try {
+      await revealStakeFunction(
+        jobAdIdCommit,
+        applicationIdCommit,
+        2000,
+        aliceSalt,
+        alice,
+        aliceTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "InvalidReveal");
+    }
+    await revealStakeFunction(
+      jobAdIdCommit,
+      applicationIdCommit,
+      2000,
+      aliceSalt,
+      alice,
+      aliceTokenAccount
+    );
+    await revealStakeFunction(
+      jobAdIdCommit,
+      applicationIdCommit,
+      2000,
+      casSalt,
+      cas,
+      casTokenAccount
+    );
+
+    // alice's stake is entirely in the first tier, cas's spills into the second
+    const aliceState =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        (await getCandidatePDA(applicationIdCommit, alice.publicKey))
+          .candidatePDA
+      );
+    const casState =
+      await candidateStakingProgram.account.candidateParameter.fetch(
+        (await getCandidatePDA(applicationIdCommit, cas.publicKey)).candidatePDA
+      );
+    assert.equal(aliceState.rewardAmount.toNumber(), 6000);
+    assert.ok(casState.rewardAmount.toNumber() < 6000);
+
+    const { applicationPDA } = await getApplicationPDA(applicationIdCommit);
+    const application =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.equal(application.stakedAmount.toNumber(), 4000);
+    const { commitQueuePDA, commitmentPDA } = await getCommitmentPDAs(
+      applicationPDA,
+      alice.publicKey
+    );
+    const commitQueue =
+      await candidateStakingProgram.account.commitQueue.fetch(commitQueuePDA);
+    assert.equal(commitQueue.commitCount.toNumber(), 2);
+    assert.equal(commitQueue.pendingCount.toNumber(), 0);
+
+    // a commitment left unrevealed past its window can only be dropped
+    await updateRevealWindow(jobAdIdCommit, 1);
+    await commitStakeFunction(
+      jobAdIdCommit,
+      applicationIdCommit,
+      1000,
+      aliceSalt,
+      alice
+    );
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    try {
+      await revealStakeFunction(
+        jobAdIdCommit,
+        applicationIdCommit,
+        1000,
+        aliceSalt,
+        alice,
+        aliceTokenAccount
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "CommitmentExpired");
+    }
+    await candidateStakingProgram.methods
+      .expireCommitment(jobAdIdCommit, applicationIdCommit)
+      .accounts({
+        commitQueue: commitQueuePDA,
+        commitment: commitmentPDA,
+        staker: alice.publicKey,
+        jobAccount: (await getJobPDA(jobAdIdCommit)).jobFactoryPDA,
+        applicationAccount: applicationPDA,
+        applicationProgram: applicationProgram.programId,
+        jobProgram: jobProgram.programId,
+      })
+      .rpc();
+    assert.equal(
+      await candidateStakingProgram.account.stakeCommitment.fetchNullable(
+        commitmentPDA
+      ),
+      null
+    );
+  });
+
+  it("Keeps pending commitments when the reveal window changes", async () => {
+    const jobAdIdFlip = uuidv4();
+    const applicationIdFlip = uuidv4();
+    const salt = Buffer.alloc(32, 3);
+
+    await allProgramInitialize(jobAdIdFlip, applicationIdFlip, alice);
+    await allProgramInitialize(jobAdIdFlip, applicationIdFlip, cas);
+    await updateRevealWindow(jobAdIdFlip, 1000);
+    await commitStakeFunction(
+      jobAdIdFlip,
+      applicationIdFlip,
+      1000,
+      salt,
+      alice
+    );
+
+    // turning commit-reveal off opens direct stakes right away
+    await updateRevealWindow(jobAdIdFlip, 0);
+    await stakeAmountFunction(
+      jobAdIdFlip,
+      applicationIdFlip,
+      500,
+      cas,
+      casTokenAccount
+    );
+    try {
+      await commitStakeFunction(
+        jobAdIdFlip,
+        applicationIdFlip,
+        500,
+        salt,
+        cas
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "CommitRevealDisabled");
+    }
+
+    // while the commitment made before keeps the window it was made under
+    await revealStakeFunction(
+      jobAdIdFlip,
+      applicationIdFlip,
+      1000,
+      salt,
+      alice,
+      aliceTokenAccount
+    );
+
+    const { applicationPDA } = await getApplicationPDA(applicationIdFlip);
+    const application =
+      await applicationProgram.account.applicationParameter.fetch(
+        applicationPDA
+      );
+    assert.equal(application.stakedAmount.toNumber(), 1500);
+  });
+
+  it("Gives the hire back when a selected application is rejected", async () => {
+    const jobAdIdRelease = uuidv4();
+    const selectedApplicationId = uuidv4();
+    const otherApplicationId = uuidv4();
+
+    await allProgramInitialize(jobAdIdRelease, selectedApplicationId, alice, 1);
+    await allProgramInitialize(jobAdIdRelease, otherApplicationId, cas, 1);
+    await stakeAmountFunction(
+      jobAdIdRelease,
+      selectedApplicationId,
+      stakeAmount,
+      alice,
+      aliceTokenAccount
+    );
+
+    const { jobFactoryPDA } = await getJobPDA(jobAdIdRelease);
+    await changeApplicationStatus(jobAdIdRelease, selectedApplicationId, {
+      selected: {},
+    });
+    let jobState = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(jobState.hires, 1);
+    assert.equal(jobState.isFilled, true);
+    assert.ok(jobState.totalRewardToBeGiven.toNumber() > 0);
+
+    await changeApplicationStatus(jobAdIdRelease, selectedApplicationId, {
+      rejected: {},
+    });
+    jobState = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(jobState.hires, 0);
+    assert.equal(jobState.isFilled, false);
+    assert.equal(jobState.totalRewardToBeGiven.toNumber(), 0);
+
+    // the hire is free for another application again
+    await changeApplicationStatus(jobAdIdRelease, otherApplicationId, {
+      selected: {},
+    });
+    jobState = await jobProgram.account.jobStakingParameter.fetch(
+      jobFactoryPDA
+    );
+    assert.equal(jobState.hires, 1);
+    assert.equal(jobState.isFilled, true);
+
+    const quoteReward = async (stakerProfile) =>
+      await candidateStakingProgram.methods
+        .quoteReward(
+          jobAdIdRelease,
+          selectedApplicationId,
+          alice.publicKey,
+          new anchor.BN(stakeAmount)
+        )
+        .accounts({
+          jobAccount: jobFactoryPDA,
+          applicationAccount: (await getApplicationPDA(selectedApplicationId))
+            .applicationPDA,
+          generalAccount: (await getGeneralPDA()).generalPDA,
+          stakerProfile,
+          generalProgram: generalProgram.programId,
+          applicationProgram: applicationProgram.programId,
+          jobProgram: jobProgram.programId,
+        })
+        .rpc();
+
+    // quotes only take the profile of the wallet they are for
+    try {
+      await quoteReward(
+        (await getStakerProfilePDA(cas.publicKey)).stakerProfilePDA
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "ConstraintSeeds");
+    }
+
+    // and filled jobs aren't quoted
+    try {
+      await quoteReward(
+        (await getStakerProfilePDA(alice.publicKey)).stakerProfilePDA
+      );
+      assert.equal(true, false);
+    } catch (error) {
+      assert.equal(error.error.errorCode.code, "JobFilled");
+    }
+  });
 });